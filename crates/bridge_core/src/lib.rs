@@ -45,15 +45,23 @@ use std::{
     ptr,
     result::Result as StdResult,
     slice,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
 };
 use tectonic_errors::prelude::*;
 use tectonic_io_base::{
     digest::DigestData, normalize_tex_path, InputFeatures, InputHandle, IoProvider, OpenResult,
-    OutputHandle,
+    OutputHandle, TectonicIoError,
 };
 use tectonic_status_base::{tt_error, tt_warning, MessageKind, StatusBackend};
 
+mod sandbox;
+
+pub use sandbox::{ChildOutput, SandboxSettings};
+
 /// Possible failures for “system request” calls to the driver.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SystemRequestError {
@@ -214,11 +222,69 @@ impl Display for EngineAbortedError {
 
 impl std::error::Error for EngineAbortedError {}
 
+/// A handle that can be used to stop running engines from another thread.
+///
+/// Clones of a token share the same state, so one clone can be handed to a
+/// [`CoreBridgeLauncher`] while another is kept by whoever might want to
+/// cancel the processing. The engines poll the token as they do I/O and
+/// process tokens, and abort with an [`EngineAbortedError`] once it has been
+/// cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a new token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that any processing using this token stop.
+    ///
+    /// This cannot be undone.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether this token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Whether a running engine should stop, as reported by
+/// `ttbc_check_cancelled`.
+///
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub enum CancellationState {
+    /// Processing may continue.
+    Running = 0,
+
+    /// The processing was cancelled through a [`CancellationToken`].
+    Cancelled = 1,
+
+    /// The processing deadline has passed.
+    TimedOut = 2,
+
+    /// A limit on the data written during processing was reached.
+    LimitExceeded = 3,
+}
+
+/// How many polls to skip between checks of the wall-clock deadline. Engines
+/// poll very frequently, so we don't want to consult the clock every time.
+const DEADLINE_POLL_INTERVAL: u32 = 1024;
+
 /// A mechanism for launching bridged FFI code.
 pub struct CoreBridgeLauncher<'a> {
     hooks: &'a mut dyn DriverHooks,
     status: &'a mut dyn StatusBackend,
     security: SecuritySettings,
+    fixed_input_mtime: Option<i64>,
+    cancellation: Option<CancellationToken>,
+    deadline: Option<Instant>,
 }
 
 impl<'a> CoreBridgeLauncher<'a> {
@@ -242,8 +308,41 @@ impl<'a> CoreBridgeLauncher<'a> {
             hooks,
             status,
             security,
+            fixed_input_mtime: None,
+            cancellation: None,
+            deadline: None,
         }
     }
+
+    /// Make the engines see the same modification time for every input file.
+    ///
+    /// The time is given in seconds since the Unix epoch. This is useful for
+    /// reproducible builds, since engines can embed modification times in
+    /// their outputs. By default, the real modification times are reported.
+    pub fn fixed_input_mtime(&mut self, mtime: Option<i64>) -> &mut Self {
+        self.fixed_input_mtime = mtime;
+        self
+    }
+
+    /// Allow launched engines to be stopped through a cancellation token.
+    ///
+    /// If the token is cancelled while an engine is running, the engine will
+    /// abort, and [`Self::with_global_lock`] will return an
+    /// [`EngineAbortedError`].
+    pub fn cancellation_token(&mut self, token: Option<CancellationToken>) -> &mut Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Stop launched engines once a certain moment has passed.
+    ///
+    /// If an engine is still running at the deadline, it will abort, and
+    /// [`Self::with_global_lock`] will return an [`EngineAbortedError`].
+    pub fn deadline(&mut self, deadline: Option<Instant>) -> &mut Self {
+        self.deadline = deadline;
+        self
+    }
+
     /// Invoke a function to launch a bridged FFI engine with a global mutex
     /// held.
     ///
@@ -263,6 +362,9 @@ impl<'a> CoreBridgeLauncher<'a> {
     {
         let _guard = ENGINE_LOCK.lock().unwrap();
         let mut state = CoreBridgeState::new(self.security.clone(), self.hooks, self.status);
+        state.fixed_input_mtime = self.fixed_input_mtime;
+        state.cancellation = self.cancellation.clone();
+        state.deadline = self.deadline;
         let result = callback(&mut state);
 
         if let Err(ref e) = result {
@@ -271,6 +373,13 @@ impl<'a> CoreBridgeLauncher<'a> {
             }
         }
 
+        if result.is_ok() && !state.failed_outputs.is_empty() {
+            bail!(
+                "failed to completely write the output(s): {}",
+                state.failed_outputs.join(", ")
+            );
+        }
+
         result
     }
 }
@@ -305,6 +414,28 @@ pub struct CoreBridgeState<'a> {
     /// recent input didn't have a filesystem path (it came from a bundle or
     /// memory or something else).
     latest_input_path: Option<PathBuf>,
+
+    /// If set, the modification time reported for every input file.
+    fixed_input_mtime: Option<i64>,
+
+    /// If set, a token that the engine should poll to see if it should stop.
+    cancellation: Option<CancellationToken>,
+
+    /// If set, the moment after which the engine should stop.
+    deadline: Option<Instant>,
+
+    /// How many times the engine has polled for cancellation, so that we
+    /// only occasionally look at the clock.
+    cancellation_polls: u32,
+
+    /// Whether the I/O layer has refused an output because a limit was
+    /// reached. If so, there's no point in letting the engine continue.
+    limit_exceeded: bool,
+
+    /// The names of outputs that couldn't be completely written when they
+    /// were closed. If there are any, the engine's run is a failure, whatever
+    /// the engine itself thinks.
+    failed_outputs: Vec<String>,
 }
 
 impl<'a> CoreBridgeState<'a> {
@@ -320,9 +451,41 @@ impl<'a> CoreBridgeState<'a> {
             output_handles: Vec::new(),
             input_handles: Vec::new(),
             latest_input_path: None,
+            fixed_input_mtime: None,
+            cancellation: None,
+            deadline: None,
+            cancellation_polls: 0,
+            limit_exceeded: false,
+            failed_outputs: Vec::new(),
         }
     }
 
+    fn check_cancelled(&mut self) -> CancellationState {
+        if self.limit_exceeded {
+            return CancellationState::LimitExceeded;
+        }
+
+        if let Some(ref token) = self.cancellation {
+            if token.is_cancelled() {
+                return CancellationState::Cancelled;
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            self.cancellation_polls += 1;
+
+            if self.cancellation_polls >= DEADLINE_POLL_INTERVAL {
+                self.cancellation_polls = 0;
+
+                if Instant::now() >= deadline {
+                    return CancellationState::TimedOut;
+                }
+            }
+        }
+
+        CancellationState::Running
+    }
+
     fn input_open_name_format(
         &mut self,
         name: &str,
@@ -459,7 +622,13 @@ impl<'a> CoreBridgeState<'a> {
             OpenResult::Ok(oh) => oh,
             OpenResult::NotAvailable => return ptr::null_mut(),
             OpenResult::Err(e) => {
-                tt_warning!(self.status, "open of output {} failed", name; e);
+                if let Some(TectonicIoError::LimitExceeded(_)) = e.downcast_ref() {
+                    tt_error!(self.status, "open of output {} failed", name; e);
+                    self.limit_exceeded = true;
+                } else {
+                    tt_warning!(self.status, "open of output {} failed", name; e);
+                }
+
                 return ptr::null_mut();
             }
         };
@@ -499,7 +668,15 @@ impl<'a> CoreBridgeState<'a> {
         match result {
             Ok(_) => false,
             Err(e) => {
-                tt_warning!(self.status, "write failed"; e.into());
+                if let Some(TectonicIoError::LimitExceeded(_)) =
+                    e.get_ref().and_then(|inner| inner.downcast_ref())
+                {
+                    tt_error!(self.status, "write failed"; e.into());
+                    self.limit_exceeded = true;
+                } else {
+                    tt_warning!(self.status, "write failed"; e.into());
+                }
+
                 true
             }
         }
@@ -528,7 +705,8 @@ impl<'a> CoreBridgeState<'a> {
             if p == handle {
                 let mut oh = self.output_handles.swap_remove(i);
                 if let Err(e) = oh.flush() {
-                    tt_warning!(self.status, "error when closing output {}", oh.name(); e.into());
+                    tt_error!(self.status, "error when closing output {}", oh.name(); e.into());
+                    self.failed_outputs.push(oh.name().to_owned());
                     rv = true;
                 }
                 let (name, digest) = oh.into_name_digest();
@@ -592,6 +770,10 @@ impl<'a> CoreBridgeState<'a> {
     }
 
     fn input_get_mtime(&mut self, handle: *mut InputHandle) -> i64 {
+        if let Some(t) = self.fixed_input_mtime {
+            return t;
+        }
+
         let rhandle: &mut InputHandle = unsafe { &mut *handle };
 
         let maybe_time = match rhandle.get_unix_mtime() {
@@ -699,24 +881,35 @@ impl<'a> CoreBridgeState<'a> {
 /// are known to create security risks with *untrusted* input, but that trusted
 /// users may wish to use due to the extra functionalities they bring. (This is
 /// why these are settings and not simply security flaws!) The primary example
-/// of this is the TeX engine’s shell-escape feature.
+/// of this is the TeX engine’s shell-escape feature. The individual features
+/// are toggled by a [`SecurityPolicy`].
 ///
 /// Of course, this framework is only as good as our understanding of Tectonic’s
 /// security profile. Future versions might disable or restrict different pieces
 /// of functionality as new risks are discovered.
 #[derive(Clone, Debug)]
 pub struct SecuritySettings {
-    /// While we might eventually gain finer-grained enable/disable settings,
-    /// there should always be a hard "disable everything known to be risky"
-    /// option that supersedes everything else.
+    /// Whatever the policy might say, there should always be a hard "disable
+    /// everything known to be risky" option that supersedes everything else.
     disable_insecures: bool,
+
+    /// Which potentially insecure features are allowed.
+    policy: SecurityPolicy,
+
+    /// How external programs, such as shell-escape commands, are confined.
+    sandbox: SandboxSettings,
 }
 
 /// Different high-level security stances that can be adopted when creating
 /// [`SecuritySettings`].
 #[derive(Clone, Debug)]
 pub enum SecurityStance {
-    /// Ensure that all known-insecure features are disabled.
+    /// Disable the features that are known to be insecure by default:
+    /// shell-escape and extra search paths. The remaining features of
+    /// [`SecurityPolicy`] stay enabled for compatibility; use
+    /// [`SecurityPolicy::deny_all`] to disable them too, or
+    /// [`SecuritySettings::confine_reads`] to stop input files from being read
+    /// from outside of the filesystem root.
     ///
     /// Use this stance if you are processing untrusted input.
     DisableInsecures,
@@ -737,6 +930,88 @@ impl Default for SecurityStance {
     }
 }
 
+/// Independent toggles for the potentially insecure features of Tectonic.
+///
+/// Each field is true if the corresponding feature is allowed. The default
+/// policy corresponds to [`SecurityStance::DisableInsecures`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SecurityPolicy {
+    /// Whether the TeX engine’s shell-escape feature may be used.
+    pub shell_escape: bool,
+
+    /// Whether extra directories may be searched for input files.
+    pub extra_search_paths: bool,
+
+    /// Whether input files may be opened using absolute paths.
+    pub absolute_path_reads: bool,
+
+    /// Whether input files may be read from outside of the filesystem root,
+    /// for instance using `..` paths or symbolic links. If not, absolute paths
+    /// may only be used to read files inside the root.
+    pub reads_outside_root: bool,
+
+    /// Whether output files may be written outside of the output directory.
+    pub writes_outside_output_dir: bool,
+
+    /// Whether external tools, such as `biber`, may be run.
+    pub external_tools: bool,
+}
+
+impl SecurityPolicy {
+    /// A policy that allows every feature.
+    pub fn allow_all() -> Self {
+        SecurityPolicy {
+            shell_escape: true,
+            extra_search_paths: true,
+            absolute_path_reads: true,
+            reads_outside_root: true,
+            writes_outside_output_dir: true,
+            external_tools: true,
+        }
+    }
+
+    /// A policy that disallows every feature.
+    pub fn deny_all() -> Self {
+        SecurityPolicy {
+            shell_escape: false,
+            extra_search_paths: false,
+            absolute_path_reads: false,
+            reads_outside_root: false,
+            writes_outside_output_dir: false,
+            external_tools: false,
+        }
+    }
+
+    /// Create the policy corresponding to a high-level security stance.
+    pub fn for_stance(stance: &SecurityStance) -> Self {
+        match stance {
+            SecurityStance::DisableInsecures => SecurityPolicy {
+                shell_escape: false,
+                extra_search_paths: false,
+                ..SecurityPolicy::allow_all()
+            },
+            SecurityStance::MaybeAllowInsecures => SecurityPolicy::allow_all(),
+        }
+    }
+
+    /// Disallow every feature that *other* disallows.
+    pub fn restrict(&mut self, other: &SecurityPolicy) -> &mut Self {
+        self.shell_escape &= other.shell_escape;
+        self.extra_search_paths &= other.extra_search_paths;
+        self.absolute_path_reads &= other.absolute_path_reads;
+        self.reads_outside_root &= other.reads_outside_root;
+        self.writes_outside_output_dir &= other.writes_outside_output_dir;
+        self.external_tools &= other.external_tools;
+        self
+    }
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        SecurityPolicy::for_stance(&SecurityStance::default())
+    }
+}
+
 impl SecuritySettings {
     /// Create a new security configuration.
     ///
@@ -750,26 +1025,106 @@ impl SecuritySettings {
     /// user-level setting. Other mechanisms for disable known-insecure features
     /// may be added in the future.
     pub fn new(stance: SecurityStance) -> Self {
-        let disable_insecures = if std::env::var_os("TECTONIC_UNTRUSTED_MODE").is_some() {
-            true
+        Self::new_with_policy(SecurityPolicy::for_stance(&stance))
+    }
+
+    /// Create a new security configuration with a fine-grained policy.
+    ///
+    /// As with [`Self::new`], if the environment variable
+    /// `TECTONIC_UNTRUSTED_MODE` is set to any value, every feature will be
+    /// disabled regardless of the policy.
+    pub fn new_with_policy(policy: SecurityPolicy) -> Self {
+        let disable_insecures = std::env::var_os("TECTONIC_UNTRUSTED_MODE").is_some();
+
+        SecuritySettings {
+            disable_insecures,
+            policy,
+            sandbox: SandboxSettings::default(),
+        }
+    }
+
+    /// Get the policy that is in effect.
+    ///
+    /// If insecure features have been forcibly disabled, this is
+    /// [`SecurityPolicy::deny_all`], whatever policy was requested.
+    pub fn policy(&self) -> SecurityPolicy {
+        if self.disable_insecures {
+            SecurityPolicy::deny_all()
         } else {
-            match stance {
-                SecurityStance::DisableInsecures => true,
-                SecurityStance::MaybeAllowInsecures => false,
-            }
-        };
+            self.policy
+        }
+    }
 
-        SecuritySettings { disable_insecures }
+    /// Change the requested policy.
+    ///
+    /// This can’t be used to get around the `TECTONIC_UNTRUSTED_MODE`
+    /// environment variable.
+    pub fn set_policy(&mut self, policy: SecurityPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Disallow every feature that *restrictions* disallows.
+    pub fn restrict(&mut self, restrictions: &SecurityPolicy) -> &mut Self {
+        self.policy.restrict(restrictions);
+        self
+    }
+
+    /// Disallow reading input files from outside of the filesystem root.
+    ///
+    /// This is not implied by any [`SecurityStance`], since it can break
+    /// existing documents that use `..` paths or symbolic links. Programs that
+    /// process untrusted input should opt into it explicitly.
+    pub fn confine_reads(&mut self) -> &mut Self {
+        self.policy.reads_outside_root = false;
+        self
     }
 
     /// Query whether the shell-escape TeX engine feature is allowed to be used.
     pub fn allow_shell_escape(&self) -> bool {
-        !self.disable_insecures
+        self.policy().shell_escape
     }
 
     /// Query whether we're allowed to specify extra paths to read files from.
     pub fn allow_extra_search_paths(&self) -> bool {
-        !self.disable_insecures
+        self.policy().extra_search_paths
+    }
+
+    /// Query whether input files may be opened using absolute paths.
+    pub fn allow_absolute_path_reads(&self) -> bool {
+        self.policy().absolute_path_reads
+    }
+
+    /// Query whether input files may be read from outside of the filesystem
+    /// root.
+    pub fn allow_reads_outside_root(&self) -> bool {
+        self.policy().reads_outside_root
+    }
+
+    /// Query whether output files may be written outside of the output
+    /// directory.
+    pub fn allow_writes_outside_output_dir(&self) -> bool {
+        self.policy().writes_outside_output_dir
+    }
+
+    /// Query whether external tools, such as `biber`, may be run.
+    pub fn allow_external_tools(&self) -> bool {
+        self.policy().external_tools
+    }
+
+    /// Get the settings used to confine the external programs that Tectonic
+    /// runs, such as shell-escape commands and bibliography tools.
+    pub fn sandbox(&self) -> &SandboxSettings {
+        &self.sandbox
+    }
+
+    /// Set how external programs should be confined.
+    ///
+    /// By default, no restrictions are imposed. Note that this does not enable
+    /// any features: shell-escape, for instance, must still be allowed.
+    pub fn set_sandbox(&mut self, sandbox: SandboxSettings) -> &mut Self {
+        self.sandbox = sandbox;
+        self
     }
 }
 
@@ -1194,6 +1549,15 @@ pub extern "C" fn ttbc_diag_finish(es: &mut CoreBridgeState, diag: *mut Diagnost
         .report(rdiag.kind, format_args!("{}", rdiag.message), None);
 }
 
+/// Check whether the engine should stop processing.
+///
+/// Engines should call this function regularly. If it returns anything but
+/// `Running`, they should abort.
+#[no_mangle]
+pub extern "C" fn ttbc_check_cancelled(es: &mut CoreBridgeState) -> CancellationState {
+    es.check_cancelled()
+}
+
 /// Run a shell command
 ///
 /// # Safety
@@ -0,0 +1,1066 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Confinement of the external programs that Tectonic runs.
+//!
+//! Some Tectonic features, such as shell-escape and the external tools
+//! requested by packages like `biblatex`, involve running arbitrary programs
+//! on behalf of the document being processed. A [`SandboxSettings`]
+//! describes how such programs should be confined: which environment
+//! variables they can see, which programs may be run at all, what resources
+//! they may use, and, on Linux, whether they should be isolated from the rest
+//! of the system using namespaces and a seccomp filter.
+//!
+//! The default settings impose no restrictions, matching Tectonic’s
+//! historical behavior.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use tectonic_errors::prelude::*;
+
+/// The environment variables that are passed through to sandboxed programs
+/// when the environment is scrubbed, in addition to any that are explicitly
+/// allowed.
+const BASE_ENV_ALLOWLIST: &[&str] = &["PATH", "LANG", "LC_ALL", "LC_CTYPE", "TZ", "SYSTEMROOT"];
+
+/// Characters that, if found in a shell-escape command, mean that it can’t be
+/// run without a shell.
+const SHELL_METACHARACTERS: &[char] = &[
+    ';', '&', '|', '`', '$', '<', '>', '(', ')', '{', '}', '*', '?', '[', ']', '~', '\n', '\\',
+];
+
+/// What to do with the output of a program run in the sandbox.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChildOutput {
+    /// The program’s standard output and error are inherited from this
+    /// process.
+    Inherit,
+
+    /// The program’s standard output is copied to this process’s standard
+    /// error, and its standard error is inherited. This is needed when this
+    /// process’s standard output is itself an output file.
+    StdoutToStderr,
+
+    /// The program’s standard output and error are captured and returned.
+    Capture,
+}
+
+/// Settings for confining the external programs that Tectonic runs.
+///
+/// This type has a “builder” interface: start with [`SandboxSettings::default`]
+/// (no restrictions) or [`SandboxSettings::strict`] and adjust as needed.
+/// Install the settings with [`crate::SecuritySettings::set_sandbox`].
+#[derive(Clone, Debug, Default)]
+pub struct SandboxSettings {
+    scrub_environment: bool,
+    env_allowlist: Vec<String>,
+    allowed_programs: Option<HashSet<String>>,
+    time_limit: Option<Duration>,
+    cpu_time_limit: Option<u64>,
+    memory_limit: Option<u64>,
+    file_size_limit: Option<u64>,
+    process_limit: Option<u64>,
+    isolate: bool,
+}
+
+impl SandboxSettings {
+    /// Settings suitable for running programs on behalf of semi-trusted
+    /// documents.
+    ///
+    /// The environment is scrubbed, programs are limited to two minutes of
+    /// wall-clock and CPU time, 2 GiB of memory, and 512 MiB files, and on
+    /// Linux they are isolated from the network and can only write to their
+    /// working directory. No programs are allowed until they are added with
+    /// [`Self::allow_program`].
+    pub fn strict() -> Self {
+        SandboxSettings {
+            scrub_environment: true,
+            env_allowlist: Vec::new(),
+            allowed_programs: Some(HashSet::new()),
+            time_limit: Some(Duration::from_secs(120)),
+            cpu_time_limit: Some(120),
+            memory_limit: Some(2 << 30),
+            file_size_limit: Some(512 << 20),
+            process_limit: None,
+            isolate: cfg!(target_os = "linux"),
+        }
+    }
+
+    /// If true, programs are run with an environment that contains only a few
+    /// basic variables, such as `PATH`, plus any allowed with
+    /// [`Self::allow_env_var`].
+    pub fn scrub_environment(&mut self, scrub: bool) -> &mut Self {
+        self.scrub_environment = scrub;
+        self
+    }
+
+    /// Pass the named environment variable through to programs even if the
+    /// environment is scrubbed.
+    pub fn allow_env_var<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.env_allowlist.push(name.to_string());
+        self
+    }
+
+    /// Allow the named program to be run.
+    ///
+    /// Once any program has been allowed, only allowed programs may be run.
+    /// They must be named without any directory, and are looked up in the
+    /// absolute directories of `PATH`, so that a document can't substitute
+    /// its own program for an allowed one. In this mode, shell-escape
+    /// commands are not passed to a shell: they are split into words and run
+    /// directly, and commands that use shell features such as pipes and
+    /// redirections are rejected.
+    pub fn allow_program<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.allowed_programs
+            .get_or_insert_with(HashSet::new)
+            .insert(name.to_string());
+        self
+    }
+
+    /// Kill programs that run for longer than this.
+    ///
+    /// On Unix, programs are run in their own process group, so that any
+    /// processes they start are killed too. Elsewhere, only the program
+    /// itself is killed.
+    pub fn time_limit(&mut self, limit: Duration) -> &mut Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Limit the CPU time of programs, in seconds. Only available on Unix.
+    pub fn cpu_time_limit(&mut self, seconds: u64) -> &mut Self {
+        self.cpu_time_limit = Some(seconds);
+        self
+    }
+
+    /// Limit the address space of programs, in bytes. Only available on Unix.
+    pub fn memory_limit(&mut self, bytes: u64) -> &mut Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Limit the size of the files that programs can write, in bytes. Only
+    /// available on Unix.
+    pub fn file_size_limit(&mut self, bytes: u64) -> &mut Self {
+        self.file_size_limit = Some(bytes);
+        self
+    }
+
+    /// Limit the number of processes that programs can create. Only available
+    /// on Unix. Note that on most systems this limit applies to all of the
+    /// processes of the current user, not just those created by the program.
+    pub fn process_limit(&mut self, n: u64) -> &mut Self {
+        self.process_limit = Some(n);
+        self
+    }
+
+    /// If true, isolate programs from the rest of the system. Only available
+    /// on Linux.
+    ///
+    /// Isolated programs run in their own user, mount, network, and IPC
+    /// namespaces. They have no network access, see the whole filesystem
+    /// read-only except for their working directory, cannot gain privileges,
+    /// and are forbidden from making system calls that administer the system
+    /// or escape the sandbox.
+    pub fn isolate(&mut self, isolate: bool) -> &mut Self {
+        self.isolate = isolate;
+        self
+    }
+
+    /// Whether these settings impose any restrictions at all.
+    pub fn is_active(&self) -> bool {
+        self.scrub_environment
+            || self.allowed_programs.is_some()
+            || self.time_limit.is_some()
+            || self.cpu_time_limit.is_some()
+            || self.memory_limit.is_some()
+            || self.file_size_limit.is_some()
+            || self.process_limit.is_some()
+            || self.isolate
+    }
+
+    /// Check whether the program named by *program* is allowed to be run.
+    ///
+    /// If an allow-list is in effect, *program* must be the bare name of an
+    /// allowed program: paths are never allowed, even if they end with such a
+    /// name.
+    pub fn is_program_allowed(&self, program: &str) -> bool {
+        match self.allowed_programs {
+            None => true,
+            Some(ref allowed) => is_bare_name(program) && allowed.contains(program),
+        }
+    }
+
+    /// Compute the command line to use for a shell-escape command.
+    ///
+    /// If no program allow-list is in effect, this is the command passed to
+    /// the operating system’s shell. Otherwise, the command is split into
+    /// words, honoring single and double quotes, and an error is returned if
+    /// it uses any shell features.
+    pub fn shell_command(&self, command: &str) -> Result<Vec<String>> {
+        if self.allowed_programs.is_none() {
+            #[cfg(unix)]
+            const SHELL: &[&str] = &["sh", "-c"];
+
+            #[cfg(windows)]
+            const SHELL: &[&str] = &["cmd.exe", "/c"];
+
+            let mut argv: Vec<String> = SHELL.iter().map(|s| (*s).to_owned()).collect();
+            argv.push(command.to_owned());
+            return Ok(argv);
+        }
+
+        split_command(command)
+    }
+
+    /// Run a program in the sandbox.
+    ///
+    /// The program is run in the directory *dir*, which is the only place
+    /// that it may write to if isolation is enabled. The files listed in
+    /// *inputs*, which should be inside *dir*, are the program’s inputs: if
+    /// isolation is enabled, they are read-only too. The *output* argument
+    /// says what to do with the program’s standard output and error; unless
+    /// they are captured, the returned vectors are empty.
+    pub fn run(
+        &self,
+        argv: &[String],
+        dir: &Path,
+        inputs: &[PathBuf],
+        output: ChildOutput,
+    ) -> Result<Output> {
+        let program = match argv.first() {
+            Some(p) => p,
+            None => bail!("no program to run"),
+        };
+
+        if !self.is_program_allowed(program) {
+            bail!(
+                "the program `{}` is not on the list of allowed programs",
+                program
+            );
+        }
+
+        let mut cmd = if self.allowed_programs.is_some() {
+            Command::new(resolve_program(program)?)
+        } else {
+            Command::new(program)
+        };
+        cmd.args(&argv[1..]).current_dir(dir);
+
+        if self.scrub_environment {
+            cmd.env_clear();
+
+            let names = BASE_ENV_ALLOWLIST
+                .iter()
+                .copied()
+                .chain(self.env_allowlist.iter().map(|s| s.as_str()));
+
+            for name in names {
+                if let Some(value) = std::env::var_os(name) {
+                    cmd.env(name, value);
+                }
+            }
+        }
+
+        if self.is_active() {
+            cmd.stdin(Stdio::null());
+        }
+
+        match output {
+            ChildOutput::Inherit => {}
+            ChildOutput::StdoutToStderr => {
+                cmd.stdout(Stdio::piped());
+            }
+            ChildOutput::Capture => {
+                cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+            }
+        }
+
+        self.configure_child(&mut cmd, dir, inputs)?;
+
+        let mut child = atry!(cmd.spawn(); ["failed to run `{}`", program]);
+
+        let (stdout, forwarder) = match output {
+            ChildOutput::StdoutToStderr => (None, child.stdout.take().map(spawn_forwarder)),
+            _ => (child.stdout.take().map(spawn_reader), None),
+        };
+        let stderr = child.stderr.take().map(spawn_reader);
+        let status = self.wait(&mut child, program)?;
+
+        if let Some(h) = forwarder {
+            let _ = h.join();
+        }
+
+        let collect = |h: Option<thread::JoinHandle<Vec<u8>>>| {
+            h.map(|h| h.join().unwrap_or_default()).unwrap_or_default()
+        };
+
+        Ok(Output {
+            status,
+            stdout: collect(stdout),
+            stderr: collect(stderr),
+        })
+    }
+
+    fn wait(&self, child: &mut Child, program: &str) -> Result<ExitStatus> {
+        let limit = match self.time_limit {
+            Some(l) => l,
+            None => return Ok(atry!(child.wait(); ["failed to wait for `{}`", program])),
+        };
+
+        let start = Instant::now();
+
+        loop {
+            if let Some(status) = atry!(child.try_wait(); ["failed to wait for `{}`", program]) {
+                return Ok(status);
+            }
+
+            if start.elapsed() > limit {
+                kill_tree(child);
+                let _ = child.wait();
+                bail!(
+                    "`{}` was stopped after exceeding its time limit of {} seconds",
+                    program,
+                    limit.as_secs_f64()
+                );
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    fn configure_child(&self, cmd: &mut Command, dir: &Path, inputs: &[PathBuf]) -> Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        #[cfg(target_os = "linux")]
+        let isolation = if self.isolate {
+            // Temporary files must go somewhere writable.
+            cmd.env("TMPDIR", dir);
+            Some(linux::Isolation::prepare(dir, inputs)?)
+        } else {
+            None
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (dir, inputs);
+
+            if self.isolate {
+                bail!("sandbox isolation is only available on Linux");
+            }
+        }
+
+        // Programs with a time limit get their own process group, so that
+        // everything they start can be killed along with them.
+        let own_group = self.time_limit.is_some();
+        let cpu = self.cpu_time_limit;
+        let mem = self.memory_limit;
+        let fsize = self.file_size_limit;
+        let nproc = self.process_limit;
+
+        macro_rules! set_limit {
+            ($resource:expr, $value:expr) => {
+                if let Some(v) = $value {
+                    let rlim = libc::rlimit {
+                        rlim_cur: v as libc::rlim_t,
+                        rlim_max: v as libc::rlim_t,
+                    };
+
+                    if libc::setrlimit($resource, &rlim) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            };
+        }
+
+        // Safety: the closure runs in the child process between `fork()` and
+        // `exec()`, so it must only make async-signal-safe calls. Everything
+        // that needs allocation is prepared beforehand.
+        unsafe {
+            cmd.pre_exec(move || {
+                if own_group && libc::setpgid(0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                set_limit!(libc::RLIMIT_CPU, cpu);
+                set_limit!(libc::RLIMIT_AS, mem);
+                set_limit!(libc::RLIMIT_FSIZE, fsize);
+                set_limit!(libc::RLIMIT_NPROC, nproc);
+
+                #[cfg(target_os = "linux")]
+                {
+                    if let Some(ref isolation) = isolation {
+                        isolation.enter()?;
+                    }
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn configure_child(&self, _cmd: &mut Command, _dir: &Path, _inputs: &[PathBuf]) -> Result<()> {
+        if self.isolate {
+            bail!("sandbox isolation is only available on Linux");
+        }
+
+        if self.cpu_time_limit.is_some()
+            || self.memory_limit.is_some()
+            || self.file_size_limit.is_some()
+            || self.process_limit.is_some()
+        {
+            bail!("sandbox resource limits are only available on Unix");
+        }
+
+        Ok(())
+    }
+}
+
+/// Kill a program started with a time limit, along with any processes it
+/// started, since they are in its process group.
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_tree(child: &mut Child) {
+    let _ = child.kill();
+}
+
+/// Check whether *program* is a plain file name, with no directory.
+fn is_bare_name(program: &str) -> bool {
+    let mut components = Path::new(program).components();
+
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(c)), None) if c == program
+    )
+}
+
+/// Find an allowed program in the absolute directories of `PATH`.
+///
+/// Relative entries, such as `.`, are skipped, since they would resolve
+/// inside the program's working directory, where the document can write.
+fn resolve_program(name: &str) -> Result<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+
+    #[cfg(windows)]
+    const SUFFIXES: &[&str] = &["", ".exe", ".com"];
+
+    #[cfg(not(windows))]
+    const SUFFIXES: &[&str] = &[""];
+
+    for dir in std::env::split_paths(&path) {
+        if !dir.is_absolute() {
+            continue;
+        }
+
+        for suffix in SUFFIXES {
+            let mut file_name = OsString::from(name);
+            file_name.push(suffix);
+            let candidate = dir.join(file_name);
+
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    bail!("the program `{}` could not be found in the `PATH`", name)
+}
+
+fn spawn_reader<R: Read + Send + 'static>(mut stream: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = stream.read_to_end(&mut data);
+        data
+    })
+}
+
+fn spawn_forwarder<R: Read + Send + 'static>(mut stream: R) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut stderr = io::stderr();
+        let _ = io::copy(&mut stream, &mut stderr);
+        let _ = stderr.flush();
+    })
+}
+
+/// Split a command into words, honoring single and double quotes, without
+/// any other shell processing.
+fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+
+            None => {
+                if c == '\'' || c == '"' {
+                    quote = Some(c);
+                    word.get_or_insert_with(String::new);
+                } else if c.is_whitespace() {
+                    if let Some(w) = word.take() {
+                        words.push(w);
+                    }
+                } else if SHELL_METACHARACTERS.contains(&c) {
+                    bail!(
+                        "the shell-escape command `{}` uses shell features, which are not \
+                         allowed in the sandbox",
+                        command
+                    );
+                } else {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+        }
+    }
+
+    if quote.is_some() {
+        bail!(
+            "the shell-escape command `{}` has unbalanced quotes",
+            command
+        );
+    }
+
+    if let Some(w) = word {
+        words.push(w);
+    }
+
+    if words.is_empty() {
+        bail!("the shell-escape command is empty");
+    }
+
+    Ok(words)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::CString,
+        io,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+        ptr,
+    };
+    use tectonic_errors::prelude::*;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// `open_tree_attr()`, which is too new to be in the `libc` crate. It has
+    /// the same number on all architectures.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const SYS_OPEN_TREE_ATTR: libc::c_long = 467;
+
+    /// The system calls that isolated programs may not make. Besides the
+    /// obviously dangerous ones, this includes everything that can change
+    /// mounts, since the read-only view of the filesystem is made of mounts
+    /// that the program could otherwise change back.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_add_key,
+        libc::SYS_bpf,
+        libc::SYS_chroot,
+        libc::SYS_delete_module,
+        libc::SYS_finit_module,
+        libc::SYS_fsconfig,
+        libc::SYS_fsmount,
+        libc::SYS_fsopen,
+        libc::SYS_fspick,
+        libc::SYS_init_module,
+        libc::SYS_kexec_load,
+        libc::SYS_keyctl,
+        libc::SYS_mount,
+        libc::SYS_mount_setattr,
+        libc::SYS_move_mount,
+        libc::SYS_open_tree,
+        SYS_OPEN_TREE_ATTR,
+        libc::SYS_perf_event_open,
+        libc::SYS_pivot_root,
+        libc::SYS_ptrace,
+        libc::SYS_reboot,
+        libc::SYS_request_key,
+        libc::SYS_setns,
+        libc::SYS_swapoff,
+        libc::SYS_swapon,
+        libc::SYS_umount2,
+        libc::SYS_unshare,
+    ];
+
+    /// Everything needed to isolate a child process, prepared before forking.
+    pub struct Isolation {
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        workdir: CString,
+        inputs: Vec<CString>,
+        mounts: Vec<CString>,
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        filter: Vec<libc::sock_filter>,
+    }
+
+    impl Isolation {
+        pub fn prepare(workdir: &Path, inputs: &[PathBuf]) -> Result<Self> {
+            let workdir = atry!(
+                workdir.canonicalize();
+                ["failed to resolve the sandbox directory `{}`", workdir.display()]
+            );
+
+            // Inputs outside of the working directory are read-only anyway.
+            let mut input_paths = Vec::new();
+
+            for input in inputs {
+                if let Ok(p) = input.canonicalize() {
+                    if p.starts_with(&workdir) && p.is_file() {
+                        input_paths.push(CString::new(p.as_os_str().as_bytes())?);
+                    }
+                }
+            }
+
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+            // Find all of the current mount points, so that we can make them
+            // read-only in the child.
+            let mountinfo = atry!(
+                std::fs::read_to_string("/proc/self/mountinfo");
+                ["failed to read the list of mounted filesystems"]
+            );
+
+            let mut mounts = Vec::new();
+
+            for line in mountinfo.lines() {
+                if let Some(mp) = line.split(' ').nth(4) {
+                    let mp = unescape_mount_point(mp);
+
+                    if Path::new(std::ffi::OsStr::from_bytes(&mp)).starts_with(&workdir) {
+                        continue;
+                    }
+
+                    mounts.push(CString::new(mp)?);
+                }
+            }
+
+            Ok(Isolation {
+                uid_map: format!("{} {} 1\n", uid, uid).into_bytes(),
+                gid_map: format!("{} {} 1\n", gid, gid).into_bytes(),
+                workdir: CString::new(workdir.as_os_str().as_bytes())?,
+                inputs: input_paths,
+                mounts,
+                #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+                filter: seccomp_filter(),
+            })
+        }
+
+        /// Isolate the current process. This runs in the child after
+        /// `fork()`, so it must not allocate.
+        pub fn enter(&self) -> io::Result<()> {
+            unsafe {
+                check(libc::unshare(
+                    libc::CLONE_NEWUSER
+                        | libc::CLONE_NEWNS
+                        | libc::CLONE_NEWNET
+                        | libc::CLONE_NEWIPC,
+                ))?;
+
+                // Older kernels don't have the `setgroups` file.
+                let _ = write_file(b"/proc/self/setgroups\0", b"deny");
+                write_file(b"/proc/self/uid_map\0", &self.uid_map)?;
+                write_file(b"/proc/self/gid_map\0", &self.gid_map)?;
+
+                // Keep our mount changes to ourselves, then bind the working
+                // directory onto itself so that it stays writable when
+                // everything else is made read-only.
+                check(libc::mount(
+                    ptr::null(),
+                    b"/\0".as_ptr() as *const libc::c_char,
+                    ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    ptr::null(),
+                ))?;
+
+                check(libc::mount(
+                    self.workdir.as_ptr(),
+                    self.workdir.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?;
+
+                // The working directory was entered before we got here, so
+                // re-enter it to land on the new mount.
+                check(libc::chdir(self.workdir.as_ptr()))?;
+
+                // The program may create new files in its working directory,
+                // but not modify its inputs.
+                for input in &self.inputs {
+                    check(libc::mount(
+                        input.as_ptr(),
+                        input.as_ptr(),
+                        ptr::null(),
+                        libc::MS_BIND,
+                        ptr::null(),
+                    ))?;
+                    remount_readonly(input)?;
+                }
+
+                for mp in &self.mounts {
+                    if let Err(e) = remount_readonly(mp) {
+                        // Pseudo-filesystems can't always be remounted, but
+                        // they aren't places where files get written.
+                        let bytes = mp.as_bytes();
+
+                        if !(bytes.starts_with(b"/proc")
+                            || bytes.starts_with(b"/sys")
+                            || bytes.starts_with(b"/dev"))
+                        {
+                            return Err(e);
+                        }
+                    }
+                }
+
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+
+                #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+                {
+                    let prog = libc::sock_fprog {
+                        len: self.filter.len() as libc::c_ushort,
+                        filter: self.filter.as_ptr() as *mut libc::sock_filter,
+                    };
+
+                    check(libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &prog as *const libc::sock_fprog,
+                    ))?;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    fn check(rv: libc::c_int) -> io::Result<()> {
+        if rv < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn write_file(path: &[u8], data: &[u8]) -> io::Result<()> {
+        let fd = libc::open(path.as_ptr() as *const libc::c_char, libc::O_WRONLY);
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let n = libc::write(fd, data.as_ptr() as *const libc::c_void, data.len());
+        let err = io::Error::last_os_error();
+        libc::close(fd);
+
+        if n != data.len() as isize {
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Remount a mount point read-only, preserving the flags that the kernel
+    /// won't let us change inside a user namespace.
+    unsafe fn remount_readonly(mp: &CString) -> io::Result<()> {
+        let mut st: libc::statvfs = std::mem::zeroed();
+        check(libc::statvfs(mp.as_ptr(), &mut st))?;
+
+        let mut flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY;
+
+        for &(st_flag, ms_flag) in &[
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if st.f_flag & st_flag != 0 {
+                flags |= ms_flag;
+            }
+        }
+
+        check(libc::mount(
+            ptr::null(),
+            mp.as_ptr(),
+            ptr::null(),
+            flags,
+            ptr::null(),
+        ))
+    }
+
+    /// Mount points in `/proc/self/mountinfo` escape spaces and a few other
+    /// characters as octal sequences.
+    fn unescape_mount_point(text: &str) -> Vec<u8> {
+        let bytes = text.as_bytes();
+        let mut result = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let is_escape = bytes[i] == b'\\'
+                && i + 3 < bytes.len()
+                && bytes[i + 1..i + 4]
+                    .iter()
+                    .all(|b| (b'0'..=b'7').contains(b));
+
+            if is_escape {
+                let v =
+                    (bytes[i + 1] - b'0') * 64 + (bytes[i + 2] - b'0') * 8 + (bytes[i + 3] - b'0');
+                result.push(v);
+                i += 4;
+            } else {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+
+        result
+    }
+
+    /// Build the seccomp filter: make sure that we're dealing with the native
+    /// architecture, then return `EPERM` for the denied system calls.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn seccomp_filter() -> Vec<libc::sock_filter> {
+        fn stmt(code: u32, k: u32) -> libc::sock_filter {
+            libc::sock_filter {
+                code: code as u16,
+                jt: 0,
+                jf: 0,
+                k,
+            }
+        }
+
+        fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+            libc::sock_filter {
+                code: code as u16,
+                jt,
+                jf,
+                k,
+            }
+        }
+
+        const NR_OFFSET: u32 = 0;
+        const ARCH_OFFSET: u32 = 4;
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+        let mut filter = vec![
+            stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH_OFFSET),
+            jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                AUDIT_ARCH,
+                1,
+                0,
+            ),
+            stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR_OFFSET),
+        ];
+
+        // On x86_64, the x32 ABI has its own system call numbers, which would
+        // otherwise sneak past the checks below.
+        #[cfg(target_arch = "x86_64")]
+        {
+            filter.push(jump(
+                libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+                0x4000_0000,
+                0,
+                1,
+            ));
+            filter.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+        }
+
+        for &nr in DENIED_SYSCALLS {
+            filter.push(jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                nr as u32,
+                0,
+                1,
+            ));
+            filter.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+        }
+
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            split_command("pygmentize -l 'text' -o \"out file.pygtex\" in.pyg").unwrap(),
+            vec![
+                "pygmentize",
+                "-l",
+                "text",
+                "-o",
+                "out file.pygtex",
+                "in.pyg"
+            ]
+        );
+        assert!(split_command("cat in | sh").is_err());
+        assert!(split_command("echo $HOME").is_err());
+        assert!(split_command("echo 'unbalanced").is_err());
+        assert!(split_command("   ").is_err());
+    }
+
+    #[test]
+    fn allow_list() {
+        let mut s = SandboxSettings::default();
+        assert!(!s.is_active());
+        assert!(s.is_program_allowed("/usr/bin/anything"));
+        assert_eq!(
+            s.shell_command("echo hi").unwrap().last().unwrap(),
+            "echo hi"
+        );
+
+        s.allow_program("pygmentize");
+        assert!(s.is_active());
+        assert!(s.is_program_allowed("pygmentize"));
+        assert!(!s.is_program_allowed("/usr/bin/pygmentize"));
+        assert!(!s.is_program_allowed("./pygmentize"));
+        assert!(!s.is_program_allowed("rm"));
+        assert_eq!(
+            s.shell_command("pygmentize -V").unwrap(),
+            vec!["pygmentize", "-V"]
+        );
+        assert!(s
+            .run(
+                &["rm".to_owned()],
+                Path::new("."),
+                &[],
+                ChildOutput::Capture
+            )
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn environment_and_time_limit() {
+        std::env::set_var("TECTONIC_SANDBOX_TEST_SECRET", "1");
+
+        let mut s = SandboxSettings::default();
+        s.scrub_environment(true);
+        let argv = s.shell_command("env").unwrap();
+        let out = s
+            .run(&argv, Path::new("."), &[], ChildOutput::Capture)
+            .unwrap();
+        assert!(out.status.success());
+        assert!(!String::from_utf8_lossy(&out.stdout).contains("TECTONIC_SANDBOX_TEST_SECRET"));
+
+        s.time_limit(Duration::from_millis(200));
+        let argv = s.shell_command("sleep 10").unwrap();
+        assert!(s
+            .run(&argv, Path::new("."), &[], ChildOutput::Capture)
+            .is_err());
+
+        // Processes started by the program must be killed too. Otherwise the
+        // `sleep`, which holds the output pipe open, would keep us waiting.
+        let start = Instant::now();
+        let argv = s.shell_command("sleep 10 & wait").unwrap();
+        assert!(s
+            .run(&argv, Path::new("."), &[], ChildOutput::Capture)
+            .is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Check that an isolated process can't undo its read-only view of the
+    /// filesystem with the newer mount API.
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn isolation_denies_mount_changes() {
+        // Exit codes for the child; anything else is the errno of a call
+        // that failed the wrong way.
+        const DENIED: i32 = 0;
+        const UNAVAILABLE: i32 = 200;
+        const ALLOWED: i32 = 201;
+
+        let isolation = linux::Isolation::prepare(Path::new("."), &[]).unwrap();
+
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+
+        if pid == 0 {
+            let code = unsafe {
+                if isolation.enter().is_err() {
+                    UNAVAILABLE
+                } else {
+                    // Check each result right away, before errno changes.
+                    let outcome = |rv: libc::c_long| match (rv, *libc::__errno_location()) {
+                        (rv, _) if rv >= 0 => ALLOWED,
+                        (_, libc::EPERM) => DENIED,
+                        (_, e) => e,
+                    };
+
+                    let attr = [0u64; 4]; // a `struct mount_attr` that changes nothing
+                    const AT_RECURSIVE: libc::c_int = 0x8000;
+                    const OPEN_TREE_CLONE: libc::c_int = 1;
+
+                    [
+                        outcome(libc::syscall(
+                            libc::SYS_mount_setattr,
+                            libc::AT_FDCWD,
+                            b"/\0".as_ptr(),
+                            AT_RECURSIVE,
+                            attr.as_ptr(),
+                            std::mem::size_of_val(&attr),
+                        )),
+                        outcome(libc::syscall(
+                            libc::SYS_open_tree,
+                            libc::AT_FDCWD,
+                            b"/\0".as_ptr(),
+                            OPEN_TREE_CLONE,
+                        )),
+                        outcome(libc::syscall(libc::SYS_fsopen, b"tmpfs\0".as_ptr(), 0)),
+                    ]
+                    .iter()
+                    .copied()
+                    .find(|c| *c != DENIED)
+                    .unwrap_or(DENIED)
+                }
+            };
+
+            unsafe { libc::_exit(code) };
+        }
+
+        let mut wstatus = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut wstatus, 0) }, pid);
+        assert!(libc::WIFEXITED(wstatus));
+
+        match libc::WEXITSTATUS(wstatus) {
+            DENIED => {}
+            UNAVAILABLE => eprintln!("skipping: user namespaces aren't available here"),
+            code => panic!("a mount system call wasn't denied (code {})", code),
+        }
+    }
+}
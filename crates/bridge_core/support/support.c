@@ -128,11 +128,29 @@ ttbc_global_engine_exit(void)
 }
 
 
+void
+ttstub_check_cancelled(void)
+{
+    switch (ttbc_check_cancelled(tectonic_global_bridge_core)) {
+    case TTBC_CANCELLATION_STATE_RUNNING:
+        break;
+    case TTBC_CANCELLATION_STATE_CANCELLED:
+        _tt_abort("processing was cancelled");
+    case TTBC_CANCELLATION_STATE_TIMED_OUT:
+        _tt_abort("processing timed out");
+    case TTBC_CANCELLATION_STATE_LIMIT_EXCEEDED:
+        _tt_abort("an output limit was exceeded");
+    }
+}
+
+
 PRINTF_FUNC(1,2) void
 ttstub_issue_warning(const char *format, ...)
 {
     va_list ap;
 
+    ttstub_check_cancelled();
+
     va_start(ap, format);
     vsnprintf(format_buf, BUF_SIZE, format, ap);
     va_end(ap);
@@ -145,6 +163,8 @@ ttstub_issue_error(const char *format, ...)
 {
     va_list ap;
 
+    ttstub_check_cancelled();
+
     va_start(ap, format);
     vsnprintf(format_buf, BUF_SIZE, format, ap); /* Not ideal to (ab)use format_buf here */
     va_end(ap);
@@ -156,6 +176,7 @@ void
 ttstub_diag_finish(ttbc_diagnostic_t *diag)
 {
     ttbc_diag_finish(tectonic_global_bridge_core, diag);
+    ttstub_check_cancelled();
 }
 
 
@@ -176,6 +197,7 @@ ttstub_output_open_stdout(void)
 int
 ttstub_output_putc(rust_output_handle_t handle, int c)
 {
+    ttstub_check_cancelled();
     return ttbc_output_putc(tectonic_global_bridge_core, handle, c);
 }
 
@@ -183,6 +205,7 @@ ttstub_output_putc(rust_output_handle_t handle, int c)
 size_t
 ttstub_output_write(rust_output_handle_t handle, const char *data, size_t len)
 {
+    ttstub_check_cancelled();
     return ttbc_output_write(tectonic_global_bridge_core, handle, (const uint8_t*) data, len);
 }
 
@@ -282,6 +305,7 @@ ttstub_input_seek(rust_input_handle_t handle, ssize_t offset, int whence)
 ssize_t
 ttstub_input_read(rust_input_handle_t handle, char *data, size_t len)
 {
+    ttstub_check_cancelled();
     return ttbc_input_read(tectonic_global_bridge_core, handle, (uint8_t *) data, len);
 }
 
@@ -289,6 +313,7 @@ ttstub_input_read(rust_input_handle_t handle, char *data, size_t len)
 int
 ttstub_input_getc(rust_input_handle_t handle)
 {
+    ttstub_check_cancelled();
     return ttbc_input_getc(tectonic_global_bridge_core, handle);
 }
 
@@ -223,6 +223,11 @@ void ttbc_global_engine_exit(void);
 
 NORETURN PRINTF_FUNC(1,2) int _tt_abort(const char *format, ...);
 
+/* Abort if the driver has asked for processing to stop. The I/O wrappers below
+ * call this automatically, but engines should also call it in any loops that
+ * might run for a long time without doing any I/O. */
+void ttstub_check_cancelled(void);
+
 PRINTF_FUNC(1,2) void ttstub_issue_warning(const char *format, ...);
 PRINTF_FUNC(1,2) void ttstub_issue_error(const char *format, ...);
 
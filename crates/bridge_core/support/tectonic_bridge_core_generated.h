@@ -11,6 +11,30 @@ typedef ttbc_input_handle_t *rust_input_handle_t;
 typedef ttbc_output_handle_t *rust_output_handle_t;
 
 
+/**
+ * Whether a running engine should stop, as reported by
+ * `ttbc_check_cancelled`.
+ *
+ */
+typedef enum {
+  /**
+   * Processing may continue.
+   */
+  TTBC_CANCELLATION_STATE_RUNNING = 0,
+  /**
+   * The processing was cancelled through a [`CancellationToken`].
+   */
+  TTBC_CANCELLATION_STATE_CANCELLED = 1,
+  /**
+   * The processing deadline has passed.
+   */
+  TTBC_CANCELLATION_STATE_TIMED_OUT = 2,
+  /**
+   * A limit on the data written during processing was reached.
+   */
+  TTBC_CANCELLATION_STATE_LIMIT_EXCEEDED = 3,
+} ttbc_cancellation_state;
+
 /**
  * Different types of files that can be opened by TeX engines
  *
@@ -325,6 +349,14 @@ void ttbc_diag_append(ttbc_diagnostic_t *diag, const char *text);
  */
 void ttbc_diag_finish(ttbc_state_t *es, ttbc_diagnostic_t *diag);
 
+/**
+ * Check whether the engine should stop processing.
+ *
+ * Engines should call this function regularly. If it returns anything but
+ * `Running`, they should abort.
+ */
+ttbc_cancellation_state ttbc_check_cancelled(ttbc_state_t *es);
+
 /**
  * Run a shell command
  *
@@ -91,6 +91,61 @@ impl Cache {
     ) -> Result<CachingBundle<CB>> {
         CachingBundle::new(url, only_cached, status, &self.root)
     }
+
+    /// Get the digests of the bundles that have been superseded.
+    ///
+    /// When the bundle behind a URL is updated, the cache starts using the new
+    /// version, but keeps the data of the old one. This returns the digests of
+    /// the bundles that were cached at some point but that no URL in the cache
+    /// leads to any more. Bundles that never went through this cache, such as
+    /// local ones, are never included, since we can't tell whether they're
+    /// still in use.
+    pub fn superseded_digests(&self) -> Result<Vec<DigestData>> {
+        let current = read_digests(&self.root.join("urls"), |path| {
+            let mut text = String::with_capacity(digest::DIGEST_LEN);
+            File::open(path)?
+                .take(digest::DIGEST_LEN as u64)
+                .read_to_string(&mut text)?;
+            Ok(Some(text))
+        })?;
+
+        let mut cached = read_digests(&self.root.join("indexes"), |path| {
+            Ok(path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_owned()))
+        })?;
+
+        cached.retain(|d| !current.contains(d));
+        Ok(cached)
+    }
+}
+
+/// Read a digest from each file in a directory of the cache, ignoring files
+/// that don't contain one. If the directory doesn't exist, there are none.
+fn read_digests<F>(dir: &Path, mut get_text: F) -> Result<Vec<DigestData>>
+where
+    F: FnMut(&Path) -> std::io::Result<Option<String>>,
+{
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut digests = Vec::new();
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if let Some(text) = atry!(get_text(&path); ["couldn't read `{}`", path.display()]) {
+            if let Ok(d) = DigestData::from_str(text.trim()) {
+                digests.push(d);
+            }
+        }
+    }
+
+    Ok(digests)
 }
 
 /// Information describing a cache backend.
@@ -641,6 +696,18 @@ impl<CB: CacheBackend> IoProvider for CachingBundle<CB> {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
+        match self.input_open_name_with_abspath(name, status) {
+            OpenResult::Ok((h, _path)) => OpenResult::Ok(h),
+            OpenResult::Err(e) => OpenResult::Err(e),
+            OpenResult::NotAvailable => OpenResult::NotAvailable,
+        }
+    }
+
+    fn input_open_name_with_abspath(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
         let path = match self.ensure_file_availability(name, status) {
             OpenResult::Ok(p) => p,
             OpenResult::NotAvailable => return OpenResult::NotAvailable,
@@ -652,10 +719,9 @@ impl<CB: CacheBackend> IoProvider for CachingBundle<CB> {
             Err(e) => return OpenResult::Err(e.into()),
         };
 
-        OpenResult::Ok(InputHandle::new_read_only(
-            name,
-            BufReader::new(f),
-            InputOrigin::Other,
+        OpenResult::Ok((
+            InputHandle::new_read_only(name, BufReader::new(f), InputOrigin::Other),
+            Some(path),
         ))
     }
 }
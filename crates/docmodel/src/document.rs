@@ -19,7 +19,7 @@ use std::{
 };
 use tectonic_errors::prelude::*;
 
-use crate::workspace::WorkspaceCreator;
+use crate::{security::SecurityOverrides, workspace::WorkspaceCreator};
 
 /// The default filesystem name for the "preamble" file of a document.
 ///
@@ -63,6 +63,13 @@ pub struct Document {
     /// different settings (e.g., PDF with A4 paper and PDF with US Letter
     /// paper).
     pub outputs: HashMap<String, OutputProfile>,
+
+    /// Security restrictions requested by the document, from the `[security]`
+    /// section of `Tectonic.toml`.
+    ///
+    /// These can only be used to *disable* features. A document can’t use
+    /// them to enable a feature that the person building it hasn’t allowed.
+    pub security: SecurityOverrides,
 }
 
 impl Document {
@@ -104,6 +111,11 @@ impl Document {
             name: doc.doc.name,
             bundle_loc: doc.doc.bundle,
             outputs,
+            security: doc
+                .security
+                .as_ref()
+                .map(|s| s.to_runtime())
+                .unwrap_or_default(),
         })
     }
 
@@ -126,6 +138,13 @@ impl Document {
                 bundle: self.bundle_loc.clone(),
             },
             outputs,
+            security: if self.security.is_empty() {
+                None
+            } else {
+                Some(crate::security::syntax::SecuritySection::from_runtime(
+                    &self.security,
+                ))
+            },
         };
 
         let toml_text = toml::to_string_pretty(&doc)?;
@@ -206,6 +225,11 @@ pub struct OutputProfile {
     /// The name of the TeX format used by this profile.
     pub tex_format: String,
 
+    /// The name of a file within the `src` directory containing initex source
+    /// for a custom format, if the format should be generated from it rather
+    /// than from the bundle.
+    pub tex_format_source: Option<String>,
+
     /// The name of the preamble file within the `src` directory.
     pub preamble_file: String,
 
@@ -221,6 +245,10 @@ pub struct OutputProfile {
     /// shell-escape opens enormous security holes. It should only ever be
     /// activated with fully trusted input.
     pub shell_escape: bool,
+
+    /// Whether this profile should be built reproducibly, so that the same
+    /// inputs always produce byte-identical outputs.
+    pub reproducible: bool,
 }
 
 /// The output target type of a document build.
@@ -279,6 +307,7 @@ impl Document {
             name,
             bundle_loc,
             outputs: crate::document::default_outputs(),
+            security: SecurityOverrides::default(),
         })
     }
 }
@@ -291,10 +320,12 @@ pub(crate) fn default_outputs() -> HashMap<String, OutputProfile> {
             name: "default".to_owned(),
             target_type: BuildTargetType::Pdf,
             tex_format: "latex".to_owned(),
+            tex_format_source: None,
             preamble_file: DEFAULT_PREAMBLE_FILE.to_owned(),
             index_file: DEFAULT_INDEX_FILE.to_owned(),
             postamble_file: DEFAULT_POSTAMBLE_FILE.to_owned(),
             shell_escape: false,
+            reproducible: false,
         },
     );
     outputs
@@ -312,6 +343,9 @@ mod syntax {
 
         #[serde(rename = "output")]
         pub outputs: Vec<OutputProfile>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub security: Option<crate::security::syntax::SecuritySection>,
     }
 
     #[derive(Debug, Deserialize, Serialize)]
@@ -328,6 +362,7 @@ mod syntax {
         #[serde(rename = "type")]
         pub target_type: BuildTargetType,
         pub tex_format: Option<String>,
+        pub tex_format_source: Option<String>,
         #[serde(rename = "preamble")]
         pub preamble_file: Option<String>,
         #[serde(rename = "index")]
@@ -335,6 +370,7 @@ mod syntax {
         #[serde(rename = "postamble")]
         pub postamble_file: Option<String>,
         pub shell_escape: Option<bool>,
+        pub reproducible: Option<bool>,
     }
 
     impl OutputProfile {
@@ -364,15 +400,18 @@ mod syntax {
             };
 
             let shell_escape = if !rt.shell_escape { None } else { Some(true) };
+            let reproducible = if !rt.reproducible { None } else { Some(true) };
 
             OutputProfile {
                 name: rt.name.clone(),
                 target_type: BuildTargetType::from_runtime(&rt.target_type),
                 tex_format,
+                tex_format_source: rt.tex_format_source.clone(),
                 preamble_file,
                 index_file,
                 postamble_file,
                 shell_escape,
+                reproducible,
             }
         }
 
@@ -386,6 +425,7 @@ mod syntax {
                     .map(|s| s.as_ref())
                     .unwrap_or("latex")
                     .to_owned(),
+                tex_format_source: self.tex_format_source.clone(),
                 preamble_file: self
                     .preamble_file
                     .clone()
@@ -399,6 +439,7 @@ mod syntax {
                     .clone()
                     .unwrap_or_else(|| DEFAULT_POSTAMBLE_FILE.to_owned()),
                 shell_escape: self.shell_escape.unwrap_or_default(),
+                reproducible: self.reproducible.unwrap_or_default(),
             }
         }
     }
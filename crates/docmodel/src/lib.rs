@@ -19,4 +19,5 @@
 //! creating new workspaces from scratch.
 
 pub mod document;
+pub mod security;
 pub mod workspace;
@@ -0,0 +1,109 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Security policy settings and their TOML serialization.
+//!
+//! Security policies can be expressed in two places: the `[security]` section
+//! of a document’s `Tectonic.toml` file, and standalone policy files that the
+//! person running Tectonic can provide. Both use the same syntax:
+//!
+//! ```toml
+//! shell_escape = false
+//! extra_search_paths = false
+//! absolute_path_reads = false
+//! reads_outside_root = false
+//! writes_outside_output_dir = false
+//! external_tools = true
+//! ```
+//!
+//! Every setting is optional. This crate only deals with the data; the main
+//! `tectonic` crate turns these settings into the runtime security
+//! configuration.
+
+use std::io::Read;
+use tectonic_errors::prelude::*;
+
+/// A set of security settings, each of which may be left unspecified.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SecurityOverrides {
+    /// Whether TeX’s shell-escape feature may be used.
+    pub shell_escape: Option<bool>,
+
+    /// Whether extra directories may be searched for input files.
+    pub extra_search_paths: Option<bool>,
+
+    /// Whether input files may be read using absolute paths.
+    pub absolute_path_reads: Option<bool>,
+
+    /// Whether input files may be read from outside of the project root
+    /// directory.
+    pub reads_outside_root: Option<bool>,
+
+    /// Whether output files may be written outside of the output directory.
+    pub writes_outside_output_dir: Option<bool>,
+
+    /// Whether external tools, such as `biber`, may be run.
+    pub external_tools: Option<bool>,
+}
+
+impl SecurityOverrides {
+    /// Load security settings from a TOML-formatted data stream, such as a
+    /// standalone policy file.
+    pub fn new_from_toml<R: Read>(toml_data: &mut R) -> Result<Self> {
+        let mut toml_text = String::new();
+        toml_data.read_to_string(&mut toml_text)?;
+        let section: syntax::SecuritySection = toml::from_str(&toml_text)?;
+        Ok(section.to_runtime())
+    }
+
+    /// Check whether no settings are specified.
+    pub fn is_empty(&self) -> bool {
+        *self == SecurityOverrides::default()
+    }
+}
+
+/// The concrete syntax for security settings, wired up via serde.
+pub(crate) mod syntax {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct SecuritySection {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub shell_escape: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub extra_search_paths: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub absolute_path_reads: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub reads_outside_root: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub writes_outside_output_dir: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub external_tools: Option<bool>,
+    }
+
+    impl SecuritySection {
+        pub fn from_runtime(rt: &super::SecurityOverrides) -> Self {
+            SecuritySection {
+                shell_escape: rt.shell_escape,
+                extra_search_paths: rt.extra_search_paths,
+                absolute_path_reads: rt.absolute_path_reads,
+                reads_outside_root: rt.reads_outside_root,
+                writes_outside_output_dir: rt.writes_outside_output_dir,
+                external_tools: rt.external_tools,
+            }
+        }
+
+        pub fn to_runtime(&self) -> super::SecurityOverrides {
+            super::SecurityOverrides {
+                shell_escape: self.shell_escape,
+                extra_search_paths: self.extra_search_paths,
+                absolute_path_reads: self.absolute_path_reads,
+                reads_outside_root: self.reads_outside_root,
+                writes_outside_output_dir: self.writes_outside_output_dir,
+                external_tools: self.external_tools,
+            }
+        }
+    }
+}
@@ -0,0 +1,726 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Sorting raw index entries and generating the typeset index.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{
+    input::{KeyLevel, RangeMark, RawEntry},
+    style::Style,
+};
+
+/// The different styles of page numbers that we understand, as named in the
+/// `page_precedence` style setting.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PageKind {
+    LowerRoman,
+    Arabic,
+    LowerAlpha,
+    UpperRoman,
+    UpperAlpha,
+}
+
+impl PageKind {
+    fn letter(self) -> char {
+        match self {
+            PageKind::LowerRoman => 'r',
+            PageKind::Arabic => 'n',
+            PageKind::LowerAlpha => 'a',
+            PageKind::UpperRoman => 'R',
+            PageKind::UpperAlpha => 'A',
+        }
+    }
+}
+
+/// A parsed page number, which may be composite (like `2-14`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Page {
+    text: String,
+    parts: Vec<(PageKind, u32)>,
+}
+
+impl Page {
+    fn parse(text: &str, style: &Style, hints: LetterHints) -> Option<Page> {
+        let parts = text
+            .split(style.page_compositor.as_str())
+            .map(|t| parse_page_part(t, hints))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Page {
+            text: text.to_owned(),
+            parts,
+        })
+    }
+
+    /// A key for sorting pages, taking the style's precedence into account.
+    fn sort_key(&self, style: &Style) -> Vec<(usize, u32)> {
+        self.parts
+            .iter()
+            .map(|(kind, value)| {
+                let prec = style
+                    .page_precedence
+                    .find(kind.letter())
+                    .unwrap_or(usize::MAX);
+                (prec, *value)
+            })
+            .collect()
+    }
+
+    /// Whether `other` is the page right after this one.
+    fn is_followed_by(&self, other: &Page) -> bool {
+        let n = self.parts.len();
+
+        if n == 0 || other.parts.len() != n || self.parts[..n - 1] != other.parts[..n - 1] {
+            return false;
+        }
+
+        let (k1, v1) = self.parts[n - 1];
+        let (k2, v2) = other.parts[n - 1];
+        k1 == k2 && v1.checked_add(1) == Some(v2)
+    }
+}
+
+/// How to read single-letter page numbers, like `c` or `V`, that could be
+/// either roman numerals or letters.
+#[derive(Clone, Copy, Debug, Default)]
+struct LetterHints {
+    lower_roman: bool,
+    upper_roman: bool,
+}
+
+impl LetterHints {
+    /// Decide based on the other page numbers in the index: if the only
+    /// unambiguous ones of the same case are roman numerals, like `ii`, or
+    /// letters, like `b`, the single letters are taken to be the same kind.
+    /// Otherwise, they are only taken to be roman numerals if the style’s
+    /// `page_precedence` mentions roman numerals but not letters.
+    fn new<'a, I: IntoIterator<Item = &'a str>>(pages: I, style: &Style) -> Self {
+        let mut seen = [[false; 2]; 2]; // [upper][roman]
+
+        for page in pages {
+            for part in page.split(style.page_compositor.as_str()) {
+                let mut chars = part.chars();
+
+                let upper = match chars.next() {
+                    Some(c) if c.is_ascii_alphabetic() => c.is_ascii_uppercase(),
+                    _ => continue,
+                };
+
+                if chars.next().is_some() {
+                    if parse_roman(part, upper).is_some() {
+                        seen[upper as usize][1] = true;
+                    }
+                } else if parse_roman(part, upper).is_none() {
+                    seen[upper as usize][0] = true;
+                }
+            }
+        }
+
+        let decide = |upper: bool, roman: char, alpha: char| match seen[upper as usize] {
+            [false, true] => true,
+            [true, false] => false,
+            _ => style.page_precedence.contains(roman) && !style.page_precedence.contains(alpha),
+        };
+
+        LetterHints {
+            lower_roman: decide(false, 'r', 'a'),
+            upper_roman: decide(true, 'R', 'A'),
+        }
+    }
+}
+
+fn parse_page_part(text: &str, hints: LetterHints) -> Option<(PageKind, u32)> {
+    if text.is_empty() {
+        return None;
+    }
+
+    if text.chars().all(|c| c.is_ascii_digit()) {
+        return text.parse().ok().map(|v| (PageKind::Arabic, v));
+    }
+
+    let mut chars = text.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() => {
+            if hints.lower_roman {
+                if let Some(v) = parse_roman(text, false) {
+                    return Some((PageKind::LowerRoman, v));
+                }
+            }
+
+            return Some((PageKind::LowerAlpha, c as u32 - 'a' as u32 + 1));
+        }
+        (Some(c), None) if c.is_ascii_uppercase() => {
+            if hints.upper_roman {
+                if let Some(v) = parse_roman(text, true) {
+                    return Some((PageKind::UpperRoman, v));
+                }
+            }
+
+            return Some((PageKind::UpperAlpha, c as u32 - 'A' as u32 + 1));
+        }
+        _ => {}
+    }
+
+    if let Some(v) = parse_roman(text, false) {
+        return Some((PageKind::LowerRoman, v));
+    }
+
+    parse_roman(text, true).map(|v| (PageKind::UpperRoman, v))
+}
+
+fn parse_roman(text: &str, upper: bool) -> Option<u32> {
+    let mut total = 0;
+    let mut prev = 0;
+
+    for c in text.chars().rev() {
+        if c.is_ascii_uppercase() != upper {
+            return None;
+        }
+
+        let v = match c.to_ascii_lowercase() {
+            'i' => 1,
+            'v' => 5,
+            'x' => 10,
+            'l' => 50,
+            'c' => 100,
+            'd' => 500,
+            'm' => 1000,
+            _ => return None,
+        };
+
+        if v < prev {
+            total -= v;
+        } else {
+            total += v;
+            prev = v;
+        }
+    }
+
+    if total > 0 {
+        Some(total as u32)
+    } else {
+        None
+    }
+}
+
+/// One reference to a page from an index entry.
+#[derive(Clone, Debug)]
+struct PageRef {
+    page: Page,
+    encap: Option<String>,
+    range: RangeMark,
+    line: usize,
+}
+
+/// An index entry with all of its page references gathered together.
+#[derive(Clone, Debug)]
+struct Entry {
+    levels: Vec<KeyLevel>,
+    refs: Vec<PageRef>,
+}
+
+/// An item in the page list of an entry.
+#[derive(Clone, Debug)]
+enum PageItem {
+    Single(Page, Option<String>),
+    Range(Page, Page, Option<String>),
+    Suffixed(Page, String, Option<String>),
+}
+
+/// The different groups that entries fall into.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Group {
+    Symbols,
+    Numbers,
+    Letter(char),
+}
+
+fn group_of(sort: &str) -> Group {
+    match sort.chars().next() {
+        Some(c) if c.is_alphabetic() => Group::Letter(c.to_lowercase().next().unwrap_or(c)),
+        Some(c) if c.is_ascii_digit() => Group::Numbers,
+        _ => Group::Symbols,
+    }
+}
+
+/// Compare sort keys the way `makeindex` does: symbols first, then numbers,
+/// then letters, ignoring case unless that's the only difference.
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    let (ga, gb) = (group_of(a), group_of(b));
+    let class = |g: Group| match g {
+        Group::Symbols => 0,
+        Group::Numbers => 1,
+        Group::Letter(_) => 2,
+    };
+
+    class(ga)
+        .cmp(&class(gb))
+        .then_with(|| {
+            if ga == Group::Numbers && gb == Group::Numbers {
+                if let (Ok(na), Ok(nb)) = (a.parse::<u64>(), b.parse::<u64>()) {
+                    return na.cmp(&nb);
+                }
+            }
+
+            a.to_lowercase().cmp(&b.to_lowercase())
+        })
+        .then_with(|| {
+            // Lowercase before uppercase.
+            let flip = |c: char| (c.to_lowercase().next().unwrap_or(c), c.is_uppercase());
+            a.chars().map(flip).cmp(b.chars().map(flip))
+        })
+}
+
+fn compare_levels(a: &[KeyLevel], b: &[KeyLevel]) -> Ordering {
+    for (la, lb) in a.iter().zip(b.iter()) {
+        let o = compare_keys(&la.sort, &lb.sort).then_with(|| la.display.cmp(&lb.display));
+
+        if o != Ordering::Equal {
+            return o;
+        }
+    }
+
+    a.len().cmp(&b.len())
+}
+
+/// Generate a typeset index from raw entries.
+///
+/// Problems are described in `messages`, which are intended for the
+/// transcript file.
+pub fn generate(raw: Vec<RawEntry>, style: &Style, messages: &mut Vec<String>) -> String {
+    // Gather the page references for each distinct key.
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut index: HashMap<Vec<KeyLevel>, usize> = HashMap::new();
+    let hints = LetterHints::new(raw.iter().map(|r| r.page.as_str()), style);
+
+    for r in raw {
+        let page = match Page::parse(&r.page, style, hints) {
+            Some(p) => p,
+            None => {
+                messages.push(format!(
+                    "## Warning (input line {}): illegal page number `{}`; entry ignored",
+                    r.line, r.page
+                ));
+                continue;
+            }
+        };
+
+        let pref = PageRef {
+            page,
+            encap: r.encap,
+            range: r.range,
+            line: r.line,
+        };
+
+        match index.get(&r.levels) {
+            Some(&i) => entries[i].refs.push(pref),
+            None => {
+                index.insert(r.levels.clone(), entries.len());
+                entries.push(Entry {
+                    levels: r.levels,
+                    refs: vec![pref],
+                });
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| compare_levels(&a.levels, &b.levels));
+
+    // Now write out the results.
+
+    let mut out = style.preamble.clone();
+    let mut prev: Option<&Entry> = None;
+    let mut prev_group = None;
+
+    for entry in &entries {
+        let group = group_of(&entry.levels[0].sort);
+
+        if prev_group != Some(group) {
+            if prev_group.is_some() {
+                out.push_str(&style.group_skip);
+            }
+
+            if style.headings_flag != 0 {
+                out.push_str(&style.heading_prefix);
+                out.push_str(&heading_for(group, style));
+                out.push_str(&style.heading_suffix);
+            }
+        }
+
+        // Where does this entry start to differ from the previous one?
+
+        let start = match prev {
+            Some(p) if prev_group == Some(group) => entry
+                .levels
+                .iter()
+                .zip(p.levels.iter())
+                .take_while(|(a, b)| a == b)
+                .count()
+                .min(entry.levels.len() - 1),
+            _ => 0,
+        };
+        let prev_depth = prev.map(|p| p.levels.len()).unwrap_or(0);
+
+        for (depth, level) in entry.levels.iter().enumerate().skip(start) {
+            let item = match depth {
+                0 => &style.item_0,
+                1 if depth > start => &style.item_x1,
+                1 if prev_depth == 1 => &style.item_01,
+                1 => &style.item_1,
+                _ if depth > start => &style.item_x2,
+                _ if prev_depth == 2 => &style.item_12,
+                _ => &style.item_2,
+            };
+
+            out.push_str(item);
+            out.push_str(&level.display);
+        }
+
+        let delim = match entry.levels.len() {
+            1 => &style.delim_0,
+            2 => &style.delim_1,
+            _ => &style.delim_2,
+        };
+        out.push_str(delim);
+
+        let items = page_items(entry, style, messages);
+
+        for (i, item) in items.iter().enumerate() {
+            let text = format_item(item, style);
+
+            if i > 0 {
+                let col = out.len() - out.rfind('\n').map(|p| p + 1).unwrap_or(0);
+
+                if col + style.delim_n.len() + text.len() > style.line_max {
+                    out.push_str(style.delim_n.trim_end());
+                    out.push('\n');
+                    out.push_str(&style.indent_space);
+                } else {
+                    out.push_str(&style.delim_n);
+                }
+            }
+
+            out.push_str(&text);
+        }
+
+        out.push_str(&style.delim_t);
+        prev = Some(entry);
+        prev_group = Some(group);
+    }
+
+    out.push_str(&style.postamble);
+    out
+}
+
+fn heading_for(group: Group, style: &Style) -> String {
+    let positive = style.headings_flag > 0;
+
+    match group {
+        Group::Symbols if positive => style.symhead_positive.clone(),
+        Group::Symbols => style.symhead_negative.clone(),
+        Group::Numbers if positive => style.numhead_positive.clone(),
+        Group::Numbers => style.numhead_negative.clone(),
+        Group::Letter(c) if positive => c.to_uppercase().collect(),
+        Group::Letter(c) => c.to_string(),
+    }
+}
+
+/// Resolve the page references of an entry into a list of items to print,
+/// handling explicit and implicit page ranges.
+fn page_items(entry: &Entry, style: &Style, messages: &mut Vec<String>) -> Vec<PageItem> {
+    let mut refs = entry.refs.clone();
+
+    // Stable sort, so that references to the same page stay in input order,
+    // except that range openings come first and closings last.
+    refs.sort_by(|a, b| {
+        let rank = |r: &PageRef| match r.range {
+            RangeMark::Open => 0,
+            RangeMark::None => 1,
+            RangeMark::Close => 2,
+        };
+
+        a.page
+            .sort_key(style)
+            .cmp(&b.page.sort_key(style))
+            .then_with(|| rank(a).cmp(&rank(b)))
+    });
+
+    // First, explicit ranges.
+
+    let mut items = Vec::new();
+    let mut open: Option<(usize, Option<String>)> = None;
+
+    for r in refs {
+        match r.range {
+            RangeMark::Open => {
+                if open.is_some() {
+                    messages.push(format!(
+                        "## Warning (input line {}): extra range opening operator",
+                        r.line
+                    ));
+                } else {
+                    open = Some((items.len(), r.encap.clone()));
+                    items.push(PageItem::Single(r.page, r.encap));
+                }
+            }
+
+            RangeMark::Close => match open.take() {
+                Some((i, encap)) => {
+                    if let PageItem::Single(ref start, _) = items[i] {
+                        if start.text != r.page.text {
+                            items[i] = PageItem::Range(start.clone(), r.page, encap);
+                        }
+                    }
+                }
+
+                None => {
+                    messages.push(format!(
+                        "## Warning (input line {}): unmatched range closing operator",
+                        r.line
+                    ));
+                    items.push(PageItem::Single(r.page, r.encap));
+                }
+            },
+
+            RangeMark::None => {
+                if let Some((_, ref encap)) = open {
+                    if r.encap.is_none() || r.encap == *encap {
+                        continue; // inside the open range
+                    }
+                }
+
+                let dup = items.iter().any(|item| match item {
+                    PageItem::Single(p, e) => p.text == r.page.text && *e == r.encap,
+                    _ => false,
+                });
+
+                if !dup {
+                    items.push(PageItem::Single(r.page, r.encap));
+                }
+            }
+        }
+    }
+
+    if open.is_some() {
+        messages.push(format!(
+            "## Warning: unmatched range opening operator for `{}`",
+            entry.levels[0].display
+        ));
+    }
+
+    // Now, implicit ranges of consecutive pages.
+
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < items.len() {
+        let (first, encap) = match items[i] {
+            PageItem::Single(ref p, ref e) if !is_see(e) => (p, e),
+            ref other => {
+                result.push(other.clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let mut j = i + 1;
+        let mut last = first;
+
+        while j < items.len() {
+            match items[j] {
+                PageItem::Single(ref p, ref e) if e == encap && last.is_followed_by(p) => {
+                    last = p;
+                    j += 1;
+                }
+                _ => break,
+            }
+        }
+
+        let n = j - i;
+
+        let suffix = match n {
+            2 => &style.suffix_2p,
+            3 if !style.suffix_3p.is_empty() => &style.suffix_3p,
+            _ => &style.suffix_mp,
+        };
+
+        if n >= 2 && !suffix.is_empty() {
+            result.push(PageItem::Suffixed(
+                first.clone(),
+                suffix.clone(),
+                encap.clone(),
+            ));
+        } else if n >= 3 {
+            result.push(PageItem::Range(first.clone(), last.clone(), encap.clone()));
+        } else {
+            result.extend(items[i..j].iter().cloned());
+        }
+
+        i = j;
+    }
+
+    result
+}
+
+fn is_see(encap: &Option<String>) -> bool {
+    match encap {
+        Some(e) => e.starts_with("see"),
+        None => false,
+    }
+}
+
+fn format_item(item: &PageItem, style: &Style) -> String {
+    let (text, encap) = match item {
+        PageItem::Single(p, e) => (p.text.clone(), e),
+        PageItem::Range(p1, p2, e) => (format!("{}{}{}", p1.text, style.delim_r, p2.text), e),
+        PageItem::Suffixed(p, s, e) => (format!("{}{}", p.text, s), e),
+    };
+
+    let text = format!("{}{}{}", style.setpage_prefix, text, style.setpage_suffix);
+
+    match encap {
+        Some(e) => format!(
+            "{}{}{}{}{}",
+            style.encap_prefix, e, style.encap_infix, text, style.encap_suffix
+        ),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parse_idx;
+
+    fn run(idx: &str) -> String {
+        let style = Style::default();
+        let (entries, messages) = parse_idx(idx, &style);
+        assert!(messages.is_empty(), "{:?}", messages);
+        let mut messages = Vec::new();
+        let out = generate(entries, &style, &mut messages);
+        assert!(messages.is_empty(), "{:?}", messages);
+        out
+    }
+
+    #[test]
+    fn basic() {
+        let out = run("\\indexentry{beta}{2}\n\
+             \\indexentry{alpha}{1}\n\
+             \\indexentry{Alpha}{3}\n\
+             \\indexentry{alpha}{5}\n");
+
+        assert_eq!(
+            out,
+            "\\begin{theindex}\n\
+             \n  \\item alpha, 1, 5\
+             \n  \\item Alpha, 3\
+             \n\n  \\indexspace\n\
+             \n  \\item beta, 2\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn groups_and_subitems() {
+        let out = run("\\indexentry{zeta!eta}{4}\n\
+             \\indexentry{10}{1}\n\
+             \\indexentry{9}{1}\n\
+             \\indexentry{$\\alpha$}{1}\n\
+             \\indexentry{zeta}{2}\n");
+
+        assert_eq!(
+            out,
+            "\\begin{theindex}\n\
+             \n  \\item $\\alpha$, 1\
+             \n\n  \\indexspace\n\
+             \n  \\item 9, 1\
+             \n  \\item 10, 1\
+             \n\n  \\indexspace\n\
+             \n  \\item zeta, 2\
+             \n    \\subitem eta, 4\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let out = run("\\indexentry{xa}{1}\n\
+             \\indexentry{xa}{2}\n\
+             \\indexentry{xa}{3}\n\
+             \\indexentry{xa}{5}\n\
+             \\indexentry{xa}{6}\n\
+             \\indexentry{xb|(}{2}\n\
+             \\indexentry{xb}{4}\n\
+             \\indexentry{xb|)}{7}\n\
+             \\indexentry{xc|textbf}{ii}\n\
+             \\indexentry{xc|textbf}{iii}\n\
+             \\indexentry{xc|textbf}{iv}\n\
+             \\indexentry{xc}{1}\n\
+             \\indexentry{xd|see{xa}}{1}\n");
+
+        assert_eq!(
+            out,
+            "\\begin{theindex}\n\
+             \n  \\item xa, 1--3, 5, 6\
+             \n  \\item xb, 2--7\
+             \n  \\item xc, \\textbf{ii--iv}, 1\
+             \n  \\item xd, \\see{xa}{1}\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn style_applies() {
+        let mut style = Style::default();
+        style.apply_file("headings_flag 1\nheading_prefix \"{\\\\bf \"\nheading_suffix \"}\"\ndelim_0 \"\\\\dotfill \"\n");
+        let (entries, _) = parse_idx("\\indexentry{apple}{1}\n", &style);
+        let mut messages = Vec::new();
+        let out = generate(entries, &style, &mut messages);
+        assert_eq!(
+            out,
+            "\\begin{theindex}\n{\\bf A}\n  \\item apple\\dotfill 1\n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn bad_page() {
+        let style = Style::default();
+        let (entries, _) = parse_idx("\\indexentry{a}{1.5}\n\\indexentry{a}{2}\n", &style);
+        let mut messages = Vec::new();
+        let out = generate(entries, &style, &mut messages);
+        assert_eq!(messages.len(), 1);
+        assert!(out.contains("\\item a, 2"));
+    }
+
+    #[test]
+    fn letter_pages() {
+        // Appendix pages: `c` and `d` are letters here, not roman numerals.
+        let out = run("\\indexentry{xa}{a}\n\
+             \\indexentry{xa}{b}\n\
+             \\indexentry{xa}{c}\n\
+             \\indexentry{xa}{d}\n\
+             \\indexentry{xb}{c}\n\
+             \\indexentry{xb}{99}\n");
+        assert!(out.contains("\\item xa, a--d\n"));
+        assert!(out.contains("\\item xb, 99, c\n"));
+
+        // Front matter: here, `i` and `v` are roman numerals.
+        let out = run("\\indexentry{xa}{i}\n\
+             \\indexentry{xa}{ii}\n\
+             \\indexentry{xa}{iii}\n\
+             \\indexentry{xb}{iv}\n\
+             \\indexentry{xb}{v}\n\
+             \\indexentry{xb}{vi}\n");
+        assert!(out.contains("\\item xa, i--iii\n"));
+        assert!(out.contains("\\item xb, iv--vi\n"));
+    }
+
+    #[test]
+    fn huge_page_numbers() {
+        let out = run("\\indexentry{a}{4294967295}\n\\indexentry{a}{04294967295}\n");
+        assert!(out.contains("\\item a, 4294967295, 04294967295\n"));
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Parsing raw index entries from `.idx` files.
+
+use crate::style::Style;
+
+/// The maximum number of levels (item, subitem, subsubitem) in an entry.
+pub const MAX_LEVELS: usize = 3;
+
+/// One level of an index entry's key.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct KeyLevel {
+    /// The text used to sort this level.
+    pub sort: String,
+
+    /// The text to typeset for this level.
+    pub display: String,
+}
+
+/// How a raw entry participates in an explicit page range.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RangeMark {
+    /// This entry is not part of an explicit range.
+    None,
+
+    /// This entry opens an explicit range.
+    Open,
+
+    /// This entry closes an explicit range.
+    Close,
+}
+
+/// A single `\indexentry` from the input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawEntry {
+    /// The levels of the key, outermost first.
+    pub levels: Vec<KeyLevel>,
+
+    /// The encapsulating command for the page number, without the escape
+    /// character, if any.
+    pub encap: Option<String>,
+
+    /// Whether this entry opens or closes an explicit range.
+    pub range: RangeMark,
+
+    /// The page number, as written by TeX.
+    pub page: String,
+
+    /// The line of the input where the entry appeared.
+    pub line: usize,
+}
+
+/// Parse the contents of an `.idx` file.
+///
+/// Returns the entries that were accepted, and messages describing the ones
+/// that were rejected.
+pub fn parse_idx(text: &str, style: &Style) -> (Vec<RawEntry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut messages = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let mut rest = line;
+
+        while let Some(pos) = rest.find(&style.keyword) {
+            rest = &rest[pos + style.keyword.len()..];
+
+            match parse_one(&mut rest, style) {
+                Ok((key, page)) => match parse_key(&key, style) {
+                    Ok((levels, encap, range)) => entries.push(RawEntry {
+                        levels,
+                        encap,
+                        range,
+                        page,
+                        line: line_num,
+                    }),
+
+                    Err(e) => {
+                        messages.push(format!("!! Input index error (line {}): {}", line_num, e))
+                    }
+                },
+
+                Err(e) => {
+                    messages.push(format!("!! Input index error (line {}): {}", line_num, e));
+                    break;
+                }
+            }
+        }
+    }
+
+    (entries, messages)
+}
+
+/// Parse the two arguments that follow the keyword.
+fn parse_one(rest: &mut &str, style: &Style) -> Result<(String, String), String> {
+    let key = read_arg(rest, style)?;
+    let page = read_arg(rest, style)?;
+    Ok((key, page.trim().to_owned()))
+}
+
+/// Read one delimited argument, honoring nested delimiters and the quote
+/// character. The delimiters are not included in the result, but quote
+/// characters are, since they are interpreted later.
+fn read_arg(rest: &mut &str, style: &Style) -> Result<String, String> {
+    let text = rest.trim_start();
+    let mut chars = text.char_indices();
+
+    match chars.next() {
+        Some((_, c)) if c == style.arg_open => {}
+        _ => return Err(format!("expected `{}`", style.arg_open)),
+    }
+
+    let mut depth = 0;
+    let mut arg = String::new();
+    let mut prev = None;
+
+    while let Some((i, c)) = chars.next() {
+        if c == style.quote && prev != Some(style.escape) {
+            // The next character is taken literally.
+            arg.push(c);
+
+            if let Some((_, next)) = chars.next() {
+                arg.push(next);
+            }
+
+            prev = None;
+            continue;
+        }
+
+        if c == style.arg_open {
+            depth += 1;
+        } else if c == style.arg_close {
+            if depth == 0 {
+                *rest = &text[i + c.len_utf8()..];
+                return Ok(arg);
+            }
+
+            depth -= 1;
+        }
+
+        arg.push(c);
+        prev = Some(c);
+    }
+
+    Err(format!(
+        "unterminated argument; expected `{}`",
+        style.arg_close
+    ))
+}
+
+type ParsedKey = (Vec<KeyLevel>, Option<String>, RangeMark);
+
+/// Split a key into levels, sort and display text, and encapsulation.
+fn parse_key(key: &str, style: &Style) -> Result<ParsedKey, String> {
+    // We accumulate the text of each level, switching between the "sort" and
+    // "display" parts when we see the `actual` character. Once we see the
+    // `encap` character, everything else is the encapsulation.
+
+    let mut levels = Vec::new();
+    let mut sort = String::new();
+    let mut display: Option<String> = None;
+    let mut encap: Option<String> = None;
+    let mut prev = None;
+    let mut chars = key.chars();
+
+    fn finish_level(
+        levels: &mut Vec<KeyLevel>,
+        sort: &mut String,
+        display: &mut Option<String>,
+    ) -> Result<(), String> {
+        let sort = std::mem::take(sort);
+        let display = display.take().unwrap_or_else(|| sort.clone());
+
+        if sort.is_empty() && display.is_empty() {
+            return Err("empty index key".to_owned());
+        }
+
+        if levels.len() == MAX_LEVELS {
+            return Err(format!("too many levels (at most {} allowed)", MAX_LEVELS));
+        }
+
+        levels.push(KeyLevel { sort, display });
+        Ok(())
+    }
+
+    while let Some(c) = chars.next() {
+        let c = if c == style.quote && prev != Some(style.escape) {
+            // Take the next character literally, dropping the quote.
+            prev = None;
+
+            match chars.next() {
+                Some(next) => next,
+                None => break,
+            }
+        } else {
+            prev = Some(c);
+
+            if encap.is_none() {
+                if c == style.level {
+                    finish_level(&mut levels, &mut sort, &mut display)?;
+                    continue;
+                } else if c == style.actual && display.is_none() {
+                    display = Some(String::new());
+                    continue;
+                } else if c == style.encap {
+                    encap = Some(String::new());
+                    continue;
+                }
+            }
+
+            c
+        };
+
+        let cur = match encap {
+            Some(ref mut e) => e,
+            None => match display {
+                Some(ref mut d) => d,
+                None => &mut sort,
+            },
+        };
+
+        cur.push(c);
+    }
+
+    finish_level(&mut levels, &mut sort, &mut display)?;
+
+    // Check for range markers.
+
+    let (encap, range) = match encap {
+        None => (None, RangeMark::None),
+
+        Some(e) => {
+            let mut chars = e.chars();
+
+            let range = match chars.next() {
+                Some(c) if c == style.range_open => RangeMark::Open,
+                Some(c) if c == style.range_close => RangeMark::Close,
+                _ => RangeMark::None,
+            };
+
+            let e = if range == RangeMark::None {
+                e
+            } else {
+                chars.collect()
+            };
+
+            if e.is_empty() {
+                (None, range)
+            } else {
+                (Some(e), range)
+            }
+        }
+    };
+
+    Ok((levels, encap, range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one(text: &str) -> RawEntry {
+        let (mut entries, messages) = parse_idx(text, &Style::default());
+        assert!(messages.is_empty(), "{:?}", messages);
+        assert_eq!(entries.len(), 1);
+        entries.remove(0)
+    }
+
+    fn level(sort: &str, display: &str) -> KeyLevel {
+        KeyLevel {
+            sort: sort.to_owned(),
+            display: display.to_owned(),
+        }
+    }
+
+    #[test]
+    fn simple() {
+        let e = one("\\indexentry{alpha}{3}\n");
+        assert_eq!(e.levels, vec![level("alpha", "alpha")]);
+        assert_eq!(e.encap, None);
+        assert_eq!(e.range, RangeMark::None);
+        assert_eq!(e.page, "3");
+    }
+
+    #[test]
+    fn levels_actual_encap() {
+        let e = one("\\indexentry{alpha!beta@\\textit{beta}|textbf}{iv}");
+        assert_eq!(
+            e.levels,
+            vec![level("alpha", "alpha"), level("beta", "\\textit{beta}")]
+        );
+        assert_eq!(e.encap.as_deref(), Some("textbf"));
+        assert_eq!(e.page, "iv");
+    }
+
+    #[test]
+    fn ranges() {
+        let e = one("\\indexentry{alpha|(}{3}");
+        assert_eq!(e.range, RangeMark::Open);
+        assert_eq!(e.encap, None);
+
+        let e = one("\\indexentry{alpha|)textbf}{5}");
+        assert_eq!(e.range, RangeMark::Close);
+        assert_eq!(e.encap.as_deref(), Some("textbf"));
+    }
+
+    #[test]
+    fn quoting() {
+        let e = one("\\indexentry{a\"!b\"@c}{1}");
+        assert_eq!(e.levels, vec![level("a!b@c", "a!b@c")]);
+
+        // An escaped quote is not a quote, so that `\"a` umlauts work.
+        let e = one("\\indexentry{M\\\"uller}{1}");
+        assert_eq!(e.levels, vec![level("M\\\"uller", "M\\\"uller")]);
+
+        let e = one("\\indexentry{brace \"}}{1}");
+        assert_eq!(e.levels, vec![level("brace }", "brace }")]);
+    }
+
+    #[test]
+    fn hyperref() {
+        let e = one("\\indexentry{alpha|hyperpage}{12}");
+        assert_eq!(e.encap.as_deref(), Some("hyperpage"));
+        assert_eq!(e.page, "12");
+    }
+
+    #[test]
+    fn rejected() {
+        let (entries, messages) = parse_idx(
+            "\\indexentry{a!b!c!d}{1}\n\\indexentry{ok}{2}\n\\indexentry{bad}\n",
+            &Style::default(),
+        );
+        assert_eq!(entries.len(), 1);
+        assert_eq!(messages.len(), 2);
+    }
+}
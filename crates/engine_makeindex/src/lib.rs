@@ -0,0 +1,185 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+#![deny(missing_docs)]
+
+//! The [makeindex] program as a reusable crate.
+//!
+//! [makeindex]: https://ctan.org/pkg/makeindex
+//!
+//! This crate provides a pure-Rust implementation of the classic `makeindex`
+//! program used by [Tectonic]. It reads the raw index entries that the LaTeX
+//! `\makeindex` machinery writes into an `.idx` file, sorts and merges them,
+//! and writes out the typeset index as an `.ind` file, along with an `.ilg`
+//! transcript. Index style (`.ist`) files are supported.
+//!
+//! All I/O goes through the [`DriverHooks`] interface, so this engine can
+//! operate on the in-memory files of a processing session. Rather than using
+//! this crate directly you should probably use the main [`tectonic`] crate,
+//! which runs this engine automatically when a document asks for an index.
+//!
+//! [Tectonic]: https://tectonic-typesetting.github.io/
+//! [`tectonic`]: https://docs.rs/tectonic/
+
+use std::io::{Read, Write};
+use tectonic_bridge_core::DriverHooks;
+use tectonic_errors::prelude::*;
+use tectonic_status_base::StatusBackend;
+
+use crate::style::Style;
+
+mod index;
+mod input;
+mod style;
+
+/// A possible outcome from a makeindex engine invocation.
+///
+/// Fatal problems, like a missing input file, are represented as an `Err`
+/// result rather than a [`MakeindexOutcome`].
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum MakeindexOutcome {
+    /// Nothing bad happened.
+    Spotless = 0,
+
+    /// Some entries or style settings were rejected. The details are in the
+    /// transcript (`.ilg`) file.
+    Warnings = 1,
+}
+
+/// A struct for invoking the makeindex engine.
+///
+/// This struct has a fairly straightforward “builder” interface: you create it,
+/// apply any settings that you wish, and eventually run the
+/// [`process()`](Self::process) method.
+///
+/// Unlike the other Tectonic engines, this one is implemented in pure Rust and
+/// so does not need to take the global engine lock.
+#[derive(Debug, Default)]
+pub struct MakeindexEngine {
+    style: Option<String>,
+}
+
+impl MakeindexEngine {
+    /// Use the named index style (`.ist`) file.
+    ///
+    /// The file is opened through the same I/O stack as the input, so it may
+    /// come from the document directory or the support bundle. If the name has
+    /// no extension, `.ist` is added.
+    pub fn style<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.style = Some(name.to_string());
+        self
+    }
+
+    /// Run makeindex.
+    ///
+    /// The *hooks* parameter provides the I/O environment and receives
+    /// notifications about the files that are read and written.
+    ///
+    /// The *idx* parameter gives the name of the raw index file, created by
+    /// the TeX engine, that will be processed. The index is written to a file
+    /// with the same basename and the extension `.ind`, and the transcript to
+    /// one with the extension `.ilg`.
+    pub fn process(
+        &mut self,
+        hooks: &mut dyn DriverHooks,
+        status: &mut dyn StatusBackend,
+        idx: &str,
+    ) -> Result<MakeindexOutcome> {
+        let stem = idx.strip_suffix(".idx").unwrap_or(idx);
+        let ind_name = format!("{}.ind", stem);
+        let ilg_name = format!("{}.ilg", stem);
+
+        let mut log = vec!["This is makeindex, as implemented in Tectonic.".to_owned()];
+        let mut n_warnings = 0;
+        let mut style = Style::default();
+
+        if let Some(ref name) = self.style {
+            let name = if name.contains('.') {
+                name.clone()
+            } else {
+                format!("{}.ist", name)
+            };
+
+            let text = read_input(hooks, status, &name)?;
+            let messages = style.apply_file(&text);
+            log.push(format!(
+                "Scanning style file {}...done ({} problems).",
+                name,
+                messages.len()
+            ));
+            n_warnings += messages.len();
+            log.extend(messages);
+        }
+
+        let text = read_input(hooks, status, idx)?;
+        let (entries, messages) = input::parse_idx(&text, &style);
+        log.push(format!(
+            "Scanning input file {}...done ({} entries accepted, {} rejected).",
+            idx,
+            entries.len(),
+            messages.len()
+        ));
+        n_warnings += messages.len();
+        log.extend(messages);
+
+        let mut messages = Vec::new();
+        let ind = index::generate(entries, &style, &mut messages);
+        log.push(format!(
+            "Generating output file {}...done ({} lines written, {} warnings).",
+            ind_name,
+            ind.lines().count(),
+            messages.len()
+        ));
+        n_warnings += messages.len();
+        log.extend(messages);
+
+        log.push(format!("Output written in {}.", ind_name));
+        log.push(format!("Transcript written in {}.", ilg_name));
+        log.push(String::new());
+
+        write_output(hooks, status, &ind_name, ind.as_bytes())?;
+        write_output(hooks, status, &ilg_name, log.join("\n").as_bytes())?;
+
+        Ok(if n_warnings == 0 {
+            MakeindexOutcome::Spotless
+        } else {
+            MakeindexOutcome::Warnings
+        })
+    }
+}
+
+fn read_input(
+    hooks: &mut dyn DriverHooks,
+    status: &mut dyn StatusBackend,
+    name: &str,
+) -> Result<String> {
+    let mut ih = atry!(
+        hooks.io().input_open_name(name, status).must_exist();
+        ["makeindex could not open input file `{}`", name]
+    );
+
+    let mut data = Vec::new();
+    atry!(ih.read_to_end(&mut data); ["failed to read `{}`", name]);
+
+    let (name, digest_opt) = ih.into_name_digest();
+    hooks.event_input_closed(name, digest_opt, status);
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn write_output(
+    hooks: &mut dyn DriverHooks,
+    status: &mut dyn StatusBackend,
+    name: &str,
+    data: &[u8],
+) -> Result<()> {
+    let mut oh = atry!(
+        hooks.io().output_open_name(name).must_exist();
+        ["makeindex could not open output file `{}`", name]
+    );
+
+    atry!(oh.write_all(data); ["failed to write `{}`", name]);
+
+    let (name, digest) = oh.into_name_digest();
+    hooks.event_output_closed(name, digest, status);
+    Ok(())
+}
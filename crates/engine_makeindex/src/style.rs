@@ -0,0 +1,418 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Index style (`.ist`) files.
+//!
+//! A style file is a sequence of `key value` pairs, where the value is a
+//! string in double quotes, a character in single quotes, or an integer.
+//! Comments start with `%`. The keys and their defaults follow the
+//! documentation of the classic `makeindex` program.
+
+use std::{iter::Peekable, str::Chars};
+
+/// The settings that control how index entries are parsed and formatted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Style {
+    // Input settings.
+    pub keyword: String,
+    pub arg_open: char,
+    pub arg_close: char,
+    pub range_open: char,
+    pub range_close: char,
+    pub level: char,
+    pub actual: char,
+    pub encap: char,
+    pub quote: char,
+    pub escape: char,
+    pub page_compositor: String,
+
+    // Output settings.
+    pub preamble: String,
+    pub postamble: String,
+    pub setpage_prefix: String,
+    pub setpage_suffix: String,
+    pub group_skip: String,
+    pub headings_flag: i32,
+    pub heading_prefix: String,
+    pub heading_suffix: String,
+    pub symhead_positive: String,
+    pub symhead_negative: String,
+    pub numhead_positive: String,
+    pub numhead_negative: String,
+    pub item_0: String,
+    pub item_1: String,
+    pub item_2: String,
+    pub item_01: String,
+    pub item_x1: String,
+    pub item_12: String,
+    pub item_x2: String,
+    pub delim_0: String,
+    pub delim_1: String,
+    pub delim_2: String,
+    pub delim_n: String,
+    pub delim_r: String,
+    pub delim_t: String,
+    pub encap_prefix: String,
+    pub encap_infix: String,
+    pub encap_suffix: String,
+    pub page_precedence: String,
+    pub line_max: usize,
+    pub indent_space: String,
+    pub indent_length: usize,
+    pub suffix_2p: String,
+    pub suffix_3p: String,
+    pub suffix_mp: String,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            keyword: "\\indexentry".to_owned(),
+            arg_open: '{',
+            arg_close: '}',
+            range_open: '(',
+            range_close: ')',
+            level: '!',
+            actual: '@',
+            encap: '|',
+            quote: '"',
+            escape: '\\',
+            page_compositor: "-".to_owned(),
+
+            preamble: "\\begin{theindex}\n".to_owned(),
+            postamble: "\n\n\\end{theindex}\n".to_owned(),
+            setpage_prefix: String::new(),
+            setpage_suffix: String::new(),
+            group_skip: "\n\n  \\indexspace\n".to_owned(),
+            headings_flag: 0,
+            heading_prefix: String::new(),
+            heading_suffix: String::new(),
+            symhead_positive: "Symbols".to_owned(),
+            symhead_negative: "symbols".to_owned(),
+            numhead_positive: "Numbers".to_owned(),
+            numhead_negative: "numbers".to_owned(),
+            item_0: "\n  \\item ".to_owned(),
+            item_1: "\n    \\subitem ".to_owned(),
+            item_2: "\n      \\subsubitem ".to_owned(),
+            item_01: "\n    \\subitem ".to_owned(),
+            item_x1: "\n    \\subitem ".to_owned(),
+            item_12: "\n      \\subsubitem ".to_owned(),
+            item_x2: "\n      \\subsubitem ".to_owned(),
+            delim_0: ", ".to_owned(),
+            delim_1: ", ".to_owned(),
+            delim_2: ", ".to_owned(),
+            delim_n: ", ".to_owned(),
+            delim_r: "--".to_owned(),
+            delim_t: String::new(),
+            encap_prefix: "\\".to_owned(),
+            encap_infix: "{".to_owned(),
+            encap_suffix: "}".to_owned(),
+            page_precedence: "rnaRA".to_owned(),
+            line_max: 72,
+            indent_space: "\t\t".to_owned(),
+            indent_length: 16,
+            suffix_2p: String::new(),
+            suffix_3p: String::new(),
+            suffix_mp: String::new(),
+        }
+    }
+}
+
+/// A value in a style file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Str(String),
+    Char(char),
+    Int(i64),
+}
+
+impl Style {
+    /// Apply the settings in a style file on top of this style.
+    ///
+    /// Problems are described in the returned list of messages, which are
+    /// intended for the transcript file. Settings that can’t be understood are
+    /// skipped.
+    pub fn apply_file(&mut self, text: &str) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut chars = text.chars().peekable();
+        let mut line = 1;
+
+        loop {
+            // Skip whitespace and comments.
+
+            match chars.peek() {
+                None => break,
+
+                Some('\n') => {
+                    line += 1;
+                    chars.next();
+                    continue;
+                }
+
+                Some(c) if c.is_whitespace() => {
+                    chars.next();
+                    continue;
+                }
+
+                Some('%') => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            line += 1;
+                            break;
+                        }
+                    }
+                    continue;
+                }
+
+                _ => {}
+            }
+
+            // Read a key.
+
+            let mut key = String::new();
+
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+
+                key.push(c);
+                chars.next();
+            }
+
+            while let Some(&c) = chars.peek() {
+                if c == '\n' || !c.is_whitespace() {
+                    break;
+                }
+
+                chars.next();
+            }
+
+            // Read its value.
+
+            let value = match chars.peek() {
+                Some('"') => {
+                    chars.next();
+                    read_quoted(&mut chars, '"', &mut line).map(Value::Str)
+                }
+
+                Some('\'') => {
+                    chars.next();
+                    read_quoted(&mut chars, '\'', &mut line).and_then(|s| {
+                        let mut it = s.chars();
+                        match (it.next(), it.next()) {
+                            (Some(c), None) => Some(Value::Char(c)),
+                            _ => None,
+                        }
+                    })
+                }
+
+                Some(c) if c.is_ascii_digit() || *c == '-' => {
+                    let mut digits = String::new();
+
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_digit() || c == '-') {
+                            break;
+                        }
+
+                        digits.push(c);
+                        chars.next();
+                    }
+
+                    digits.parse().ok().map(Value::Int)
+                }
+
+                _ => None,
+            };
+
+            match value {
+                Some(v) => {
+                    if let Err(e) = self.set(&key, v) {
+                        messages.push(format!("-- style file line {}: {}", line, e));
+                    }
+                }
+
+                None => {
+                    messages.push(format!(
+                        "-- style file line {}: bad value for `{}`",
+                        line, key
+                    ));
+
+                    // Skip to the end of the line to resynchronize.
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            line += 1;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        messages
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        fn char_field(field: &mut char, value: Value) -> Result<(), String> {
+            match value {
+                Value::Char(c) => {
+                    *field = c;
+                    Ok(())
+                }
+                _ => Err("expected a character value".to_owned()),
+            }
+        }
+
+        fn string_field(field: &mut String, value: Value) -> Result<(), String> {
+            match value {
+                Value::Str(s) => {
+                    *field = s;
+                    Ok(())
+                }
+                _ => Err("expected a string value".to_owned()),
+            }
+        }
+
+        fn int_field(value: Value) -> Result<i64, String> {
+            match value {
+                Value::Int(n) => Ok(n),
+                _ => Err("expected a number value".to_owned()),
+            }
+        }
+
+        match key {
+            "keyword" => string_field(&mut self.keyword, value),
+            "arg_open" => char_field(&mut self.arg_open, value),
+            "arg_close" => char_field(&mut self.arg_close, value),
+            "range_open" => char_field(&mut self.range_open, value),
+            "range_close" => char_field(&mut self.range_close, value),
+            "level" => char_field(&mut self.level, value),
+            "actual" => char_field(&mut self.actual, value),
+            "encap" => char_field(&mut self.encap, value),
+            "quote" => char_field(&mut self.quote, value),
+            "escape" => char_field(&mut self.escape, value),
+            "page_compositor" => string_field(&mut self.page_compositor, value),
+            "preamble" => string_field(&mut self.preamble, value),
+            "postamble" => string_field(&mut self.postamble, value),
+            "setpage_prefix" => string_field(&mut self.setpage_prefix, value),
+            "setpage_suffix" => string_field(&mut self.setpage_suffix, value),
+            "group_skip" => string_field(&mut self.group_skip, value),
+            "headings_flag" | "lethead_flag" => {
+                self.headings_flag = int_field(value)? as i32;
+                Ok(())
+            }
+            "heading_prefix" | "lethead_prefix" => string_field(&mut self.heading_prefix, value),
+            "heading_suffix" | "lethead_suffix" => string_field(&mut self.heading_suffix, value),
+            "symhead_positive" => string_field(&mut self.symhead_positive, value),
+            "symhead_negative" => string_field(&mut self.symhead_negative, value),
+            "numhead_positive" => string_field(&mut self.numhead_positive, value),
+            "numhead_negative" => string_field(&mut self.numhead_negative, value),
+            "item_0" => string_field(&mut self.item_0, value),
+            "item_1" => string_field(&mut self.item_1, value),
+            "item_2" => string_field(&mut self.item_2, value),
+            "item_01" => string_field(&mut self.item_01, value),
+            "item_x1" => string_field(&mut self.item_x1, value),
+            "item_12" => string_field(&mut self.item_12, value),
+            "item_x2" => string_field(&mut self.item_x2, value),
+            "delim_0" => string_field(&mut self.delim_0, value),
+            "delim_1" => string_field(&mut self.delim_1, value),
+            "delim_2" => string_field(&mut self.delim_2, value),
+            "delim_n" => string_field(&mut self.delim_n, value),
+            "delim_r" => string_field(&mut self.delim_r, value),
+            "delim_t" => string_field(&mut self.delim_t, value),
+            "encap_prefix" => string_field(&mut self.encap_prefix, value),
+            "encap_infix" => string_field(&mut self.encap_infix, value),
+            "encap_suffix" => string_field(&mut self.encap_suffix, value),
+            "page_precedence" => {
+                string_field(&mut self.page_precedence, value)?;
+
+                if self.page_precedence.chars().all(|c| "rnaRA".contains(c)) {
+                    Ok(())
+                } else {
+                    self.page_precedence = Style::default().page_precedence;
+                    Err("page_precedence may only contain the letters `rnaRA`".to_owned())
+                }
+            }
+            "line_max" => {
+                self.line_max = int_field(value)?.max(1) as usize;
+                Ok(())
+            }
+            "indent_space" => string_field(&mut self.indent_space, value),
+            "indent_length" => {
+                self.indent_length = int_field(value)?.max(0) as usize;
+                Ok(())
+            }
+            "suffix_2p" => string_field(&mut self.suffix_2p, value),
+            "suffix_3p" => string_field(&mut self.suffix_3p, value),
+            "suffix_mp" => string_field(&mut self.suffix_mp, value),
+            _ => Err(format!("unknown specifier `{}`", key)),
+        }
+    }
+}
+
+/// Read a quoted value from a style file, after the opening delimiter. The
+/// usual backslash escapes are understood. Returns None if the input ends
+/// first.
+fn read_quoted(chars: &mut Peekable<Chars<'_>>, delim: char, line: &mut usize) -> Option<String> {
+    let mut value = String::new();
+
+    loop {
+        let c = chars.next()?;
+
+        if c == delim {
+            return Some(value);
+        }
+
+        if c == '\n' {
+            *line += 1;
+        }
+
+        if c == '\\' {
+            value.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                other => other,
+            });
+        } else {
+            value.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_basic() {
+        let mut style = Style::default();
+        let messages = style.apply_file(
+            "% A comment\n\
+             preamble \"\\\\begin{myindex}\\n\"\n\
+             delim_0 \"\\\\dotfill \"  % trailing comment\n\
+             level '>'\n\
+             headings_flag 1\n",
+        );
+
+        assert!(messages.is_empty(), "{:?}", messages);
+        assert_eq!(style.preamble, "\\begin{myindex}\n");
+        assert_eq!(style.delim_0, "\\dotfill ");
+        assert_eq!(style.level, '>');
+        assert_eq!(style.headings_flag, 1);
+    }
+
+    #[test]
+    fn parse_problems() {
+        let mut style = Style::default();
+        let messages = style.apply_file(
+            "bogus \"x\"\n\
+             level \"not a char\"\n\
+             delim_n\n\
+             delim_r \"-\"\n",
+        );
+
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert_eq!(style.level, '!');
+        assert_eq!(style.delim_r, "-");
+    }
+}
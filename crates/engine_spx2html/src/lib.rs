@@ -25,7 +25,46 @@ mod font;
 
 /// An engine that converts SPX to HTML.
 #[derive(Default)]
-pub struct Spx2HtmlEngine {}
+pub struct Spx2HtmlEngine {
+    output_files: Vec<HtmlOutputFile>,
+}
+
+/// An HTML file written by the engine, and the part of the SPX file that it
+/// was made from.
+///
+/// Together with SyncTeX data, which locate source lines on the pages, this
+/// maps between source lines and HTML files. The page numbers count the
+/// pages of the SPX file, starting at 1, just as SyncTeX does. Vertical
+/// positions are in scaled points down from the TeX origin, as in the SPX
+/// file. Since a file can be emitted partway through a page, the last page
+/// of one file is often the first page of the next one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HtmlOutputFile {
+    /// The path of the file, relative to the output directory, using `/` as
+    /// the separator.
+    pub path: String,
+
+    /// The SPX page on which the content of the file starts.
+    pub first_page: u32,
+
+    /// The vertical position on `first_page` at which the content starts.
+    pub start_y: i32,
+
+    /// The SPX page on which the content of the file ends.
+    pub last_page: u32,
+
+    /// The vertical position on `last_page` at which the content ends. If
+    /// the content runs to the end of the page, this is `i32::MAX`.
+    pub end_y: i32,
+}
+
+impl HtmlOutputFile {
+    /// Whether material at the given position of the SPX file went into this
+    /// file.
+    pub fn contains(&self, page: u32, y: i32) -> bool {
+        (page, y) >= (self.first_page, self.start_y) && (page, y) < (self.last_page, self.end_y)
+    }
+}
 
 impl Spx2HtmlEngine {
     /// Process SPX into HTML.
@@ -47,13 +86,19 @@ impl Spx2HtmlEngine {
         {
             let state = EngineState::new(hooks, status, out_base);
             let state = XdvParser::process_with_seeks(&mut input, state)?;
-            state.finished()?;
+            self.output_files = state.finished()?;
         }
 
         let (name, digest_opt) = input.into_name_digest();
         hooks.event_input_closed(name, digest_opt, status);
         Ok(())
     }
+
+    /// Get the HTML files written by the most recent call to
+    /// [`Self::process_to_filesystem`], in the order that they were written.
+    pub fn output_files(&self) -> &[HtmlOutputFile] {
+        &self.output_files[..]
+    }
 }
 
 struct EngineState<'a> {
@@ -65,6 +110,15 @@ struct Common<'a> {
     hooks: &'a mut dyn DriverHooks,
     status: &'a mut dyn StatusBackend,
     out_base: &'a Path,
+
+    /// The number of the current SPX page, or 0 before the first one.
+    page: u32,
+
+    /// The position at which the content of the next HTML file started.
+    file_first_page: u32,
+    file_start_y: i32,
+
+    output_files: Vec<HtmlOutputFile>,
 }
 
 impl<'a> EngineState<'a> {
@@ -78,6 +132,10 @@ impl<'a> EngineState<'a> {
                 hooks,
                 status,
                 out_base,
+                page: 0,
+                file_first_page: 1,
+                file_start_y: i32::MIN,
+                output_files: Vec::new(),
             },
             state: State::Initializing(InitializationState::default()),
         }
@@ -94,14 +152,14 @@ enum State {
 }
 
 impl<'a> EngineState<'a> {
-    pub fn finished(mut self) -> Result<()> {
+    pub fn finished(mut self) -> Result<Vec<HtmlOutputFile>> {
         if let State::Emitting(mut s) = self.state {
             if !s.current_content.is_empty() {
-                s.finish_file(&mut self.common)?;
+                s.finish_file(i32::MAX, &mut self.common)?;
             }
         }
 
-        Ok(())
+        Ok(self.common.output_files)
     }
 }
 
@@ -116,6 +174,11 @@ impl<'a> XdvEvents for EngineState<'a> {
         Ok(())
     }
 
+    fn handle_begin_page(&mut self, _counters: &[i32], _previous_bop: i32) -> Result<()> {
+        self.common.page += 1;
+        Ok(())
+    }
+
     fn handle_special(&mut self, x: i32, y: i32, contents: &[u8]) -> Result<()> {
         let contents = atry!(std::str::from_utf8(contents); ["could not parse \\special as UTF-8"]);
 
@@ -563,7 +626,7 @@ impl EmittingState {
             }
             Ok(())
         } else if contents == "tdux:emit" {
-            self.finish_file(common)
+            self.finish_file(y, common)
         } else if let Some(texpath) = contents.strip_prefix("tdux:setTemplate ") {
             self.next_template_path = texpath.to_owned();
             Ok(())
@@ -905,7 +968,7 @@ impl EmittingState {
         Ok(())
     }
 
-    fn finish_file(&mut self, common: &mut Common) -> Result<()> {
+    fn finish_file(&mut self, end_y: i32, common: &mut Common) -> Result<()> {
         // Prep the output path
 
         let mut out_path = common.out_base.to_owned();
@@ -990,6 +1053,25 @@ impl EmittingState {
             );
         }
 
+        // Any remaining content on this page goes into the next file.
+
+        let rel_path: Vec<_> = self
+            .next_output_path
+            .split('/')
+            .filter(|p| !p.is_empty())
+            .collect();
+        let last_page = common.page.max(common.file_first_page);
+
+        common.output_files.push(HtmlOutputFile {
+            path: rel_path.join("/"),
+            first_page: common.file_first_page,
+            start_y: common.file_start_y,
+            last_page,
+            end_y,
+        });
+        common.file_first_page = last_page;
+        common.file_start_y = end_y;
+
         self.current_content = String::default();
         Ok(())
     }
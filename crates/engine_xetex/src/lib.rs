@@ -85,6 +85,7 @@ pub struct TexEngine {
     semantic_pagination_enabled: bool,
     shell_escape_enabled: bool,
     build_date: SystemTime,
+    utc_dates: bool,
 }
 
 impl Default for TexEngine {
@@ -96,6 +97,7 @@ impl Default for TexEngine {
             semantic_pagination_enabled: false,
             shell_escape_enabled: false,
             build_date: SystemTime::UNIX_EPOCH,
+            utc_dates: false,
         }
     }
 }
@@ -161,6 +163,17 @@ impl TexEngine {
         self
     }
 
+    /// Configure whether the build date is expressed in UTC, rather than in
+    /// the local time zone, when setting TeX's `\time`, `\day`, `\month`,
+    /// and `\year` parameters.
+    ///
+    /// The default is false. Reproducible builds should enable this so that
+    /// their outputs don't depend on the `TZ` of the machine doing the build.
+    pub fn utc_dates(&mut self, utc: bool) -> &mut Self {
+        self.utc_dates = utc;
+        self
+    }
+
     /// Process a document using the current engine configuration.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -220,6 +233,11 @@ impl TexEngine {
                 c_api::tt_xetex_set_int_variable(b"semantic_pagination_enabled\0".as_ptr() as _, v);
             }
 
+            let v = if self.utc_dates { 1 } else { 0 };
+            unsafe {
+                c_api::tt_xetex_set_int_variable(b"utc_dates\0".as_ptr() as _, v);
+            }
+
             let r = unsafe {
                 c_api::tt_engine_xetex_main(
                     state,
@@ -117,7 +117,8 @@ get_date_and_time (time_t source_date_epoch,
                    int32_t *minutes, int32_t *day,
                    int32_t *month, int32_t *year)
 {
-  struct tm *tmptr = localtime (&source_date_epoch);
+  /* Tectonic: in reproducible mode the date mustn't depend on the time zone. */
+  struct tm *tmptr = utc_dates ? gmtime (&source_date_epoch) : localtime (&source_date_epoch);
   *minutes = tmptr->tm_hour * 60 + tmptr->tm_min;
   *day = tmptr->tm_mday;
   *month = tmptr->tm_mon + 1;
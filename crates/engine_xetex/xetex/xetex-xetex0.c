@@ -5159,6 +5159,11 @@ check_outer_validity(void)
     case (s + MATH_SHIFT): case (s + TAB_MARK): case (s + MAC_PARAM): \
     case (s + SUB_MARK): case (s + LETTER): case (s + OTHER_CHAR)
 
+/* How many tokens get_next() reads between checks for cancellation. */
+#define CANCELLATION_POLL_INTERVAL 4096
+
+static unsigned int tokens_since_cancellation_check = 0;
+
 
 void
 get_next(void)
@@ -5172,6 +5177,14 @@ get_next(void)
     small_number sup_count;
 
 restart:
+    /* Infinite macro loops need not do any I/O, so this is where we make
+     * sure that runaway documents can be stopped. Calling out to the driver
+     * for every token would be slow, though, so we only do so occasionally. */
+    if (++tokens_since_cancellation_check >= CANCELLATION_POLL_INTERVAL) {
+        tokens_since_cancellation_check = 0;
+        ttstub_check_cancelled();
+    }
+
     cur_cs = 0;
 
     if (cur_input.state != TOKEN_LIST) { /*355:*/
@@ -114,16 +114,37 @@ impl GetUrlBackend for ReqwestBackend {
 #[derive(Debug)]
 pub struct ReqwestRangeReader {
     url: String,
-    client: Client,
+
+    /// The client, and the ID of the process that created it.
+    client: Option<(u32, Client)>,
 }
 
 impl ReqwestRangeReader {
     fn new(url: &str) -> ReqwestRangeReader {
         ReqwestRangeReader {
             url: url.to_owned(),
-            client: Client::new(),
+            client: None,
         }
     }
+
+    /// Get the HTTP client, creating it if needed.
+    ///
+    /// The blocking client does its work in a background thread. If this
+    /// process was forked from the one that created the client, that thread
+    /// doesn't exist here, so we need a new client. The old one is leaked
+    /// rather than dropped, since dropping it would wait for the missing
+    /// thread.
+    fn client(&mut self) -> &Client {
+        let pid = std::process::id();
+
+        match self.client.take() {
+            Some((p, c)) if p == pid => self.client = Some((p, c)),
+            Some((_, stale)) => std::mem::forget(stale),
+            None => {}
+        }
+
+        &self.client.get_or_insert_with(|| (pid, Client::new())).1
+    }
 }
 
 impl RangeReader for ReqwestRangeReader {
@@ -136,7 +157,8 @@ impl RangeReader for ReqwestRangeReader {
         let mut headers = HeaderMap::new();
         headers.insert(RANGE, header_val);
 
-        let res = self.client.get(&self.url).headers(headers).send()?;
+        let url = self.url.clone();
+        let res = self.client().get(&url).headers(headers).send()?;
 
         if res.status() != StatusCode::PARTIAL_CONTENT {
             bail!(
@@ -8,7 +8,7 @@ use std::{
     env,
     fs::File,
     io::{self, BufReader, Seek, SeekFrom},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 use tectonic_errors::Result;
 use tectonic_status_base::{tt_warning, StatusBackend};
@@ -75,15 +75,20 @@ impl IoProvider for FilesystemPrimaryInputIo {
 /// FilesystemIo is an I/O provider that reads, and optionally writes, files
 /// from a given root directory.
 ///
-/// NOTE: no effort is made to contain I/O within the specified root!! We have
-/// an option to disallow absolute paths, but we don't do anything about
-/// `../../../....` paths.
+/// NOTE: by default, no effort is made to contain I/O within the specified
+/// root!! We have an option to disallow absolute paths, and reads can be
+/// confined to the root with [`FilesystemIo::confine_reads`], but writes may
+/// still use `../../../....` paths.
 pub struct FilesystemIo {
     root: PathBuf,
     writes_allowed: bool,
     absolute_allowed: bool,
     hidden_input_paths: HashSet<PathBuf>,
     reported_paths: HashSet<PathBuf>,
+
+    /// If reads are confined to the root, the absolute path of the root as
+    /// given, and with symlinks resolved.
+    confinement: Option<(PathBuf, PathBuf)>,
 }
 
 impl FilesystemIo {
@@ -100,6 +105,7 @@ impl FilesystemIo {
             absolute_allowed,
             hidden_input_paths,
             reported_paths: HashSet::new(),
+            confinement: None,
         }
     }
 
@@ -108,6 +114,53 @@ impl FilesystemIo {
         &self.root
     }
 
+    /// Set whether reads are confined to the root directory.
+    ///
+    /// If they are, attempts to read files that are outside of the root, once
+    /// `..` components and symbolic links have been resolved, fail with a
+    /// [`TectonicIoError::PathOutsideRoot`] error. This includes absolute
+    /// paths, if those are allowed at all.
+    pub fn confine_reads(&mut self, confine: bool) -> &mut Self {
+        self.confinement = if confine {
+            let lexical = make_abspath(&self.root)
+                .map(|p| normalize_lexically(&p))
+                .unwrap_or_else(|_| self.root.clone());
+            let resolved = self.root.canonicalize().unwrap_or_else(|_| lexical.clone());
+            Some((lexical, resolved))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Check that reading from *path* is consistent with the confinement
+    /// setting.
+    fn check_confinement(&self, path: &Path) -> Result<()> {
+        let (lexical_root, resolved_root) = match self.confinement {
+            Some(ref c) => c,
+            None => return Ok(()),
+        };
+
+        let outside = || TectonicIoError::PathOutsideRoot {
+            path: path.to_owned(),
+            root: self.root.clone(),
+        };
+
+        if !normalize_lexically(&make_abspath(path)?).starts_with(lexical_root) {
+            return Err(outside().into());
+        }
+
+        // If the path can't be resolved, it probably doesn't exist, and the
+        // attempt to open it will fail in the usual way.
+        if let Ok(resolved) = path.canonicalize() {
+            if !resolved.starts_with(resolved_root) {
+                return Err(outside().into());
+            }
+        }
+
+        Ok(())
+    }
+
     fn construct_path(&mut self, name: &str) -> Result<PathBuf> {
         let path = Path::new(name);
 
@@ -180,6 +233,10 @@ impl IoProvider for FilesystemIo {
             return OpenResult::NotAvailable;
         }
 
+        if let Err(e) = self.check_confinement(&path) {
+            return OpenResult::Err(e);
+        }
+
         let f = match File::open(&path) {
             Ok(f) => f,
             Err(e) => {
@@ -280,3 +337,80 @@ fn make_abspath<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     let cwd = env::current_dir()?;
     Ok(cwd.join(path.as_ref()))
 }
+
+/// Resolve `.` and `..` components without touching the filesystem.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            c => result.push(c.as_os_str()),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[test]
+    fn confinement() {
+        let base = env::temp_dir().join(format!("tectonic-io-confine-{}", std::process::id()));
+        let root = base.join("root");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/inside.tex"), "in").unwrap();
+        std::fs::write(base.join("outside.tex"), "out").unwrap();
+
+        let mut status = NoopStatusBackend::default();
+        let mut io = FilesystemIo::new(&root, false, true, HashSet::new());
+
+        assert!(matches!(
+            io.input_open_name("../outside.tex", &mut status),
+            OpenResult::Ok(_)
+        ));
+
+        io.confine_reads(true);
+        assert!(matches!(
+            io.input_open_name("sub/inside.tex", &mut status),
+            OpenResult::Ok(_)
+        ));
+        assert!(matches!(
+            io.input_open_name("sub/../sub/inside.tex", &mut status),
+            OpenResult::Ok(_)
+        ));
+        assert!(io
+            .input_open_name("missing.tex", &mut status)
+            .is_not_available());
+
+        let escaping = [
+            "../outside.tex".to_owned(),
+            "sub/../../outside.tex".to_owned(),
+            base.join("outside.tex").display().to_string(),
+        ];
+
+        for name in &escaping {
+            match io.input_open_name(name, &mut status) {
+                OpenResult::Err(e) => assert!(e.to_string().contains("outside of the directory")),
+                _ => panic!("read of `{}` should have been refused", name),
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(base.join("outside.tex"), root.join("link.tex")).unwrap();
+            assert!(matches!(
+                io.input_open_name("link.tex", &mut status),
+                OpenResult::Err(_)
+            ));
+        }
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
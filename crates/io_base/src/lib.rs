@@ -50,6 +50,24 @@ pub enum TectonicIoError {
     /// Access to this path is forbidden.
     #[error("access to the path `{}` is forbidden", .0.display())]
     PathForbidden(PathBuf),
+
+    /// This path leads outside of the directory that I/O is confined to.
+    #[error(
+        "refusing to read `{}` because it is outside of the directory `{}`",
+        .path.display(),
+        .root.display()
+    )]
+    PathOutsideRoot {
+        /// The path that was requested.
+        path: PathBuf,
+
+        /// The directory that I/O is confined to.
+        root: PathBuf,
+    },
+
+    /// A limit on the amount of data written during processing was reached.
+    #[error("{0}")]
+    LimitExceeded(String),
 }
 
 /// An extension to the basic Read trait supporting additional features
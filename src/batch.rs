@@ -0,0 +1,596 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Compiling many documents in parallel.
+//!
+//! The TeX engines are not thread-safe, so a process can only run one of them
+//! at a time (see
+//! [`CoreBridgeLauncher::with_global_lock`](tectonic_bridge_core::CoreBridgeLauncher::with_global_lock)).
+//! To get around this, a [`BatchRunner`] processes each document in its own
+//! worker subprocess, running several of them at once:
+//!
+//! ```no_run
+//! use tectonic::{batch::BatchRunner, driver::ProcessingSessionBuilder, status::NoopStatusBackend};
+//!
+//! let mut runner = BatchRunner::default();
+//!
+//! for i in 0..100 {
+//!     let mut sb = ProcessingSessionBuilder::default();
+//!     sb.primary_input_path(format!("variant{}.tex", i))
+//!         .tex_input_name(&format!("variant{}.tex", i));
+//!     runner.add_job(format!("variant{}", i), sb);
+//! }
+//!
+//! let mut status = NoopStatusBackend::default();
+//! for result in runner.run(&mut status) {
+//!     if let Some(e) = &result.error {
+//!         eprintln!("{} failed: {}", result.name, e);
+//!     }
+//! }
+//! ```
+//!
+//! The workers are created by forking the current process, so the session
+//! builders don’t need to be serialized. The bundle cache and the format file
+//! cache can be shared by the workers, since they coordinate their updates
+//! through file locks and atomic renames, respectively. The runner itself
+//! doesn’t start any threads: it forks each worker from the calling thread,
+//! and collects their results by polling. Forking a process still has the
+//! usual caveat, though: only the calling thread is copied into the workers,
+//! so the calling program must not have any other threads running TeX
+//! engines, or holding locks that the workers might need, when
+//! [`BatchRunner::run`] is called.
+//!
+//! On platforms without `fork()`, the documents are processed one after
+//! another in the current process.
+
+use std::{
+    fmt::Arguments,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    str,
+};
+use tectonic_errors::Error;
+
+use crate::{
+    driver::ProcessingSessionBuilder,
+    status::{MessageKind, StatusBackend},
+    tt_note, tt_warning,
+};
+
+/// A message that was reported while processing one document of a batch.
+#[derive(Clone, Debug)]
+pub struct BatchMessage {
+    /// The kind of the message.
+    pub kind: MessageKind,
+
+    /// The message text. If the message was caused by an error, the error’s
+    /// causes follow on separate lines.
+    pub text: String,
+}
+
+/// The outcome of processing one document of a batch.
+#[derive(Clone, Debug)]
+pub struct BatchResult {
+    /// The name that was given to the document’s job.
+    pub name: String,
+
+    /// The messages that were reported while processing the document.
+    pub messages: Vec<BatchMessage>,
+
+    /// The engine logs that were dumped because of an error, if any.
+    pub error_logs: Vec<u8>,
+
+    /// If processing failed, a description of the error. Its causes follow on
+    /// separate lines.
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    fn new(name: String) -> Self {
+        BatchResult {
+            name,
+            messages: Vec::new(),
+            error_logs: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Check whether the document was processed successfully.
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Report the messages, logs, and error of this job to a status backend.
+    pub fn replay(&self, status: &mut dyn StatusBackend) {
+        for msg in &self.messages {
+            status.report(msg.kind, format_args!("{}", msg.text), None);
+        }
+
+        if !self.error_logs.is_empty() {
+            status.dump_error_logs(&self.error_logs);
+        }
+
+        if let Some(e) = &self.error {
+            status.report(MessageKind::Error, format_args!("{}", e), None);
+        }
+    }
+
+    /// Decode the data that a worker sent back. If the worker didn't finish
+    /// properly, *abnormal_exit* describes what happened to it.
+    fn from_worker_output(name: String, mut data: &[u8], abnormal_exit: Option<String>) -> Self {
+        let mut result = BatchResult::new(name);
+        let mut finished = false;
+
+        while let Some((tag, payload, rest)) = read_record(data) {
+            data = rest;
+            let text = || String::from_utf8_lossy(payload).into_owned();
+
+            match tag {
+                "note" | "warning" | "error" => result.messages.push(BatchMessage {
+                    kind: match tag {
+                        "note" => MessageKind::Note,
+                        "warning" => MessageKind::Warning,
+                        _ => MessageKind::Error,
+                    },
+                    text: text(),
+                }),
+                "logs" => result.error_logs.extend_from_slice(payload),
+                "failed" => {
+                    result.error = Some(text());
+                    finished = true;
+                }
+                "ok" => finished = true,
+                _ => {}
+            }
+        }
+
+        if !finished {
+            result.error = Some(match abnormal_exit {
+                Some(how) => format!("the worker process {}", how),
+                None => "the worker process stopped without reporting a result".to_owned(),
+            });
+        }
+
+        result
+    }
+}
+
+/// Process many documents in parallel.
+///
+/// Add documents to the batch with [`Self::add_job`], then process them all
+/// with [`Self::run`].
+pub struct BatchRunner {
+    jobs: Vec<(String, ProcessingSessionBuilder)>,
+    max_workers: usize,
+}
+
+impl Default for BatchRunner {
+    fn default() -> Self {
+        BatchRunner {
+            jobs: Vec::new(),
+            max_workers: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+impl BatchRunner {
+    /// Set the maximum number of documents to process at once. The default is
+    /// the number of CPUs available. Values smaller than one are treated as
+    /// one.
+    pub fn max_workers(&mut self, n: usize) -> &mut Self {
+        self.max_workers = std::cmp::max(n, 1);
+        self
+    }
+
+    /// Add a document to the batch. The *name* is used to identify the
+    /// document in status messages and in the results.
+    pub fn add_job<S: ToString>(
+        &mut self,
+        name: S,
+        builder: ProcessingSessionBuilder,
+    ) -> &mut Self {
+        self.jobs.push((name.to_string(), builder));
+        self
+    }
+
+    /// Process all of the documents in the batch.
+    ///
+    /// A note is reported to *status* as each document finishes. The messages
+    /// reported while processing each document are collected into its
+    /// [`BatchResult`], which can be shown with [`BatchResult::replay`]. The
+    /// results are returned in the order that the jobs were added.
+    pub fn run(self, status: &mut dyn StatusBackend) -> Vec<BatchResult> {
+        let results = self.run_jobs(status);
+
+        let n_failed = results.iter().filter(|r| !r.succeeded()).count();
+        if n_failed > 0 {
+            tt_warning!(
+                status,
+                "{} of {} documents in the batch failed",
+                n_failed,
+                results.len()
+            );
+        }
+
+        results
+    }
+
+    #[cfg(unix)]
+    fn run_jobs(self, status: &mut dyn StatusBackend) -> Vec<BatchResult> {
+        let n_jobs = self.jobs.len();
+        let mut results: Vec<Option<BatchResult>> = (0..n_jobs).map(|_| None).collect();
+        let mut jobs = self.jobs.into_iter().enumerate();
+        let mut running: Vec<worker::Worker> = Vec::new();
+
+        loop {
+            while running.len() < self.max_workers {
+                let (index, (name, builder)) = match jobs.next() {
+                    Some(j) => j,
+                    None => break,
+                };
+
+                match worker::Worker::spawn(index, name, builder) {
+                    Ok(w) => running.push(w),
+                    Err((name, e)) => {
+                        let mut result = BatchResult::new(name);
+                        result.error = Some(format!("couldn't start a worker process: {}", e));
+                        results[index] = Some(result);
+                    }
+                }
+            }
+
+            if running.is_empty() {
+                break;
+            }
+
+            worker::poll(&mut running);
+
+            let mut i = 0;
+
+            while i < running.len() {
+                if !running[i].finished {
+                    i += 1;
+                    continue;
+                }
+
+                let w = running.swap_remove(i);
+                let abnormal_exit = worker::wait(w.pid);
+                let result = BatchResult::from_worker_output(w.name, &w.data, abnormal_exit);
+                note_finished(&result, status);
+                results[w.index] = Some(result);
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|r| r.expect("batch job never finished"))
+            .collect()
+    }
+
+    #[cfg(not(unix))]
+    fn run_jobs(self, status: &mut dyn StatusBackend) -> Vec<BatchResult> {
+        self.jobs
+            .into_iter()
+            .map(|(name, builder)| {
+                let mut data = Vec::new();
+                run_job(builder, &mut data);
+                let result = BatchResult::from_worker_output(name, &data, None);
+                note_finished(&result, status);
+                result
+            })
+            .collect()
+    }
+}
+
+fn note_finished(result: &BatchResult, status: &mut dyn StatusBackend) {
+    if result.succeeded() {
+        tt_note!(status, "finished `{}`", result.name);
+    } else {
+        tt_warning!(status, "failed to process `{}`", result.name);
+    }
+}
+
+/// Process one document, sending the results to *out*. This is what runs
+/// inside of the worker processes. Returns whether processing succeeded.
+fn run_job<W: Write>(builder: ProcessingSessionBuilder, out: &mut W) -> bool {
+    let mut status = WorkerStatusBackend { out };
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        builder
+            .create(&mut status)
+            .and_then(|mut sess| sess.run(&mut status))
+    }));
+
+    let failure = match outcome {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            let mut text = String::new();
+
+            for (i, item) in e.iter().enumerate() {
+                if i > 0 {
+                    text.push_str("\ncaused by: ");
+                }
+                text.push_str(&item.to_string());
+            }
+
+            Some(text)
+        }
+        Err(_) => Some("processing panicked".to_owned()),
+    };
+
+    let written = match &failure {
+        None => write_record(status.out, "ok", b""),
+        Some(text) => write_record(status.out, "failed", text.as_bytes()),
+    };
+
+    written.and_then(|_| status.out.flush()).is_ok() && failure.is_none()
+}
+
+/// A status backend that forwards messages from a worker to the process
+/// running the batch.
+struct WorkerStatusBackend<'a, W: Write> {
+    out: &'a mut W,
+}
+
+impl<'a, W: Write> StatusBackend for WorkerStatusBackend<'a, W> {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        let tag = match kind {
+            MessageKind::Note => "note",
+            MessageKind::Warning => "warning",
+            MessageKind::Error => "error",
+        };
+
+        let mut text = args.to_string();
+
+        if let Some(e) = err {
+            for item in e.chain() {
+                text.push_str("\ncaused by: ");
+                text.push_str(&item.to_string());
+            }
+        }
+
+        let _ignored = write_record(self.out, tag, text.as_bytes());
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        let _ignored = write_record(self.out, "logs", output);
+    }
+}
+
+/// Write one record of the worker protocol: a header line giving a tag and
+/// the length of the payload, followed by the payload itself.
+fn write_record<W: Write>(out: &mut W, tag: &str, payload: &[u8]) -> io::Result<()> {
+    writeln!(out, "{} {}", tag, payload.len())?;
+    out.write_all(payload)
+}
+
+/// Read one record of the worker protocol, returning its tag, its payload,
+/// and the remaining data. Returns None at the end of the data or if it is
+/// truncated.
+fn read_record(data: &[u8]) -> Option<(&str, &[u8], &[u8])> {
+    let eol = data.iter().position(|b| *b == b'\n')?;
+    let header = str::from_utf8(&data[..eol]).ok()?;
+    let mut pieces = header.splitn(2, ' ');
+    let tag = pieces.next()?;
+    let len: usize = pieces.next()?.parse().ok()?;
+    let rest = &data[eol + 1..];
+
+    if rest.len() < len {
+        return None;
+    }
+
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+#[cfg(unix)]
+mod worker {
+    use std::{
+        fs::File,
+        io::{self, Read},
+        os::unix::io::{AsRawFd, FromRawFd},
+    };
+
+    use super::run_job;
+    use crate::driver::ProcessingSessionBuilder;
+
+    /// A running worker process.
+    pub struct Worker {
+        /// The index of the worker's job in the batch.
+        pub index: usize,
+
+        /// The name of the worker's job.
+        pub name: String,
+
+        pub pid: libc::pid_t,
+
+        /// The pipe from which the worker's results are read.
+        pipe: File,
+
+        /// The results read so far.
+        pub data: Vec<u8>,
+
+        /// Whether the worker has closed its end of the pipe.
+        pub finished: bool,
+    }
+
+    impl Worker {
+        /// Fork a worker process to process the document. If that fails, the
+        /// job's name is handed back along with the error.
+        pub fn spawn(
+            index: usize,
+            name: String,
+            builder: ProcessingSessionBuilder,
+        ) -> Result<Worker, (String, io::Error)> {
+            let (read_end, write_end) = match cloexec_pipe() {
+                Ok(p) => p,
+                Err(e) => return Err((name, e)),
+            };
+
+            let pid = unsafe { libc::fork() };
+
+            if pid < 0 {
+                return Err((name, io::Error::last_os_error()));
+            }
+
+            if pid == 0 {
+                // We're the worker. Don't return into the caller's code: exit
+                // directly, without running any of its cleanup.
+                drop(read_end);
+                let mut out = io::BufWriter::new(write_end);
+                let success = run_job(builder, &mut out);
+                drop(out);
+                unsafe { libc::_exit(if success { 0 } else { 1 }) };
+            }
+
+            Ok(Worker {
+                index,
+                name,
+                pid,
+                pipe: read_end,
+                data: Vec::new(),
+                finished: false,
+            })
+        }
+
+        /// Read whatever the worker has sent. This should only be called when
+        /// the pipe is known to be readable, since it may block otherwise.
+        fn read_some(&mut self) {
+            let mut buf = [0; 65536];
+
+            match self.pipe.read(&mut buf) {
+                Ok(0) => self.finished = true,
+                Ok(n) => self.data.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => self.finished = true,
+            }
+        }
+    }
+
+    /// Create a pipe whose ends are closed when a program is executed, so
+    /// that the programs that a worker runs, such as shell-escape commands,
+    /// don't hold on to the worker's end of the pipe after it exits.
+    fn cloexec_pipe() -> io::Result<(File, File)> {
+        let mut fds = [0; 2];
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let rc = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let rc = unsafe { libc::pipe(fds.as_mut_ptr()) };
+
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let files = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        // Without `pipe2()`, there's a window in which another thread could
+        // execute a program that inherits the pipe, but there's nothing
+        // better that we can do.
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        for fd in &fds {
+            if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Wait until at least one of the workers has sent something or exited,
+    /// and read what they've sent.
+    pub fn poll(workers: &mut [Worker]) {
+        let mut fds: Vec<_> = workers
+            .iter()
+            .map(|w| libc::pollfd {
+                fd: w.pipe.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        loop {
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } >= 0 {
+                break;
+            }
+
+            if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                // This shouldn't happen. Fall back to reading the workers'
+                // output one after another, which is slower but works.
+                for w in workers.iter_mut() {
+                    let _ignored = w.pipe.read_to_end(&mut w.data);
+                    w.finished = true;
+                }
+                return;
+            }
+        }
+
+        for (w, fd) in workers.iter_mut().zip(&fds) {
+            if fd.revents != 0 {
+                w.read_some();
+            }
+        }
+    }
+
+    /// Wait for a worker process to exit. If it was killed by a signal,
+    /// returns a description of what happened.
+    pub fn wait(pid: libc::pid_t) -> Option<String> {
+        let mut wstatus = 0;
+
+        loop {
+            if unsafe { libc::waitpid(pid, &mut wstatus, 0) } >= 0 {
+                break;
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Some(format!("couldn't be waited for: {}", err));
+            }
+        }
+
+        if libc::WIFSIGNALED(wstatus) {
+            Some(format!("was killed by signal {}", libc::WTERMSIG(wstatus)))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_protocol() {
+        let mut data = Vec::new();
+        write_record(&mut data, "note", b"hello").unwrap();
+        write_record(&mut data, "logs", b"line 1\nline 2\n").unwrap();
+        write_record(&mut data, "failed", b"oops\ncaused by: bad").unwrap();
+
+        let result = BatchResult::from_worker_output("doc".to_owned(), &data, None);
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].kind, MessageKind::Note);
+        assert_eq!(result.messages[0].text, "hello");
+        assert_eq!(result.error_logs, b"line 1\nline 2\n");
+        assert_eq!(result.error.as_deref(), Some("oops\ncaused by: bad"));
+    }
+
+    #[test]
+    fn worker_protocol_truncated() {
+        let mut data = Vec::new();
+        write_record(&mut data, "warning", b"careful").unwrap();
+        write_record(&mut data, "ok", b"").unwrap();
+        assert!(BatchResult::from_worker_output("doc".to_owned(), &data, None).succeeded());
+
+        data.truncate(data.len() - 1);
+        let result =
+            BatchResult::from_worker_output("doc".to_owned(), &data, Some("was killed".into()));
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(
+            result.error.as_deref(),
+            Some("the worker process was killed")
+        );
+    }
+}
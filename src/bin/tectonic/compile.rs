@@ -55,6 +55,18 @@ pub struct CompileOptions {
     #[structopt(long, name = "dest_path")]
     makefile_rules: Option<PathBuf>,
 
+    /// Leave files from the bundle out of the Makefile-format rules
+    #[structopt(long)]
+    makefile_exclude_bundle: bool,
+
+    /// Write a machine-readable JSON report describing this run to <report_path>
+    #[structopt(long, name = "report_path")]
+    build_report: Option<PathBuf>,
+
+    /// Write a JSON manifest listing every input of this run, with digests, to <manifest_path>
+    #[structopt(long, name = "manifest_path")]
+    dependency_manifest: Option<PathBuf>,
+
     /// Which engines to run
     #[structopt(long, default_value = "default", possible_values(&["default", "tex", "bibtex_first"]))]
     pass: String,
@@ -71,10 +83,26 @@ pub struct CompileOptions {
     #[structopt(long)]
     keep_logs: bool,
 
+    /// If processing fails, save the log files in <failed_logs_dir> rather than the output directory
+    #[structopt(long, name = "failed_logs_dir")]
+    failed_logs_dir: Option<PathBuf>,
+
+    /// Treat undefined references and citations as errors
+    #[structopt(long)]
+    fail_on_undefined_refs: bool,
+
     /// Generate SyncTeX data
     #[structopt(long)]
     synctex: bool,
 
+    /// Write the final output file to disk as it is generated, rather than buffering it in memory
+    #[structopt(long)]
+    stream_outputs: bool,
+
+    /// Produce byte-identical outputs for the same inputs, using SOURCE_DATE_EPOCH as the build date
+    #[structopt(long)]
+    reproducible: bool,
+
     /// Tell the engine that no file at <hide_path> exists, if it tries to read it
     #[structopt(long, name = "hide_path")]
     hide: Option<Vec<PathBuf>>,
@@ -83,7 +111,7 @@ pub struct CompileOptions {
     #[structopt(long = "print", short)]
     print_stdout: bool,
 
-    /// The directory in which to place output files [default: the directory containing <input>]
+    /// The directory in which to place output files, or `-` to write the main output to standard output [default: the directory containing <input>]
     #[structopt(name = "outdir", short, long, parse(from_os_str))]
     outdir: Option<PathBuf>,
 
@@ -91,6 +119,11 @@ pub struct CompileOptions {
     #[structopt(long)]
     untrusted: bool,
 
+    /// Load fine-grained security settings from the TOML file <policy_path>
+    #[cfg(feature = "serialization")]
+    #[structopt(long, name = "policy_path")]
+    security_policy: Option<PathBuf>,
+
     /// Unstable options. Pass -Zhelp to show a list
     // TODO we can't pass -Zhelp without also passing <input>
     #[structopt(name = "option", short = "Z", number_of_values = 1)]
@@ -98,6 +131,12 @@ pub struct CompileOptions {
 }
 
 impl CompileOptions {
+    /// Whether the main output is to be written to standard output, in which
+    /// case status messages must go to standard error.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.outdir.as_deref() == Some(Path::new("-"))
+    }
+
     pub fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
         let unstable = UnstableOptions::from_unstable_args(self.unstable.into_iter());
 
@@ -111,8 +150,20 @@ impl CompileOptions {
             SecurityStance::MaybeAllowInsecures
         };
 
-        let mut sess_builder =
-            ProcessingSessionBuilder::new_with_security(SecuritySettings::new(stance));
+        let mut security = SecuritySettings::new(stance);
+
+        if self.untrusted {
+            security.confine_reads();
+        }
+
+        #[cfg(feature = "serialization")]
+        if let Some(ref p) = self.security_policy {
+            let mut policy = security.policy();
+            tectonic::docmodel::apply_security_policy_file(&mut policy, p)?;
+            security.set_policy(policy);
+        }
+
+        let mut sess_builder = ProcessingSessionBuilder::new_with_security(security);
         let format_path = self.format;
         sess_builder
             .unstables(unstable)
@@ -120,7 +171,13 @@ impl CompileOptions {
             .keep_logs(self.keep_logs)
             .keep_intermediates(self.keep_intermediates)
             .format_cache_path(config.format_cache_path()?)
-            .synctex(self.synctex);
+            .synctex(self.synctex)
+            .stream_outputs(self.stream_outputs)
+            .fail_on_undefined_references(self.fail_on_undefined_refs);
+
+        if let Some(ref p) = self.failed_logs_dir {
+            sess_builder.failed_logs_dir(p);
+        }
 
         sess_builder.output_format(OutputFormat::from_str(&self.outfmt).unwrap());
 
@@ -132,7 +189,17 @@ impl CompileOptions {
         }
 
         if let Some(p) = self.makefile_rules {
-            sess_builder.makefile_output_path(p);
+            sess_builder
+                .makefile_output_path(p)
+                .makefile_exclude_bundle_files(self.makefile_exclude_bundle);
+        }
+
+        if let Some(p) = self.build_report {
+            sess_builder.report_output_path(p);
+        }
+
+        if let Some(p) = self.dependency_manifest {
+            sess_builder.dependency_manifest_path(p);
         }
 
         // Input and path setup
@@ -170,13 +237,22 @@ impl CompileOptions {
         }
 
         if let Some(output_dir) = self.outdir {
-            if !output_dir.is_dir() {
+            if output_dir == Path::new("-") {
+                if self.print_stdout {
+                    return Err(errmsg!(
+                        "the engine's chatter can't be printed when the output goes to standard output"
+                    ));
+                }
+
+                sess_builder.output_to_stdout();
+            } else if !output_dir.is_dir() {
                 return Err(errmsg!(
                     "output directory \"{}\" does not exist",
                     output_dir.display()
                 ));
+            } else {
+                sess_builder.output_dir(output_dir);
             }
-            sess_builder.output_dir(output_dir);
         }
 
         // Set up the rest of I/O.
@@ -211,7 +287,9 @@ impl CompileOptions {
             }
             None => time::SystemTime::now(),
         };
-        sess_builder.build_date(build_date);
+        sess_builder
+            .build_date(build_date)
+            .reproducible(self.reproducible);
         run_and_report(sess_builder, status).map(|_| 0)
     }
 }
@@ -126,10 +126,18 @@ fn main() {
         _ => unreachable!(),
     };
 
+    // If the output is going to standard output, everything else has to go
+    // to standard error.
+    let always_stderr = args.compile.writes_to_stdout();
+
     let mut status = if use_cli_color {
-        Box::new(TermcolorStatusBackend::new(chatter_level)) as Box<dyn StatusBackend>
+        let mut sb = TermcolorStatusBackend::new(chatter_level);
+        sb.always_stderr(always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
     } else {
-        Box::new(PlainStatusBackend::new(chatter_level)) as Box<dyn StatusBackend>
+        let mut sb = PlainStatusBackend::new(chatter_level);
+        sb.always_stderr(always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
     };
 
     if args.use_v2 {
@@ -10,11 +10,14 @@ use tectonic::{
     self,
     config::PersistentConfig,
     ctry,
-    docmodel::{DocumentExt, DocumentSetupOptions, WorkspaceCreatorExt},
+    docmodel::{
+        apply_security_policy_file, DocumentExt, DocumentSetupOptions, WorkspaceCreatorExt,
+    },
     driver::PassSetting,
+    errmsg,
     errors::{Result, SyncError},
     status::{termcolor::TermcolorStatusBackend, ChatterLevel, StatusBackend},
-    tt_error, tt_note,
+    tt_error, tt_note, tt_warning,
 };
 use tectonic_bridge_core::{SecuritySettings, SecurityStance};
 use tectonic_bundles::Bundle;
@@ -148,6 +151,10 @@ enum Commands {
     /// Commands relating to this document’s TeX file bundle
     Bundle(BundleCommand),
 
+    #[structopt(name = "cache")]
+    /// Commands relating to Tectonic’s per-user caches
+    Cache(CacheCommand),
+
     #[structopt(name = "compile")]
     /// Run a standalone (La)TeX compilation
     Compile(crate::compile::CompileOptions),
@@ -174,7 +181,9 @@ impl Commands {
         match self {
             Commands::Build(o) => o.customize(cc),
             Commands::Bundle(o) => o.customize(cc),
-            Commands::Compile(_) => {} // avoid namespacing/etc issues
+            Commands::Cache(o) => o.customize(cc),
+            // avoid namespacing/etc issues
+            Commands::Compile(o) => cc.always_stderr = o.writes_to_stdout(),
             Commands::Dump(o) => o.customize(cc),
             Commands::New(o) => o.customize(cc),
             Commands::Show(o) => o.customize(cc),
@@ -186,6 +195,7 @@ impl Commands {
         match self {
             Commands::Build(o) => o.execute(config, status),
             Commands::Bundle(o) => o.execute(config, status),
+            Commands::Cache(o) => o.execute(config, status),
             Commands::Compile(o) => o.execute(config, status),
             Commands::Dump(o) => o.execute(config, status),
             Commands::New(o) => o.execute(config, status),
@@ -202,6 +212,10 @@ pub struct BuildCommand {
     #[structopt(long)]
     untrusted: bool,
 
+    /// Load fine-grained security settings from the TOML file <policy_path>
+    #[structopt(long, name = "policy_path")]
+    security_policy: Option<PathBuf>,
+
     /// Use only resource files cached locally
     #[structopt(short = "C", long)]
     only_cached: bool,
@@ -214,6 +228,22 @@ pub struct BuildCommand {
     #[structopt(long)]
     keep_logs: bool,
 
+    /// Treat undefined references and citations as errors
+    #[structopt(long)]
+    fail_on_undefined_refs: bool,
+
+    /// Skip processing if no inputs have changed since the last build
+    #[structopt(long)]
+    incremental: bool,
+
+    /// Reuse intermediate files, such as `.aux` files, from the last build
+    #[structopt(long)]
+    reuse_intermediates: bool,
+
+    /// Produce byte-identical outputs for the same inputs, using SOURCE_DATE_EPOCH as the build date
+    #[structopt(long)]
+    reproducible: bool,
+
     /// Print the engine's chatter during processing
     #[structopt(long = "print", short)]
     print_stdout: bool,
@@ -240,8 +270,19 @@ impl BuildCommand {
             SecurityStance::MaybeAllowInsecures
         };
 
-        let mut setup_options =
-            DocumentSetupOptions::new_with_security(SecuritySettings::new(stance));
+        let mut security = SecuritySettings::new(stance);
+
+        if self.untrusted {
+            security.confine_reads();
+        }
+
+        if let Some(ref p) = self.security_policy {
+            let mut policy = security.policy();
+            apply_security_policy_file(&mut policy, p)?;
+            security.set_policy(policy);
+        }
+
+        let mut setup_options = DocumentSetupOptions::new_with_security(security);
         setup_options.only_cached(self.only_cached);
 
         for output_name in doc.output_names() {
@@ -253,6 +294,26 @@ impl BuildCommand {
                 .keep_logs(self.keep_logs)
                 .print_stdout(self.print_stdout);
 
+            if self.fail_on_undefined_refs {
+                builder.fail_on_undefined_references(true);
+            }
+
+            if self.incremental {
+                let mut state_dir = doc.build_dir().to_owned();
+                state_dir.push(output_name);
+                state_dir.push(".tectonic-state");
+                builder.incremental_state_dir(state_dir);
+            }
+
+            if self.reproducible {
+                builder.reproducible(true);
+            }
+
+            if self.reuse_intermediates {
+                let key = format!("{}\n{}", doc.src_dir().display(), output_name);
+                builder.intermediates_cache_dir(config.intermediates_cache_path(&key)?);
+            }
+
             crate::compile::run_and_report(builder, status)?;
 
             if self.open {
@@ -401,6 +462,81 @@ impl BundleSearchCommand {
     }
 }
 
+/// `cache`: Commands relating to Tectonic’s per-user caches
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct CacheCommand {
+    #[structopt(subcommand)]
+    command: CacheCommands,
+}
+
+#[derive(Debug, PartialEq, StructOpt)]
+enum CacheCommands {
+    #[structopt(name = "gc")]
+    /// Delete cached format files that are no longer needed
+    Gc(CacheGcCommand),
+}
+
+impl CacheCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        match &self.command {
+            CacheCommands::Gc(c) => c.customize(cc),
+        }
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.command {
+            CacheCommands::Gc(c) => c.execute(config, status),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, StructOpt)]
+struct CacheGcCommand {
+    /// Also delete formats until the rest take up at most this much space (e.g. `500MB`)
+    #[structopt(long, name = "size")]
+    max_size: Option<String>,
+}
+
+impl CacheGcCommand {
+    fn customize(&self, _cc: &mut CommandCustomizations) {}
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        use byte_unit::Byte;
+        use tectonic::io::format_cache::prune_formats;
+        use tectonic_bundles::cache::Cache;
+
+        let max_size = match self.max_size {
+            Some(ref s) => match Byte::from_str(s) {
+                Ok(b) => Some(b.get_bytes() as u64),
+                Err(e) => return Err(errmsg!("invalid size `{}`: {}", s, e)),
+            },
+            None => None,
+        };
+
+        // Only delete the formats of bundles that we know to be out of
+        // date. Formats for other bundles might belong to documents that we
+        // know nothing about.
+        let cache = Cache::get_user_default()?;
+        let stale = ctry!(
+            cache.superseded_digests();
+            "couldn't determine which bundles in the cache `{}` are out of date", cache.root().display()
+        );
+
+        let dir = config.format_cache_path()?;
+        let deleted = ctry!(prune_formats(&dir, &stale, max_size); "failed to prune the format cache `{}`", dir.display());
+        let freed: u64 = deleted.iter().map(|f| f.size).sum();
+
+        tt_note!(
+            status,
+            "deleted {} cached format file{}, freeing {}",
+            deleted.len(),
+            if deleted.len() == 1 { "" } else { "s" },
+            Byte::from_bytes(freed as u128).get_appropriate_unit(true)
+        );
+        Ok(0)
+    }
+}
+
 /// `dump`: Run a partial build and dump an intermediate file
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct DumpCommand {
@@ -445,8 +581,13 @@ impl DumpCommand {
             SecurityStance::MaybeAllowInsecures
         };
 
-        let mut setup_options =
-            DocumentSetupOptions::new_with_security(SecuritySettings::new(stance));
+        let mut security = SecuritySettings::new(stance);
+
+        if self.untrusted {
+            security.confine_reads();
+        }
+
+        let mut setup_options = DocumentSetupOptions::new_with_security(security);
         setup_options.only_cached(self.only_cached);
 
         // If output profile is unspecified, just grab one at (pseudo-)random.
@@ -614,18 +755,24 @@ enum ShowCommands {
     #[structopt(name = "user-cache-dir")]
     /// Print the location of the default per-user cache directory
     UserCacheDir(ShowUserCacheDirCommand),
+
+    #[structopt(name = "formats")]
+    /// List the cached format files
+    Formats(ShowFormatsCommand),
 }
 
 impl ShowCommand {
     fn customize(&self, cc: &mut CommandCustomizations) {
         match &self.command {
             ShowCommands::UserCacheDir(c) => c.customize(cc),
+            ShowCommands::Formats(c) => c.customize(cc),
         }
     }
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
         match self.command {
             ShowCommands::UserCacheDir(c) => c.execute(config, status),
+            ShowCommands::Formats(c) => c.execute(config, status),
         }
     }
 }
@@ -645,3 +792,33 @@ impl ShowUserCacheDirCommand {
         Ok(0)
     }
 }
+
+#[derive(Debug, PartialEq, StructOpt)]
+struct ShowFormatsCommand {}
+
+impl ShowFormatsCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        use byte_unit::Byte;
+        use tectonic::io::format_cache::list_formats;
+
+        let dir = config.format_cache_path()?;
+
+        for f in list_formats(&dir)? {
+            let size = Byte::from_bytes(f.size as u128).get_appropriate_unit(true);
+            let usable = if f.is_usable() { "" } else { " (unusable)" };
+            println!(
+                "{}\t{}\t{}{}",
+                f.name,
+                f.bundle_digest.to_string(),
+                size,
+                usable
+            );
+        }
+
+        Ok(0)
+    }
+}
@@ -18,7 +18,10 @@ use std::{
 use tectonic_bundles::{
     cache::Cache, dir::DirBundle, itar::IndexedTarBackend, zip::ZipBundle, Bundle,
 };
-use tectonic_io_base::app_dirs;
+use tectonic_io_base::{
+    app_dirs,
+    digest::{self, Digest, DigestData},
+};
 use url::Url;
 
 use crate::{
@@ -188,6 +191,25 @@ impl PersistentConfig {
             Ok(app_dirs::ensure_user_cache_dir("formats")?)
         }
     }
+
+    /// Get the directory in which to save the reusable intermediate files of
+    /// one output of one document. The *key* should uniquely identify them,
+    /// for instance by combining the document's source directory with the
+    /// output name.
+    pub fn intermediates_cache_path(&self, key: &str) -> Result<PathBuf> {
+        let mut dc = digest::create();
+        dc.update(key.as_bytes());
+        let subdir = DigestData::from(dc).to_string();
+
+        let mut path = if CONFIG_TEST_MODE_ACTIVATED.load(Ordering::SeqCst) {
+            std::env::temp_dir().join("tectonic-test-intermediates")
+        } else {
+            app_dirs::ensure_user_cache_dir("intermediates")?
+        };
+
+        path.push(subdir);
+        Ok(path)
+    }
 }
 
 impl Default for PersistentConfig {
@@ -12,12 +12,13 @@ use std::{
     fs, io,
     path::{Path, PathBuf},
 };
-use tectonic_bridge_core::SecuritySettings;
+use tectonic_bridge_core::{SecurityPolicy, SecuritySettings};
 use tectonic_bundles::{
     cache::Cache, dir::DirBundle, itar::IndexedTarBackend, zip::ZipBundle, Bundle,
 };
 use tectonic_docmodel::{
     document::{BuildTargetType, Document},
+    security::SecurityOverrides,
     workspace::{Workspace, WorkspaceCreator},
 };
 use tectonic_geturl::{DefaultBackend, GetUrlBackend};
@@ -151,8 +152,11 @@ impl DocumentExt for Document {
             writeln!(input_buffer, "\\input{{{}}}", profile.postamble_file)?;
         }
 
-        let mut sess_builder =
-            ProcessingSessionBuilder::new_with_security(setup_options.security.clone());
+        // The document can turn off features, but it can't turn them on.
+        let mut security = setup_options.security.clone();
+        security.restrict(&self.security.restrictions());
+
+        let mut sess_builder = ProcessingSessionBuilder::new_with_security(security);
 
         sess_builder
             .output_format(output_format)
@@ -162,6 +166,8 @@ impl DocumentExt for Document {
             .primary_input_buffer(input_buffer.as_bytes())
             .tex_input_name(output_profile);
 
+        sess_builder.reproducible(profile.reproducible);
+
         if profile.shell_escape {
             // For now, this is the only option we allow.
             sess_builder.shell_escape_with_temp_dir();
@@ -176,6 +182,10 @@ impl DocumentExt for Document {
         tex_dir.push("src");
         sess_builder.filesystem_root(&tex_dir);
 
+        if let Some(ref source) = profile.tex_format_source {
+            sess_builder.format_source_path(tex_dir.join(source));
+        }
+
         let mut output_dir = self.build_dir().to_owned();
         output_dir.push(output_profile);
         ctry!(
@@ -218,3 +228,70 @@ impl WorkspaceCreatorExt for WorkspaceCreator {
         Ok(self.create(bundle_loc)?)
     }
 }
+
+/// Extension methods for [`SecurityOverrides`].
+pub trait SecurityOverridesExt {
+    /// Enable or disable each feature of *policy* that these settings mention.
+    fn apply_to(&self, policy: &mut SecurityPolicy);
+
+    /// Get a policy that disallows the features that these settings disable,
+    /// and allows everything else.
+    ///
+    /// This is how the `[security]` section of a document’s `Tectonic.toml`
+    /// is interpreted, since documents shouldn’t be able to grant themselves
+    /// privileges.
+    fn restrictions(&self) -> SecurityPolicy;
+}
+
+impl SecurityOverridesExt for SecurityOverrides {
+    fn apply_to(&self, policy: &mut SecurityPolicy) {
+        fn set(allowed: &mut bool, setting: Option<bool>) {
+            if let Some(v) = setting {
+                *allowed = v;
+            }
+        }
+
+        set(&mut policy.shell_escape, self.shell_escape);
+        set(&mut policy.extra_search_paths, self.extra_search_paths);
+        set(&mut policy.absolute_path_reads, self.absolute_path_reads);
+        set(&mut policy.reads_outside_root, self.reads_outside_root);
+        set(
+            &mut policy.writes_outside_output_dir,
+            self.writes_outside_output_dir,
+        );
+        set(&mut policy.external_tools, self.external_tools);
+    }
+
+    fn restrictions(&self) -> SecurityPolicy {
+        let allowed = |setting: Option<bool>| setting != Some(false);
+
+        SecurityPolicy {
+            shell_escape: allowed(self.shell_escape),
+            extra_search_paths: allowed(self.extra_search_paths),
+            absolute_path_reads: allowed(self.absolute_path_reads),
+            reads_outside_root: allowed(self.reads_outside_root),
+            writes_outside_output_dir: allowed(self.writes_outside_output_dir),
+            external_tools: allowed(self.external_tools),
+        }
+    }
+}
+
+/// Load a standalone security policy file and apply it to *policy*.
+///
+/// The file uses the same TOML syntax as the `[security]` section of
+/// `Tectonic.toml`. Unlike that section, it can enable features as well as
+/// disable them, so it should only ever come from the person running Tectonic.
+pub fn apply_security_policy_file<P: AsRef<Path>>(
+    policy: &mut SecurityPolicy,
+    path: P,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut f =
+        ctry!(fs::File::open(path); "couldn't open security policy file `{}`", path.display());
+    let overrides = ctry!(
+        SecurityOverrides::new_from_toml(&mut f);
+        "couldn't parse security policy file `{}`", path.display()
+    );
+    overrides.apply_to(policy);
+    Ok(())
+}
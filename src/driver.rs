@@ -16,46 +16,56 @@
 //! which contains tectonic's main CLI program.
 
 use byte_unit::Byte;
-use quick_xml::{events::Event, Reader};
+#[cfg(feature = "serde")]
+use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
-    io::{Cursor, Read, Write},
-    path::{Path, PathBuf},
-    process::Command,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
     rc::Rc,
     result::Result as StdResult,
     str::FromStr,
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
+};
+use tectonic_bridge_core::{
+    CancellationToken, ChildOutput, CoreBridgeLauncher, DriverHooks, SandboxSettings,
+    SecuritySettings, SystemRequestError,
 };
-use tectonic_bridge_core::{CoreBridgeLauncher, DriverHooks, SecuritySettings, SystemRequestError};
 use tectonic_bundles::Bundle;
 use tectonic_io_base::{
-    digest::DigestData,
+    digest::{self, Digest, DigestData},
     filesystem::{FilesystemIo, FilesystemPrimaryInputIo},
     stdstreams::{BufferedPrimaryIo, GenuineStdoutIo},
-    InputHandle, IoProvider, OpenResult, OutputHandle,
+    InputHandle, IoProvider, OpenResult, OutputHandle, TectonicIoError,
 };
 
 use crate::{
-    ctry, errmsg,
-    errors::{ChainErrCompatExt, ErrorKind, Result},
+    ctry,
+    engines::{makeindex::MakeindexOutcome, spx2html::HtmlOutputFile},
+    errmsg,
+    errors::{ChainErrCompatExt, ErrorKind, Result, SyncError},
     io::{
         format_cache::FormatCache,
-        memory::{MemoryFileCollection, MemoryIo},
+        memory::{MemoryFileCollection, MemoryIo, MemoryLimits},
+        streaming::{write_output_atomically, StreamingOutputIo},
         InputOrigin,
     },
+    logreq,
     status::StatusBackend,
+    synctex::{HtmlSyncMap, SyncTex},
+    texlog::{self, DiagnosticKind, LogDiagnostic},
     tt_error, tt_note, tt_warning,
     unstable_opts::UnstableOptions,
-    BibtexEngine, Spx2HtmlEngine, TexEngine, TexOutcome, XdvipdfmxEngine,
+    BibtexEngine, MakeindexEngine, Spx2HtmlEngine, TexEngine, TexOutcome, XdvipdfmxEngine,
 };
 
 /// Different patterns with which files may have been accessed by the
 /// underlying engines. Once a file is marked as ReadThenWritten or
 /// WrittenThenRead, its pattern does not evolve further.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum AccessPattern {
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum AccessPattern {
     /// This file is only ever read.
     Read,
 
@@ -99,6 +109,12 @@ struct FileSummary {
     /// written.
     pub write_digest: Option<DigestData>,
 
+    /// If this file was read from disk, where it was found.
+    abspath: Option<PathBuf>,
+
+    /// Whether this file was read from the bundle.
+    from_bundle: bool,
+
     got_written_to_disk: bool,
 }
 
@@ -109,11 +125,423 @@ impl FileSummary {
             input_origin,
             read_digest: None,
             write_digest: None,
+            abspath: None,
+            from_bundle: false,
             got_written_to_disk: false,
         }
     }
 }
 
+/// The name of the file, inside an incremental-build state directory, that
+/// records what happened in the last successful processing session.
+const BUILD_STATE_FILE: &str = "state.txt";
+
+/// The name of the subdirectory, inside an incremental-build state directory,
+/// where copies of the `.aux` files of the last successful session are kept.
+const BUILD_STATE_INTERMEDIATES_DIR: &str = "intermediates";
+
+/// The name of the file, inside an intermediates cache directory, that lists
+/// the intermediate files saved by the last successful session.
+const INTERMEDIATES_MANIFEST_FILE: &str = "manifest.txt";
+
+/// The name of the file, inside an intermediates cache directory, that lists
+/// saved files that can't be trusted, because the session that was going to
+/// replace them didn't finish. They are deleted by the next session that
+/// saves its intermediates.
+const INTERMEDIATES_STALE_FILE: &str = "stale.txt";
+
+/// The name of the file, inside an incremental-build state directory, that
+/// holds a copy of the Makefile rules written by the last successful session.
+const BUILD_STATE_MAKEFILE_RULES: &str = "makefile-rules.d";
+
+/// The name of the file, inside an incremental-build state directory, that
+/// holds a copy of the dependency manifest written by the last successful
+/// session.
+const BUILD_STATE_DEPENDENCY_MANIFEST: &str = "dependency-manifest.json";
+
+/// The first line of a valid build-state file.
+const BUILD_STATE_HEADER: &str = "tectonic-build-state 1";
+
+/// Information about a successful processing session that is persisted to disk
+/// so that a later session with the same configuration can avoid redoing work.
+///
+/// This is stored in a simple line-oriented text format: after the header,
+/// each line is a keyword, possibly a hex digest, and then a file name that
+/// extends to the end of the line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct BuildState {
+    /// A digest of the session configuration, including the primary input and
+    /// the backing bundle.
+    config: DigestData,
+
+    /// The files that were read from the filesystem, with the digests of
+    /// their contents. Files that were looked for but not found are recorded
+    /// with the digest of an empty file.
+    inputs: Vec<(String, DigestData)>,
+
+    /// The files that were written to disk, with the digests of their
+    /// contents.
+    outputs: Vec<(String, DigestData)>,
+
+    /// The `.aux` files that were saved into the state directory.
+    intermediates: Vec<String>,
+}
+
+impl BuildState {
+    /// Parse a build-state file. Returns None if it is malformed.
+    #[allow(clippy::manual_split_once)] // requires Rust 1.52 (note that we don't actually define our MSRV)
+    fn parse(text: &str) -> Option<BuildState> {
+        let mut lines = text.lines();
+
+        if lines.next()? != BUILD_STATE_HEADER {
+            return None;
+        }
+
+        let mut config = None;
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        let mut intermediates = Vec::new();
+
+        for line in lines {
+            let mut pieces = line.splitn(2, ' ');
+            let keyword = pieces.next()?;
+            let rest = pieces.next()?;
+
+            match keyword {
+                "config" => config = Some(rest.parse().ok()?),
+
+                "input" | "output" => {
+                    let mut pieces = rest.splitn(2, ' ');
+                    let digest = pieces.next()?.parse().ok()?;
+                    let name = pieces.next()?.to_owned();
+
+                    if keyword == "input" {
+                        inputs.push((name, digest));
+                    } else {
+                        outputs.push((name, digest));
+                    }
+                }
+
+                "intermediate" => intermediates.push(rest.to_owned()),
+
+                _ => return None,
+            }
+        }
+
+        Some(BuildState {
+            config: config?,
+            inputs,
+            outputs,
+            intermediates,
+        })
+    }
+
+    /// Write out this state in the format understood by [`Self::parse`].
+    fn write<W: Write>(&self, mut dest: W) -> std::io::Result<()> {
+        writeln!(dest, "{}", BUILD_STATE_HEADER)?;
+        writeln!(dest, "config {}", self.config.to_string())?;
+
+        for (name, digest) in &self.inputs {
+            writeln!(dest, "input {} {}", digest.to_string(), name)?;
+        }
+
+        for (name, digest) in &self.outputs {
+            writeln!(dest, "output {} {}", digest.to_string(), name)?;
+        }
+
+        for name in &self.intermediates {
+            writeln!(dest, "intermediate {}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compute the digest of a buffer.
+fn digest_of(data: &[u8]) -> DigestData {
+    let mut dc = digest::create();
+    dc.update(data);
+    DigestData::from(dc)
+}
+
+/// What a [`ProcessingSession`] can skip thanks to the state saved by a
+/// previous session.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum IncrementalPlan {
+    /// Nothing can be skipped.
+    Rebuild,
+
+    /// Only bibliography databases changed, so processing can start with
+    /// BibTeX using the saved `.aux` files.
+    FromBibtex,
+
+    /// Nothing changed and the outputs are all still there, so there is
+    /// nothing to do.
+    UpToDate,
+}
+
+/// The information that a [`ProcessingSession`] needs to save and check
+/// incremental-build state.
+#[derive(Clone, Debug)]
+struct IncrementalSetup {
+    /// The directory in which the state is saved.
+    state_dir: PathBuf,
+
+    /// A digest of the session configuration. State saved with a different
+    /// configuration is ignored.
+    config: DigestData,
+}
+
+/// The different kinds of passes that a [`ProcessingSession`] may run.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum PassKind {
+    /// Generation of a format file with the TeX engine in “initex” mode.
+    Format,
+    /// A run of the TeX engine.
+    Tex,
+    /// A run of the BibTeX engine on one `.aux` file.
+    Bibtex,
+    /// A run of an external program, such as `biber`.
+    ExternalTool,
+    /// A run of the `makeindex` engine on one `.idx` file.
+    Makeindex,
+    /// A run of the `xdvipdfmx` engine.
+    Xdvipdfmx,
+    /// A run of the `spx2html` engine.
+    Spx2html,
+    /// A run of a [`Pass`] supplied by the application.
+    Custom,
+}
+
+/// How a pass run by a [`ProcessingSession`] turned out.
+///
+/// This mirrors [`TexOutcome`], with an additional variant for passes that
+/// failed outright. Passes whose engines don’t distinguish between different
+/// levels of success are reported as `Spotless` if they succeed. The variants
+/// are ordered from best to worst.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum PassOutcome {
+    /// Nothing bad happened.
+    Spotless,
+    /// The engine issued warnings.
+    Warnings,
+    /// The engine issued errors, but they were ignored.
+    Errors,
+    /// The pass failed and processing was aborted.
+    Failed,
+}
+
+impl From<TexOutcome> for PassOutcome {
+    fn from(o: TexOutcome) -> Self {
+        match o {
+            TexOutcome::Spotless => PassOutcome::Spotless,
+            TexOutcome::Warnings => PassOutcome::Warnings,
+            TexOutcome::Errors => PassOutcome::Errors,
+        }
+    }
+}
+
+/// A record of one pass run by a [`ProcessingSession`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PassReport {
+    /// What kind of pass this was.
+    pub kind: PassKind,
+
+    /// Extra information identifying the pass: the name of the format for
+    /// format passes, or [`Pass::name`] for the passes in the processing
+    /// pipeline.
+    pub detail: Option<String>,
+
+    /// For TeX passes, why the engine was (re)run. This is `None` for the
+    /// first TeX pass of a session.
+    pub rerun_reason: Option<RerunReason>,
+
+    /// How the pass turned out.
+    pub outcome: PassOutcome,
+
+    /// The wall-clock time taken by the pass, in seconds.
+    pub elapsed_secs: f64,
+
+    /// For TeX passes, the diagnostics found in the log file that the pass
+    /// wrote. See [`ProcessingSession::log_diagnostics`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub diagnostics: Vec<LogDiagnostic>,
+}
+
+/// A record of the I/O performed on one file during a [`ProcessingSession`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FileReport {
+    /// The pattern with which the file was accessed.
+    pub access_pattern: AccessPattern,
+
+    /// Where the file came from, if it was used as an input.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "report_serde::input_origin")
+    )]
+    pub input_origin: InputOrigin,
+
+    /// The digest of the file when it was first read in the most recent pass
+    /// that read it.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "report_serde::digest"))]
+    pub read_digest: Option<DigestData>,
+
+    /// The digest of the file as it was last written.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "report_serde::digest"))]
+    pub write_digest: Option<DigestData>,
+
+    /// Whether the file was written out to disk at the end of the session.
+    pub written_to_disk: bool,
+}
+
+impl From<&FileSummary> for FileReport {
+    fn from(summ: &FileSummary) -> Self {
+        FileReport {
+            access_pattern: summ.access_pattern,
+            input_origin: summ.input_origin,
+            read_digest: summ.read_digest,
+            write_digest: summ.write_digest,
+            written_to_disk: summ.got_written_to_disk,
+        }
+    }
+}
+
+/// A machine-readable summary of a [`ProcessingSession`] run.
+///
+/// This lists every pass that was run, in order, along with the full table of
+/// files that the engines touched. Obtain one with
+/// [`ProcessingSession::build_report`], or have the session write one out
+/// automatically with [`ProcessingSessionBuilder::report_output_path`].
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BuildReport {
+    /// The passes that were run, in order.
+    pub passes: Vec<PassReport>,
+
+    /// The files accessed during processing, keyed by name. Standard output is
+    /// recorded under the empty string.
+    pub files: BTreeMap<String, FileReport>,
+
+    /// The files that were still changing when the session stopped rerunning
+    /// the TeX engine. See [`ProcessingSession::unconverged_files`].
+    pub unconverged_files: Vec<String>,
+}
+
+impl BuildReport {
+    /// Write this report out in JSON format.
+    #[cfg(feature = "serialization")]
+    pub fn write_json<W: Write>(&self, dest: W) -> Result<()> {
+        serde_json::to_writer_pretty(dest, self)?;
+        Ok(())
+    }
+}
+
+/// Where an input listed in a [`DependencyManifest`] came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum InputSource {
+    /// The primary input.
+    Primary,
+
+    /// A file on the filesystem.
+    Filesystem,
+
+    /// A file from the backing bundle.
+    Bundle,
+
+    /// Something else, such as an intermediate file saved by a previous
+    /// session or a format file.
+    Other,
+}
+
+/// One input listed in a [`DependencyManifest`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ManifestInput {
+    /// The name with which the engines opened the file. This is empty for
+    /// the primary input if it didn't come from a file.
+    pub name: String,
+
+    /// Where the file came from.
+    pub source: InputSource,
+
+    /// Where the file was found on disk, if anywhere.
+    pub path: Option<PathBuf>,
+
+    /// The digest of the file contents that were read, if known.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "report_serde::digest"))]
+    pub digest: Option<DigestData>,
+}
+
+/// A complete list of the inputs of a successful [`ProcessingSession`].
+///
+/// Together with the digest of the bundle, this is a bill of materials for
+/// the outputs: a later session that reads inputs with the same digests will
+/// produce the same outputs. Have the session write one out with
+/// [`ProcessingSessionBuilder::dependency_manifest_path`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DependencyManifest {
+    /// The digest of the backing bundle, as given by [`Bundle::get_digest`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "report_serde::required_digest")
+    )]
+    pub bundle_digest: DigestData,
+
+    /// The inputs, starting with the primary input, followed by the others
+    /// sorted by name.
+    pub inputs: Vec<ManifestInput>,
+}
+
+impl DependencyManifest {
+    /// Write this manifest out in JSON format.
+    #[cfg(feature = "serialization")]
+    pub fn write_json<W: Write>(&self, dest: W) -> Result<()> {
+        serde_json::to_writer_pretty(dest, self)?;
+        Ok(())
+    }
+}
+
+/// Serialization helpers for types in the report that come from other crates.
+#[cfg(feature = "serde")]
+mod report_serde {
+    use serde::Serializer;
+    use tectonic_io_base::{digest::DigestData, InputOrigin};
+
+    pub fn input_origin<S: Serializer>(
+        origin: &InputOrigin,
+        ser: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        ser.serialize_str(match origin {
+            InputOrigin::Filesystem => "filesystem",
+            InputOrigin::NotInput => "not_input",
+            InputOrigin::Other => "other",
+        })
+    }
+
+    pub fn digest<S: Serializer>(
+        digest: &Option<DigestData>,
+        ser: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match digest {
+            Some(d) => ser.serialize_some(&d.to_string()),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn required_digest<S: Serializer>(
+        digest: &DigestData,
+        ser: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        ser.serialize_str(&digest.to_string())
+    }
+}
+
 /// The different types of output files that tectonic knows how to produce.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OutputFormat {
@@ -201,7 +629,6 @@ impl Default for PrimaryInputMode {
 }
 
 /// Different places where the output files might land.
-#[derive(Clone, Debug, Eq, PartialEq)]
 enum OutputDestination {
     /// The "sensible" default. Files will land in the same directory as the
     /// input file, or the current working directory if the input is something
@@ -214,6 +641,10 @@ enum OutputDestination {
     /// Files will not be written to disk. The code running the engine should
     /// examine the memory layer of the I/O stack to obtain the output files.
     Nowhere,
+
+    /// Files will not be written to disk, except that the main output file is
+    /// written to this stream.
+    Writer(Box<dyn Write>),
 }
 
 impl Default for OutputDestination {
@@ -246,6 +677,10 @@ struct BridgeState {
     /// Memory buffering for files written during processing.
     mem: MemoryIo,
 
+    /// If set, final outputs are streamed to disk through this provider
+    /// rather than being buffered in `mem`.
+    streaming: Option<StreamingOutputIo>,
+
     /// The main filesystem backing for input files in the project.
     filesystem: FilesystemIo,
 
@@ -259,6 +694,10 @@ struct BridgeState {
     /// that assume continuity from one to the next.
     shell_escape_work: Option<FilesystemIo>,
 
+    /// What to do with the output of shell-escape commands. If the main output
+    /// file is being written to standard output, theirs must go elsewhere.
+    shell_escape_output: ChildOutput,
+
     /// I/O for saving any generated format files.
     format_cache: FormatCache,
 
@@ -271,8 +710,18 @@ struct BridgeState {
     /// None.
     format_primary: Option<BufferedPrimaryIo>,
 
+    /// Whether the filesystem may be read while generating a format file.
+    /// This is only the case for custom formats.
+    format_reads_filesystem: bool,
+
     /// The I/O events that occurred while processing.
     events: HashMap<String, FileSummary>,
+
+    /// How to confine external tools and shell-escape commands.
+    sandbox: SandboxSettings,
+
+    /// The maximum number of distinct files that the engines may write.
+    max_output_files: Option<usize>,
 }
 
 impl BridgeState {
@@ -284,6 +733,15 @@ impl BridgeState {
             "\\input {}",
             format_file_name
         )));
+        self.format_reads_filesystem = false;
+    }
+
+    /// Enter “format mode”, generating a format from custom initex source.
+    /// Unlike with the bundle's formats, the source may read files from the
+    /// filesystem, such as the packages of the document that it comes from.
+    fn enter_custom_format_mode(&mut self, source: &[u8]) {
+        self.format_primary = Some(BufferedPrimaryIo::from_buffer(source.to_owned()));
+        self.format_reads_filesystem = true;
     }
 
     /// Leave “format mode”.
@@ -303,15 +761,12 @@ impl BridgeState {
         // are treated as "requirements" that will be placed in the tool's
         // working directory.
 
-        let mut cmd = Command::new(&tool.argv[0]);
         let mut read_files = tool.extra_requires.clone();
 
         {
             let mem_files = &*self.mem.files.borrow();
 
             for arg in &tool.argv[1..] {
-                cmd.arg(arg);
-
                 if mem_files.contains_key(arg) {
                     read_files.insert(arg.to_owned());
                 }
@@ -326,6 +781,8 @@ impl BridgeState {
             "can't create temporary directory for external tool"
         );
 
+        let mut tool_inputs = Vec::new();
+
         {
             for name in &read_files {
                 // If a relative parent is found in the file to open, this fn
@@ -371,12 +828,19 @@ impl BridgeState {
                     std::io::copy(&mut ih, &mut f);
                     "failed to write file `{}`", tool_path.display()
                 );
+                tool_inputs.push(tool_path);
             }
         }
 
-        // Now we can actually run the command.
+        // Now we can actually run the command, confined as the security
+        // settings dictate.
 
-        let output = cmd.current_dir(tempdir.path()).output()?;
+        let output = self.sandbox.run(
+            &tool.argv,
+            tempdir.path(),
+            &tool_inputs,
+            ChildOutput::Capture,
+        )?;
 
         if let Some(0) = output.status.code() {
         } else {
@@ -447,6 +911,50 @@ impl BridgeState {
         // Currently, we only consider files in memory as intermediate files.
         return self.mem.files.borrow().keys().cloned().collect();
     }
+
+    /// Compute the digest of the current contents of a file that the engines
+    /// read from the filesystem, bypassing the memory layer and the event
+    /// tracking. A file that doesn't exist gets the digest of an empty file.
+    /// Returns None if the file couldn't be read.
+    fn current_filesystem_digest(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> Option<DigestData> {
+        let providers =
+            std::iter::once(&mut self.filesystem).chain(self.extra_search_paths.iter_mut());
+
+        for fsio in providers {
+            match fsio.input_open_name(name, status) {
+                OpenResult::Ok(mut ih) => {
+                    let mut data = Vec::new();
+                    ih.read_to_end(&mut data).ok()?;
+                    return Some(digest_of(&data));
+                }
+                OpenResult::NotAvailable => continue,
+                OpenResult::Err(_) => return None,
+            }
+        }
+
+        Some(DigestData::of_nothing())
+    }
+
+    /// Compute the digest of a file in the bundle, bypassing the event
+    /// tracking. Returns None if the file couldn't be read.
+    fn current_bundle_digest(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> Option<DigestData> {
+        match self.bundle.input_open_name(name, status) {
+            OpenResult::Ok(mut ih) => {
+                let mut data = Vec::new();
+                ih.read_to_end(&mut data).ok()?;
+                Some(digest_of(&data))
+            }
+            _ => None,
+        }
+    }
 }
 
 macro_rules! bridgestate_ioprovider_try {
@@ -466,15 +974,19 @@ macro_rules! bridgestate_ioprovider_cascade {
         }
 
         // See enter_format_mode above. If creating a format file, disable local
-        // filesystem I/O.
+        // filesystem I/O, unless it's a custom format.
         let use_fs = if let Some(ref mut p) = $self.format_primary {
             bridgestate_ioprovider_try!(p, $($inner)+);
-            false
+            $self.format_reads_filesystem
         } else {
             bridgestate_ioprovider_try!($self.primary_input, $($inner)+);
             true
         };
 
+        if let Some(ref mut p) = $self.streaming {
+            bridgestate_ioprovider_try!(p, $($inner)+);
+        }
+
         bridgestate_ioprovider_try!($self.mem, $($inner)+);
 
         if use_fs {
@@ -504,6 +1016,32 @@ macro_rules! bridgestate_ioprovider_cascade {
 
 impl IoProvider for BridgeState {
     fn output_open_name(&mut self, name: &str) -> OpenResult<OutputHandle> {
+        if let Some(max) = self.max_output_files {
+            let is_new = self
+                .events
+                .get(name)
+                .map(|summ| summ.access_pattern == AccessPattern::Read)
+                .unwrap_or(true);
+
+            if is_new {
+                let n_written = self
+                    .events
+                    .values()
+                    .filter(|summ| summ.access_pattern != AccessPattern::Read)
+                    .count();
+
+                if n_written >= max {
+                    return OpenResult::Err(
+                        TectonicIoError::LimitExceeded(format!(
+                            "refusing to write `{}` because the limit of {} output files has been reached",
+                            name, max
+                        ))
+                        .into(),
+                    );
+                }
+            }
+        }
+
         let r = (|| {
             bridgestate_ioprovider_cascade!(self, output_open_name(name));
         })();
@@ -572,17 +1110,26 @@ impl IoProvider for BridgeState {
         })();
 
         match r {
-            OpenResult::Ok((ref ih, ref _path)) => {
+            OpenResult::Ok((ref ih, ref path)) => {
                 if let Some(summ) = self.events.get_mut(name) {
                     summ.access_pattern = match summ.access_pattern {
                         AccessPattern::Written => AccessPattern::WrittenThenRead,
                         c => c, // identity mapping makes sense for remaining options
                     };
+
+                    if summ.abspath.is_none() {
+                        summ.abspath = path.clone();
+                    }
                 } else {
-                    self.events.insert(
-                        name.to_owned(),
-                        FileSummary::new(AccessPattern::Read, ih.origin()),
-                    );
+                    let mut summ = FileSummary::new(AccessPattern::Read, ih.origin());
+                    summ.abspath = path.clone();
+
+                    // Everything else that comes before the bundle in the
+                    // cascade is either on the filesystem or in memory.
+                    summ.from_bundle = ih.origin() == InputOrigin::Other
+                        && !self.mem.files.borrow().contains_key(name);
+
+                    self.events.insert(name.to_owned(), summ);
                 }
             }
 
@@ -597,6 +1144,11 @@ impl IoProvider for BridgeState {
                         AccessPattern::Written => AccessPattern::WrittenThenRead,
                         c => c, // identity mapping makes sense for remaining options
                     };
+
+                    // The read digest may have been cleared for a rerun.
+                    if summ.read_digest.is_none() {
+                        summ.read_digest = Some(DigestData::of_nothing());
+                    }
                 } else {
                     // Unlike other cases, here we need to fill in the read_digest. `None`
                     // is not an appropriate value since, if the file is written and then
@@ -698,12 +1250,6 @@ impl DriverHooks for BridgeState {
         command: &str,
         status: &mut dyn StatusBackend,
     ) -> StdResult<(), SystemRequestError> {
-        #[cfg(unix)]
-        const SHELL: &[&str] = &["sh", "-c"];
-
-        #[cfg(windows)]
-        const SHELL: &[&str] = &["cmd.exe", "/c"];
-
         // Write any TeX-created files in the memory cache to the shell-escape
         // working directory, since the shell-escape program may need to use
         // them. (This is the case for `minted`.) We basically just hope that
@@ -716,6 +1262,8 @@ impl DriverHooks for BridgeState {
         // anyway!
 
         if let Some(work) = self.shell_escape_work.as_ref() {
+            let mut inputs = Vec::new();
+
             for (name, file) in &*self.mem.files.borrow() {
                 // If it's in the `mem` backend, it's of interest here ...
                 // unless it's stdout.
@@ -732,19 +1280,23 @@ impl DriverHooks for BridgeState {
                     tt_error!(status, "failed to write file `{}`", real_path.display(); e.into());
                     SystemRequestError::Failed
                 })?;
+                inputs.push(real_path);
             }
 
             // Now we can actually run the command.
 
             tt_note!(status, "running shell command: `{}`", command);
 
-            match Command::new(SHELL[0])
-                .args(&SHELL[1..])
-                .arg(&command)
-                .current_dir(work.root())
-                .status()
+            let argv = self.sandbox.shell_command(command).map_err(|e| {
+                tt_warning!(status, "refusing to run command"; e);
+                SystemRequestError::Failed
+            })?;
+
+            match self
+                .sandbox
+                .run(&argv, work.root(), &inputs, self.shell_escape_output)
             {
-                Ok(s) => match s.code() {
+                Ok(output) => match output.status.code() {
                     Some(0) => Ok(()),
                     Some(n) => {
                         tt_warning!(status, "command exited with error code {}", n);
@@ -756,7 +1308,7 @@ impl DriverHooks for BridgeState {
                     }
                 },
                 Err(err) => {
-                    tt_warning!(status, "failed to run command"; err.into());
+                    tt_warning!(status, "failed to run command"; err);
                     Err(SystemRequestError::Failed)
                 }
             }
@@ -813,6 +1365,211 @@ struct ExternalToolPass {
     extra_requires: HashSet<String>,
 }
 
+/// Where a [`Pass`] supplied with [`ProcessingSessionBuilder::add_pass`] fits
+/// into the processing pipeline.
+///
+/// The pipeline that a [`ProcessingSession`] runs with
+/// [`PassSetting::Default`] looks like this:
+///
+/// 1. the `BeforeTex` passes
+/// 2. a TeX pass
+/// 3. BibTeX, or the tools requested through the `logreq` package, if needed,
+///    after the first TeX pass only
+/// 4. the `AfterTex` passes
+/// 5. steps 2–4 again, as long as TeX needs to be rerun
+/// 6. the `BeforeOutput` passes
+/// 7. xdvipdfmx or spx2html, depending on the output format
+/// 8. the `AfterOutput` passes
+///
+/// The other pass settings don’t run any application-supplied passes.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PassPhase {
+    /// Run once, before the first TeX pass.
+    BeforeTex,
+
+    /// Run after every TeX pass. If running one of these passes changes a
+    /// file that TeX read, TeX will be rerun.
+    AfterTex,
+
+    /// Run once, after TeX has stopped being rerun but before the XDV or SPX
+    /// file is converted to the final output format.
+    BeforeOutput,
+
+    /// Run once, after the final output has been generated.
+    AfterOutput,
+}
+
+/// A step in the processing pipeline of a [`ProcessingSession`].
+///
+/// Passes do their I/O through a [`PassContext`], which connects them to the
+/// same memory layer and file-access tracking that the built-in engines use.
+/// This means that if a pass in the [`PassPhase::AfterTex`] phase writes a
+/// file that the TeX engine read, TeX will automatically be rerun.
+pub trait Pass {
+    /// A short name for this pass, used in status messages and the build
+    /// report.
+    fn name(&self) -> String;
+
+    /// What kind of pass this is, for the build report. Application-supplied
+    /// passes should generally leave this alone.
+    fn kind(&self) -> PassKind {
+        PassKind::Custom
+    }
+
+    /// Decide whether this pass needs to be run right now. Passes that
+    /// aren’t needed are not recorded in the build report. By default, passes
+    /// are always run.
+    fn is_needed(
+        &mut self,
+        _ctx: &mut PassContext<'_>,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Run the pass.
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome>;
+
+    /// If the last run of this pass means that the TeX engine must be rerun,
+    /// regardless of whether any of the files that it read have changed,
+    /// return the reason. By default, TeX is only rerun if one of its inputs
+    /// changed.
+    fn forces_rerun(&self) -> Option<RerunReason> {
+        None
+    }
+}
+
+/// The view of a [`ProcessingSession`] that is available to a [`Pass`] while
+/// it runs.
+pub struct PassContext<'a> {
+    bs: &'a mut BridgeState,
+    security: &'a SecuritySettings,
+    primary_input_tex_path: &'a str,
+    tex_aux_path: &'a str,
+    tex_xdv_path: &'a str,
+    tex_pdf_path: &'a str,
+    output_path: Option<&'a Path>,
+    output_format: OutputFormat,
+    build_date: SystemTime,
+    reproducible: bool,
+    cancellation: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    unstables: &'a UnstableOptions,
+    html_output_files: &'a mut Vec<HtmlOutputFile>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Get a launcher for running one of the C/C++ engines with the session’s
+    /// I/O stack and security settings.
+    pub fn launcher<'b>(&'b mut self, status: &'b mut dyn StatusBackend) -> CoreBridgeLauncher<'b> {
+        let mut launcher =
+            CoreBridgeLauncher::new_with_security(self.bs, status, self.security.clone());
+        launcher
+            .fixed_input_mtime(fixed_input_mtime(self.reproducible, self.build_date))
+            .cancellation_token(self.cancellation.clone())
+            .deadline(self.deadline);
+        launcher
+    }
+
+    /// Read the complete contents of a file through the session’s I/O stack,
+    /// recording the access. Returns None if the file doesn’t exist.
+    pub fn read_file(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut ih = match self.bs.input_open_name(name, status) {
+            OpenResult::Ok(ih) => ih,
+            OpenResult::NotAvailable => return Ok(None),
+            OpenResult::Err(e) => {
+                return Err(e).chain_err(|| format!("couldn't open input file `{}`", name))
+            }
+        };
+
+        let mut data = Vec::new();
+        ctry!(ih.read_to_end(&mut data); "couldn't read input file `{}`", name);
+        let (name, digest) = ih.into_name_digest();
+        self.bs.event_input_closed(name, digest, status);
+        Ok(Some(data))
+    }
+
+    /// Write a file through the session’s I/O stack, recording the access.
+    /// The file lands in the memory layer, just like the files written by the
+    /// engines.
+    pub fn write_file(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        let mut oh = match self.bs.output_open_name(name) {
+            OpenResult::Ok(oh) => oh,
+            OpenResult::NotAvailable => {
+                return Err(errmsg!("no way to write output file `{}`", name))
+            }
+            OpenResult::Err(e) => {
+                return Err(e).chain_err(|| format!("couldn't open output file `{}`", name))
+            }
+        };
+
+        ctry!(oh.write_all(data); "couldn't write output file `{}`", name);
+        let (name, digest) = oh.into_name_digest();
+        self.bs.event_output_closed(name, digest, status);
+        Ok(())
+    }
+
+    /// Get the names of the files that have been written into the memory
+    /// layer so far.
+    pub fn intermediate_file_names(&self) -> Vec<String> {
+        self.bs.get_intermediate_file_names()
+    }
+
+    /// The name of the primary input, as the TeX engine knows it.
+    pub fn primary_input_tex_path(&self) -> &str {
+        self.primary_input_tex_path
+    }
+
+    /// The name of the main `.aux` file.
+    pub fn aux_path(&self) -> &str {
+        self.tex_aux_path
+    }
+
+    /// The name of the XDV (or SPX, for HTML output) file written by TeX.
+    pub fn xdv_path(&self) -> &str {
+        self.tex_xdv_path
+    }
+
+    /// The name of the PDF file written by xdvipdfmx.
+    pub fn pdf_path(&self) -> &str {
+        self.tex_pdf_path
+    }
+
+    /// The directory where output files will be written, if they will be
+    /// written to disk at all.
+    pub fn output_path(&self) -> Option<&Path> {
+        self.output_path
+    }
+
+    /// The output format of the session.
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// The date that the engines should use as the build date.
+    pub fn build_date(&self) -> SystemTime {
+        self.build_date
+    }
+
+    /// The unstable options of the session.
+    pub fn unstables(&self) -> &UnstableOptions {
+        self.unstables
+    }
+}
+
 /// A builder-style interface for creating a [`ProcessingSession`].
 ///
 /// This uses standard builder patterns. The `Default` implementation defaults
@@ -825,21 +1582,40 @@ pub struct ProcessingSessionBuilder {
     security: SecuritySettings,
     primary_input: PrimaryInputMode,
     tex_input_name: Option<String>,
+    jobs: Vec<(String, PrimaryInputMode)>,
     output_dest: OutputDestination,
     filesystem_root: Option<PathBuf>,
     format_name: Option<String>,
+    format_source: Option<PrimaryInputMode>,
     format_cache_path: Option<PathBuf>,
     output_format: OutputFormat,
     makefile_output_path: Option<PathBuf>,
+    makefile_exclude_bundle_files: bool,
+    report_output_path: Option<PathBuf>,
+    dependency_manifest_path: Option<PathBuf>,
+    incremental_state_dir: Option<PathBuf>,
+    intermediates_cache_dir: Option<PathBuf>,
     hidden_input_paths: HashSet<PathBuf>,
     pass: PassSetting,
+    custom_passes: Vec<(PassPhase, Box<dyn Pass>)>,
+    index_style: Option<String>,
+    external_tools: HashSet<String>,
     reruns: Option<usize>,
+    rerun_policy: RerunPolicy,
     print_stdout: bool,
     bundle: Option<Box<dyn Bundle>>,
     keep_intermediates: bool,
     keep_logs: bool,
     synctex: bool,
     build_date: Option<SystemTime>,
+    reproducible: bool,
+    cancellation: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    memory_limits: MemoryLimits,
+    max_output_files: Option<usize>,
+    stream_outputs: bool,
+    failed_logs_dir: Option<PathBuf>,
+    fail_on_undefined_references: bool,
     unstables: UnstableOptions,
     shell_escape_mode: ShellEscapeMode,
 }
@@ -881,6 +1657,41 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Adds another job to the session, reading its primary input from a
+    /// file.
+    ///
+    /// Each job is processed like the main one, defined by
+    /// [`Self::primary_input_path`] and [`Self::tex_input_name`], with the
+    /// same settings. The names of its output files are inferred from
+    /// `tex_input_name` in the same way. All of the jobs share the bundle,
+    /// the format, and the memory layer, so that a job can read the
+    /// intermediate files of another one: for instance, the `.aux` files
+    /// needed for cross-document references with the `xr` package.
+    ///
+    /// Jobs are processed in the order that they were added, after the main
+    /// job. If a job has read the `.aux` file of another one that has since
+    /// changed, it is processed again, so that the jobs can refer to each
+    /// other.
+    pub fn add_job_path<P: AsRef<Path>>(&mut self, tex_input_name: &str, p: P) -> &mut Self {
+        self.jobs.push((
+            tex_input_name.to_owned(),
+            PrimaryInputMode::Path(p.as_ref().to_owned()),
+        ));
+        self
+    }
+
+    /// Adds another job to the session, with a caller-specified buffer as
+    /// its primary input.
+    ///
+    /// See [`Self::add_job_path`] for how jobs are processed.
+    pub fn add_job_buffer(&mut self, tex_input_name: &str, buf: &[u8]) -> &mut Self {
+        self.jobs.push((
+            tex_input_name.to_owned(),
+            PrimaryInputMode::Buffer(buf.to_owned()),
+        ));
+        self
+    }
+
     /// Set the directory that serves as the root for finding files on disk.
     ///
     /// If unspecified, and there is a primary input file, the directory
@@ -911,6 +1722,30 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Indicate that the main output file should be written to a stream,
+    /// rather than to disk.
+    ///
+    /// The main output file is the PDF or XDV file, depending on the output
+    /// format; other output formats can't be written to a stream. Its
+    /// contents are written once processing has succeeded. No other files are
+    /// written to disk, although they can still be found in the memory layer
+    /// of the I/O stack.
+    pub fn output_to_writer<W: Write + 'static>(&mut self, dest: W) -> &mut Self {
+        self.output_dest = OutputDestination::Writer(Box::new(dest));
+        self
+    }
+
+    /// Indicate that the main output file should be written to standard
+    /// output.
+    ///
+    /// This is a shorthand for [`Self::output_to_writer`]. Note that nothing
+    /// else should then be printed to standard output: the status backend
+    /// should write its messages to standard error, and
+    /// [`Self::print_stdout`] should not be used.
+    pub fn output_to_stdout(&mut self) -> &mut Self {
+        self.output_to_writer(std::io::stdout())
+    }
+
     /// The name of the `.fmt` file used to initialize the TeX engine.
     ///
     /// This file does not necessarily have to exist already; it will be created
@@ -921,12 +1756,38 @@ impl ProcessingSessionBuilder {
         self
     }
 
-    /// Sets the path to the format file cache.
+    /// Generate the format file from custom initex source, read from a file,
+    /// rather than from the bundle's `tectonic-format-<name>.tex` file.
     ///
-    /// This is used to, well, cache format files, which are generated as
-    /// needed from the backing bundle. Defaults to the same directory as the
-    /// input file, or PWD if the input is a non-file (such as standard
-    /// input).
+    /// This makes it possible to bake a document's preamble into a format,
+    /// like the `mylatexformat` package does, so that its packages don't have
+    /// to be loaded every time the document is processed. The source is run
+    /// through TeX in initex mode, so it must load a base format's source
+    /// itself and end with `\dump`.
+    ///
+    /// The generated format is cached under a name combining the name set
+    /// with [`Self::format_name`] and a digest of the source, so that a new
+    /// format is generated whenever the source changes. Changes to files that
+    /// the source reads don't cause a new format to be generated.
+    pub fn format_source_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.format_source = Some(PrimaryInputMode::Path(p.as_ref().to_owned()));
+        self
+    }
+
+    /// Generate the format file from custom initex source in a buffer.
+    ///
+    /// See [`Self::format_source_path`] for details.
+    pub fn format_source_buffer(&mut self, buf: &[u8]) -> &mut Self {
+        self.format_source = Some(PrimaryInputMode::Buffer(buf.to_owned()));
+        self
+    }
+
+    /// Sets the path to the format file cache.
+    ///
+    /// This is used to, well, cache format files, which are generated as
+    /// needed from the backing bundle. Defaults to the same directory as the
+    /// input file, or PWD if the input is a non-file (such as standard
+    /// input).
     pub fn format_cache_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
         self.format_cache_path = Some(p.as_ref().to_owned());
         self
@@ -939,11 +1800,87 @@ impl ProcessingSessionBuilder {
     }
 
     /// If set, a makefile will be written out at the given path.
+    ///
+    /// The file has a rule whose targets are the output files written to
+    /// disk, and whose prerequisites are the primary input and every other
+    /// file that the engines read from disk, followed by an empty rule for
+    /// each prerequisite so that `make` doesn't complain if one of them
+    /// goes away. This is the same format as the dependency files written by
+    /// `gcc -MD -MP`, so it can also be used by Ninja.
     pub fn makefile_output_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
         self.makefile_output_path = Some(p.as_ref().to_owned());
         self
     }
 
+    /// If true, leave the files that came from the bundle out of the
+    /// prerequisites listed in the makefile. Only files from the bundle cache
+    /// and from directory bundles have paths that can be listed in the first
+    /// place. Defaults to false.
+    pub fn makefile_exclude_bundle_files(&mut self, exclude: bool) -> &mut Self {
+        self.makefile_exclude_bundle_files = exclude;
+        self
+    }
+
+    /// If set, a JSON [`BuildReport`] will be written out at the given path
+    /// once processing finishes, whether or not it succeeded.
+    ///
+    /// Writing the report requires the `serialization` Cargo feature. If it
+    /// is not active, a warning is issued and no report is written.
+    pub fn report_output_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.report_output_path = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// If set, a JSON [`DependencyManifest`] will be written out at the given
+    /// path if processing succeeds.
+    ///
+    /// Writing the manifest requires the `serialization` Cargo feature. If it
+    /// is not active, a warning is issued and no manifest is written.
+    pub fn dependency_manifest_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.dependency_manifest_path = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// Enables incremental rebuilds, saving state in the specified directory.
+    ///
+    /// After a successful run, the session records the digests of the files
+    /// that it read from the filesystem and of the files that it wrote to
+    /// disk. If a later session with the same configuration finds that none
+    /// of those inputs have changed and that the outputs are still intact, it
+    /// only writes out the Makefile rules and the dependency manifest, if
+    /// they were requested, from copies saved by the previous run. If only
+    /// `.bib` files changed, it starts with a BibTeX pass using the `.aux`
+    /// files saved from the previous run.
+    ///
+    /// The directory should be dedicated to this particular document and
+    /// output. It is created if needed. Incremental rebuilds are not possible
+    /// if the primary input is read from standard input, if shell-escape is
+    /// enabled, if custom passes have been added, or if the output files are
+    /// not written to disk.
+    pub fn incremental_state_dir<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.incremental_state_dir = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// Reuse intermediate files from one session to the next, saving them in
+    /// the specified directory.
+    ///
+    /// After a successful run, the session saves the files that the engines
+    /// both wrote and read back, such as `.aux`, `.toc`, and `.bbl` files. A
+    /// later session loads them into its memory layer before starting, so
+    /// that after a typical edit, a single TeX pass produces converged
+    /// output. If a session fails, the saved files are discarded, so that
+    /// stale intermediates can't break things twice in a row.
+    ///
+    /// The directory should be dedicated to this particular document and
+    /// output. It is created if needed. Besides the saved files, the session
+    /// keeps a list of them in the directory, and it only ever deletes files
+    /// that it saved itself.
+    pub fn intermediates_cache_dir<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.intermediates_cache_dir = Some(p.as_ref().to_owned());
+        self
+    }
+
     /// Which kind of pass should the `ProcessingSession` run? Defaults to `PassSetting::Default`
     /// (duh).
     pub fn pass(&mut self, p: PassSetting) -> &mut Self {
@@ -951,6 +1888,42 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Adds an application-supplied pass to the processing pipeline.
+    ///
+    /// The pass is run in the specified phase, after any passes previously
+    /// added to the same phase. See [`PassPhase`] for a description of the
+    /// pipeline. Application-supplied passes are only run if the pass setting
+    /// is `PassSetting::Default` or `PassSetting::BibtexFirst`.
+    pub fn add_pass(&mut self, phase: PassPhase, pass: Box<dyn Pass>) -> &mut Self {
+        self.custom_passes.push((phase, pass));
+        self
+    }
+
+    /// Sets the index style (`.ist`) file used when generating indexes.
+    ///
+    /// Whenever the TeX engine writes a raw index (`.idx`) file, the default
+    /// pass runs the built-in makeindex engine on it. By default, the classic
+    /// makeindex style is used. The named style file is looked up like any
+    /// other input; if the name has no extension, `.ist` is added.
+    pub fn index_style<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.index_style = Some(name.to_string());
+        self
+    }
+
+    /// Allows the named external program to be run on behalf of the document.
+    ///
+    /// LaTeX packages that use `logreq`, such as `biblatex`, describe the
+    /// programs that should be run on a document in a `.run.xml` file.
+    /// Requests for BibTeX and makeindex are handled with the built-in
+    /// engines. Other programs are only run if they have been allowed; `biber`
+    /// is always allowed. No external programs are run if the security
+    /// settings disallow external tools. Running external programs is bad for
+    /// reproducibility and security, so use this with care.
+    pub fn allow_external_tool<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.external_tools.insert(name.to_string());
+        self
+    }
+
     /// If set, and if the pass is set to `PassSetting::Default`, the TeX engine will be re-run
     /// *exactly* this many times.
     ///
@@ -960,6 +1933,12 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Set how the TeX engine reruns are decided, if `reruns` is unset.
+    pub fn rerun_policy(&mut self, policy: RerunPolicy) -> &mut Self {
+        self.rerun_policy = policy;
+        self
+    }
+
     /// If set to `true`, stdout from the TeX engine will be forwarded to actual stdout. (By
     /// default, it will be suppressed.)
     pub fn print_stdout(&mut self, p: bool) -> &mut Self {
@@ -987,7 +1966,47 @@ impl ProcessingSessionBuilder {
         self
     }
 
-    /// If set to `true`, '.log' and '.blg' files will be written out to the filesystem.
+    /// If set to `true`, the final output file (the PDF or XDV file) is
+    /// streamed into a temporary file in the output directory as it is
+    /// generated, rather than being buffered in memory.
+    ///
+    /// Once processing succeeds, the temporary file is atomically renamed into
+    /// place. If it fails, the temporary file is deleted. This saves a lot of
+    /// memory when generating large documents. Intermediate files are still
+    /// kept in memory. Streamed outputs do not count against the limits set
+    /// with [`Self::max_output_bytes`] and [`Self::max_output_file_size`], and
+    /// are not included in [`ProcessingSession::into_file_data`]. This
+    /// setting has no effect if no output directory is in use.
+    pub fn stream_outputs(&mut self, s: bool) -> &mut Self {
+        self.stream_outputs = s;
+        self
+    }
+
+    /// Sets a directory in which to save the log files if processing fails.
+    ///
+    /// Outputs are never written if processing fails, so that the outputs of
+    /// the last successful build are left untouched. Normally, if
+    /// [`Self::keep_logs`] is set, the logs of the failed build are still
+    /// saved in the output directory, replacing the logs of that last
+    /// successful build. If this directory is set, the logs of failed builds
+    /// are saved there instead, whether or not `keep_logs` is set.
+    pub fn failed_logs_dir<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.failed_logs_dir = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// If set to `true`, undefined references and citations in the final TeX
+    /// pass cause processing to fail.
+    ///
+    /// Either way, each undefined reference or citation is reported
+    /// individually, as an error or a warning respectively. When processing
+    /// fails for this reason, no outputs are written.
+    pub fn fail_on_undefined_references(&mut self, f: bool) -> &mut Self {
+        self.fail_on_undefined_references = f;
+        self
+    }
+
+    /// If set to `true`, '.log', '.blg', and '.ilg' files will be written out to the filesystem.
     pub fn keep_logs(&mut self, k: bool) -> &mut Self {
         self.keep_logs = k;
         self
@@ -1006,6 +2025,77 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Enable reproducible builds, so that the same inputs always produce
+    /// byte-identical outputs.
+    ///
+    /// In this mode, the build date is taken from the `SOURCE_DATE_EPOCH`
+    /// environment variable, following the [reproducible-builds.org
+    /// specification][sde], or is the Unix epoch if that variable isn't set.
+    /// Any date set with [`Self::build_date`] is ignored. TeX sees the date in
+    /// UTC, whatever the local time zone is. The engines see that
+    /// date as the modification time of every input file, and `xdvipdfmx`
+    /// generates font subset tags deterministically. Since the PDF document
+    /// ID is derived from the build date and the file names, it is fixed too.
+    ///
+    /// [sde]: https://reproducible-builds.org/specs/source-date-epoch/
+    pub fn reproducible(&mut self, r: bool) -> &mut Self {
+        self.reproducible = r;
+        self
+    }
+
+    /// Allow the processing session to be stopped through a cancellation
+    /// token.
+    ///
+    /// Keep a clone of the token, and cancel it from another thread to stop
+    /// the session. Running engines notice the cancellation as they work and
+    /// abort, and no further passes are started, so that
+    /// [`ProcessingSession::run`] returns an error soon after.
+    pub fn cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Limit the wall-clock time that the processing session may take.
+    ///
+    /// The clock starts when [`ProcessingSession::run`] is called. Once the
+    /// time is up, the engines abort just as if the session had been
+    /// cancelled through [`Self::cancellation_token`]. External programs
+    /// like `biber` are not interrupted, but no new passes are started.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the total number of bytes that the engines may write during the
+    /// processing session.
+    ///
+    /// Everything written counts toward the limit, including the log and
+    /// intermediate files, and data written again on later passes. Once the
+    /// limit is reached, the running engine aborts. By default, there is no
+    /// limit.
+    pub fn max_output_bytes(&mut self, n: u64) -> &mut Self {
+        self.memory_limits.max_total_bytes = Some(n);
+        self
+    }
+
+    /// Limit the size of any single file written by the engines, in bytes.
+    ///
+    /// Once the limit is reached, the running engine aborts. By default, there
+    /// is no limit.
+    pub fn max_output_file_size(&mut self, n: u64) -> &mut Self {
+        self.memory_limits.max_file_bytes = Some(n);
+        self
+    }
+
+    /// Limit the number of distinct files that the engines may write.
+    ///
+    /// Once the limit is reached, the running engine aborts. By default, there
+    /// is no limit.
+    pub fn max_output_files(&mut self, n: usize) -> &mut Self {
+        self.max_output_files = Some(n);
+        self
+    }
+
     /// Loads unstable options into the processing session
     pub fn unstables(&mut self, opts: UnstableOptions) -> &mut Self {
         self.unstables = opts;
@@ -1055,6 +2145,23 @@ impl ProcessingSessionBuilder {
 
         let mut filesystem_root = self.filesystem_root.unwrap_or_default();
 
+        // If we're doing incremental builds, the primary input is part of the
+        // session configuration. It also goes into the dependency manifest.
+        let want_primary_input_digest =
+            self.incremental_state_dir.is_some() || self.dependency_manifest_path.is_some();
+        let primary_input_is_stdin = matches!(self.primary_input, PrimaryInputMode::Stdin);
+        let mut primary_input_digest = None;
+
+        if want_primary_input_digest {
+            match self.primary_input {
+                PrimaryInputMode::Path(ref p) => {
+                    primary_input_digest = std::fs::read(p).ok().map(|d| digest_of(&d));
+                }
+                PrimaryInputMode::Buffer(ref buf) => primary_input_digest = Some(digest_of(buf)),
+                PrimaryInputMode::Stdin => {} // see below
+            }
+        }
+
         let (pio, primary_input_path, default_output_path) = match self.primary_input {
             PrimaryInputMode::Path(p) => {
                 // Set the filesystem root (that's the directory we'll search
@@ -1083,8 +2190,14 @@ impl ProcessingSessionBuilder {
                 // Note that, due to the expected need to rerun the engine
                 // multiple times, we'll need to buffer stdin in its entirety,
                 // so we might as well do that now.
-                let pio = ctry!(BufferedPrimaryIo::from_stdin(); "error reading standard input");
-                let pio: Box<dyn IoProvider> = Box::new(pio);
+                let mut buf = Vec::new();
+                ctry!(std::io::stdin().read_to_end(&mut buf); "error reading standard input");
+
+                if want_primary_input_digest {
+                    primary_input_digest = Some(digest_of(&buf));
+                }
+
+                let pio: Box<dyn IoProvider> = Box::new(BufferedPrimaryIo::from_buffer(buf));
                 (pio, None, "".into())
             }
 
@@ -1098,7 +2211,8 @@ impl ProcessingSessionBuilder {
         let format_cache_path = self
             .format_cache_path
             .unwrap_or_else(|| filesystem_root.clone());
-        let format_cache = FormatCache::new(bundle.get_digest(status)?, format_cache_path);
+        let bundle_digest = bundle.get_digest(status)?;
+        let format_cache = FormatCache::new(bundle_digest, format_cache_path);
 
         let genuine_stdout = if self.print_stdout {
             Some(GenuineStdoutIo::new())
@@ -1109,11 +2223,19 @@ impl ProcessingSessionBuilder {
         // move this out of self to get around borrow checker issues
         let hidden_input_paths = self.hidden_input_paths;
 
+        // If reads outside of the filesystem root aren't allowed, each extra
+        // search path is confined to itself.
+        let confine_reads = !self.security.allow_reads_outside_root();
+
         let extra_search_paths = if self.security.allow_extra_search_paths() {
             self.unstables
                 .extra_search_paths
                 .iter()
-                .map(|p| FilesystemIo::new(p, false, false, hidden_input_paths.clone()))
+                .map(|p| {
+                    let mut io = FilesystemIo::new(p, false, false, hidden_input_paths.clone());
+                    io.confine_reads(confine_reads);
+                    io
+                })
                 .collect()
         } else {
             if !self.unstables.extra_search_paths.is_empty() {
@@ -1122,44 +2244,137 @@ impl ProcessingSessionBuilder {
             Vec::new()
         };
 
-        let filesystem = FilesystemIo::new(&filesystem_root, false, true, hidden_input_paths);
+        let mut filesystem = FilesystemIo::new(
+            &filesystem_root,
+            false,
+            self.security.allow_absolute_path_reads(),
+            hidden_input_paths,
+        );
+        filesystem.confine_reads(confine_reads);
+
+        let mut mem = MemoryIo::new(true);
+        mem.set_limits(self.memory_limits);
 
-        let mem = MemoryIo::new(true);
+        let shell_escape_output = match self.output_dest {
+            OutputDestination::Writer(_) => ChildOutput::StdoutToStderr,
+            _ => ChildOutput::Inherit,
+        };
 
-        let bs = BridgeState {
+        let mut bs = BridgeState {
             primary_input: pio,
             mem,
+            streaming: None,
             filesystem,
             extra_search_paths,
             shell_escape_work: None,
+            shell_escape_output,
             format_cache,
             bundle,
             genuine_stdout,
             format_primary: None,
+            format_reads_filesystem: false,
             events: HashMap::new(),
+            sandbox: self.security.sandbox().clone(),
+            max_output_files: self.max_output_files,
         };
 
         // Now we can do the rest.
 
-        let output_path = match self.output_dest {
-            OutputDestination::Default => Some(default_output_path),
-            OutputDestination::Path(p) => Some(p),
-            OutputDestination::Nowhere => None,
+        let (output_path, output_writer) = match self.output_dest {
+            OutputDestination::Default => (Some(default_output_path), None),
+            OutputDestination::Path(p) => (Some(p), None),
+            OutputDestination::Nowhere => (None, None),
+            OutputDestination::Writer(w) => (None, Some(w)),
         };
 
+        if output_writer.is_some()
+            && !matches!(self.output_format, OutputFormat::Pdf | OutputFormat::Xdv)
+        {
+            return Err(errmsg!(
+                "only PDF and XDV outputs can be written to a stream"
+            ));
+        }
+
         let tex_input_name = self
             .tex_input_name
             .expect("tex_input_name must be specified");
-        let mut aux_path = PathBuf::from(tex_input_name.clone());
-        aux_path.set_extension("aux");
-        let mut xdv_path = aux_path.clone();
-        xdv_path.set_extension(if self.output_format == OutputFormat::Html {
-            "spx"
-        } else {
-            "xdv"
-        });
-        let mut pdf_path = aux_path.clone();
-        pdf_path.set_extension("pdf");
+        let (aux_path, xdv_path, pdf_path) = job_file_names(&tex_input_name, self.output_format);
+
+        let mut format_name = self.format_name.expect("format_name must be specified");
+
+        // A custom format is cached under a name that identifies its source.
+        let format_source = match self.format_source {
+            Some(PrimaryInputMode::Path(p)) => {
+                Some(ctry!(std::fs::read(&p); "couldn't read format source `{}`", p.display()))
+            }
+            Some(PrimaryInputMode::Buffer(buf)) => Some(buf),
+            Some(PrimaryInputMode::Stdin) => unreachable!(),
+            None => None,
+        };
+
+        if let Some(ref source) = format_source {
+            let stem = format_name.split('.').next().unwrap_or_default();
+            format_name = format!("{}-{}", stem, digest_of(source).to_string());
+        }
+
+        if !self.jobs.is_empty() && self.output_format == OutputFormat::Format {
+            return Err(errmsg!(
+                "additional jobs can't be processed when generating a format file"
+            ));
+        }
+
+        let mut jobs = Vec::new();
+
+        for (job_name, input) in self.jobs {
+            let (pio, path, digest): (Box<dyn IoProvider>, _, _) = match input {
+                PrimaryInputMode::Path(p) => {
+                    let digest = if want_primary_input_digest {
+                        std::fs::read(&p).ok().map(|d| digest_of(&d))
+                    } else {
+                        None
+                    };
+                    (Box::new(FilesystemPrimaryInputIo::new(&p)), Some(p), digest)
+                }
+
+                PrimaryInputMode::Buffer(buf) => {
+                    let digest = Some(digest_of(&buf));
+                    (Box::new(BufferedPrimaryIo::from_buffer(buf)), None, digest)
+                }
+
+                PrimaryInputMode::Stdin => unreachable!(),
+            };
+
+            let (aux, xdv, pdf) = job_file_names(&job_name, self.output_format);
+
+            jobs.push(Job {
+                primary_input: pio,
+                primary_input_path: path,
+                primary_input_tex_path: job_name,
+                primary_input_digest: digest,
+                tex_aux_path: aux,
+                tex_xdv_path: xdv,
+                tex_pdf_path: pdf,
+                events: HashMap::new(),
+            });
+        }
+
+        if self.stream_outputs {
+            let output_format = self.output_format;
+            let final_outputs: Vec<_> = std::iter::once((&pdf_path, &xdv_path))
+                .chain(jobs.iter().map(|j| (&j.tex_pdf_path, &j.tex_xdv_path)))
+                .filter_map(|(pdf, xdv)| match output_format {
+                    OutputFormat::Pdf => Some(pdf.clone()),
+                    OutputFormat::Xdv => Some(xdv.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            if let Some(root) = output_path.as_ref() {
+                if !final_outputs.is_empty() {
+                    bs.streaming = Some(StreamingOutputIo::new(root, final_outputs));
+                }
+            }
+        }
 
         let shell_escape_mode = if !self.security.allow_shell_escape() {
             ShellEscapeMode::Disabled
@@ -1177,37 +2392,283 @@ impl ProcessingSessionBuilder {
             }
         };
 
+        // We can't do anything sensible with stdin for incremental builds,
+        // since it will be different next time.
+        let incremental_primary_input_digest = if primary_input_is_stdin {
+            None
+        } else {
+            primary_input_digest
+        };
+
+        // The same goes for the inputs of the additional jobs.
+        let job_digests: Option<Vec<_>> = jobs
+            .iter()
+            .map(|j| {
+                j.primary_input_digest
+                    .map(|d| (j.primary_input_tex_path.clone(), d.to_string()))
+            })
+            .collect();
+
+        let build_date = if self.reproducible {
+            source_date_epoch(status)?
+        } else {
+            self.build_date.unwrap_or(SystemTime::UNIX_EPOCH)
+        };
+
+        let mut external_tools = self.external_tools;
+
+        if self.security.allow_external_tools() {
+            external_tools.insert("biber".to_owned());
+        } else {
+            external_tools.clear();
+        }
+
+        let incremental = match (
+            self.incremental_state_dir,
+            incremental_primary_input_digest,
+            job_digests,
+        ) {
+            (None, _, _) => None,
+
+            // We have no idea what custom passes depend on.
+            (Some(_), _, _) if !self.custom_passes.is_empty() => {
+                tt_warning!(
+                    status,
+                    "incremental rebuilds are not possible when custom passes are used"
+                );
+                None
+            }
+
+            (Some(_), None, _) => {
+                tt_warning!(
+                    status,
+                    "incremental rebuilds are not possible since the primary input can't be saved"
+                );
+                None
+            }
+
+            (Some(_), Some(_), None) => {
+                tt_warning!(
+                    status,
+                    "incremental rebuilds are not possible since the input of a job can't be read"
+                );
+                None
+            }
+
+            (Some(state_dir), Some(primary_input_digest), Some(job_digests)) => {
+                // Everything that affects the outputs, other than the files
+                // that the engines read, should go in here. The build date
+                // only matters for reproducible builds: otherwise, the
+                // document model always sets it to the current time.
+                let reproducible_date = if self.reproducible {
+                    Some(build_date)
+                } else {
+                    None
+                };
+
+                let mut sorted_tools: Vec<_> = external_tools.iter().collect();
+                sorted_tools.sort();
+
+                let config_text = format!(
+                    "{}\n{}\n{}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{}\n{}\n{}\n{}\n{}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{}\n",
+                    primary_input_digest.to_string(),
+                    bundle_digest.to_string(),
+                    tex_input_name,
+                    job_digests,
+                    format_name,
+                    self.output_format,
+                    self.pass,
+                    self.reruns,
+                    self.rerun_policy,
+                    self.synctex,
+                    self.keep_intermediates,
+                    self.keep_logs,
+                    self.reproducible,
+                    self.fail_on_undefined_references,
+                    self.unstables,
+                    output_path,
+                    self.index_style,
+                    reproducible_date,
+                    self.security.policy(),
+                    shell_escape_mode,
+                    sorted_tools,
+                    self.makefile_exclude_bundle_files,
+                );
+
+                Some(IncrementalSetup {
+                    state_dir,
+                    config: digest_of(config_text.as_bytes()),
+                })
+            }
+        };
+
         Ok(ProcessingSession {
             security: self.security,
             bs,
             pass: self.pass,
             primary_input_path,
             primary_input_tex_path: tex_input_name,
-            format_name: self.format_name.unwrap(),
-            tex_aux_path: aux_path.display().to_string(),
-            tex_xdv_path: xdv_path.display().to_string(),
-            tex_pdf_path: pdf_path.display().to_string(),
+            format_name,
+            format_source,
+            tex_aux_path: aux_path,
+            tex_xdv_path: xdv_path,
+            tex_pdf_path: pdf_path,
+            jobs,
             output_format: self.output_format,
             makefile_output_path: self.makefile_output_path,
+            makefile_exclude_bundle_files: self.makefile_exclude_bundle_files,
+            report_output_path: self.report_output_path,
+            dependency_manifest_path: self.dependency_manifest_path,
+            primary_input_digest,
+            bundle_digest,
             output_path,
+            output_writer,
             tex_rerun_specification: self.reruns,
+            rerun_policy: self.rerun_policy,
+            unconverged_files: Vec::new(),
             keep_intermediates: self.keep_intermediates,
             keep_logs: self.keep_logs,
+            failed_logs_dir: self.failed_logs_dir,
+            fail_on_undefined_references: self.fail_on_undefined_references,
             synctex_enabled: self.synctex,
-            build_date: self.build_date.unwrap_or(SystemTime::UNIX_EPOCH),
+            build_date,
+            reproducible: self.reproducible,
+            cancellation: self.cancellation,
+            timeout: self.timeout,
+            deadline: None,
             unstables: self.unstables,
             shell_escape_mode,
+            passes: Vec::new(),
+            custom_passes: self.custom_passes,
+            index_style: self.index_style,
+            external_tools,
+            incremental,
+            intermediates_cache_dir: self.intermediates_cache_dir,
+            html_output_files: Vec::new(),
         })
     }
 }
 
-#[derive(Debug, Clone)]
-enum RerunReason {
+/// Why the TeX engine was rerun during a [`ProcessingSession`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "kind", content = "file", rename_all = "snake_case")
+)]
+pub enum RerunReason {
+    /// The `biber` tool was run.
     Biber,
+    /// BibTeX was run.
     Bibtex,
+    /// The named file was read, then rewritten with different contents.
     FileChange(String),
+    /// The session was told to rerun the engine a fixed number of times.
+    Requested,
+    /// The named [`Pass`] was run and asked for TeX to be rerun.
+    Pass(String),
+}
+
+impl RerunReason {
+    /// A human-readable explanation, fitting into the sentence “Rerunning TeX
+    /// because ...”.
+    fn explanation(&self) -> String {
+        match self {
+            RerunReason::Biber => "biber was run".to_owned(),
+            RerunReason::Bibtex => "bibtex was run".to_owned(),
+            RerunReason::FileChange(ref s) => format!("\"{}\" changed", s),
+            RerunReason::Requested => "I was told to".to_owned(),
+            RerunReason::Pass(ref s) => format!("{} was run", s),
+        }
+    }
+}
+
+/// How a [`ProcessingSession`] decides whether to rerun the TeX engine.
+///
+/// By default, TeX is rerun whenever a file that it read was rewritten with
+/// different contents, up to seven passes in all: the first one plus six
+/// reruns. This type has a “builder”
+/// interface for adjusting that logic; install it with
+/// [`ProcessingSessionBuilder::rerun_policy`].
+#[derive(Clone, Debug)]
+pub struct RerunPolicy {
+    max_passes: usize,
+    ignored_files: Vec<String>,
+    detect_oscillation: bool,
+}
+
+impl Default for RerunPolicy {
+    fn default() -> Self {
+        RerunPolicy {
+            max_passes: DEFAULT_MAX_TEX_PASSES,
+            ignored_files: Vec::new(),
+            detect_oscillation: true,
+        }
+    }
+}
+
+impl RerunPolicy {
+    /// Run the TeX engine at most this many times in all. Values smaller than
+    /// one are treated as one.
+    pub fn max_passes(&mut self, n: usize) -> &mut Self {
+        self.max_passes = std::cmp::max(n, 1);
+        self
+    }
+
+    /// Don’t rerun TeX just because the named file changed.
+    ///
+    /// This is useful for files that change on every pass, such as ones that
+    /// embed timestamps. If the pattern starts with `*`, it matches every
+    /// file whose name ends with the rest of the pattern; for instance,
+    /// `*.aux` matches all `.aux` files.
+    pub fn ignore_file<S: ToString>(&mut self, pattern: S) -> &mut Self {
+        self.ignored_files.push(pattern.to_string());
+        self
+    }
+
+    /// Set whether to stop rerunning TeX if a file oscillates between
+    /// different contents, rather than converging. This is on by default.
+    ///
+    /// A file oscillates if TeX writes out contents that it already wrote in
+    /// an earlier pass, but that differ from the ones that it just read.
+    /// Further passes are unlikely to help, so processing continues with a
+    /// warning naming the file.
+    pub fn detect_oscillation(&mut self, detect: bool) -> &mut Self {
+        self.detect_oscillation = detect;
+        self
+    }
+
+    /// Check whether changes to the named file are ignored.
+    fn is_ignored(&self, name: &str) -> bool {
+        self.ignored_files
+            .iter()
+            .any(|pattern| match pattern.strip_prefix('*') {
+                Some(suffix) => name.ends_with(suffix),
+                None => name == pattern,
+            })
+    }
+}
+
+/// The state of one of the additional jobs of a processing session. See
+/// [`ProcessingSessionBuilder::add_job_path`].
+struct Job {
+    primary_input: Box<dyn IoProvider>,
+    primary_input_path: Option<PathBuf>,
+    primary_input_tex_path: String,
+    primary_input_digest: Option<DigestData>,
+    tex_aux_path: String,
+    tex_xdv_path: String,
+    tex_pdf_path: String,
+
+    /// The I/O events that occurred while processing this job. They are
+    /// merged into those of the main job once all of the jobs are done.
+    events: HashMap<String, FileSummary>,
 }
 
+/// How many times the jobs of a session are processed, at most, so that
+/// jobs that refer to each other can see each other's `.aux` files.
+const MAX_JOB_ROUNDS: usize = 3;
+
 /// The ProcessingSession struct runs the whole show when we're actually
 /// processing a file. It understands, for example, the need to re-run the TeX
 /// engine if the `.aux` file changed.
@@ -1231,16 +2692,38 @@ pub struct ProcessingSession {
     /// internally, so it has to be String compatible.
     format_name: String,
 
+    /// The initex source of a custom format, if there is one.
+    format_source: Option<Vec<u8>>,
+
     /// These are the paths of the various output files as TeX knows them --
     /// just `primary_input_tex_path` with the extension changed.
     tex_aux_path: String,
     tex_xdv_path: String,
     tex_pdf_path: String,
 
+    /// The additional jobs of this session. While one of them is being
+    /// processed, its state is swapped with that of the main job, above.
+    jobs: Vec<Job>,
+
     /// If we're writing out Makefile rules, this is where they go. The TeX
     /// engine doesn't know about this path at all.
     makefile_output_path: Option<PathBuf>,
 
+    /// Whether to leave bundle files out of the Makefile rules.
+    makefile_exclude_bundle_files: bool,
+
+    /// If we're writing out a JSON build report, this is where it goes.
+    report_output_path: Option<PathBuf>,
+
+    /// If we're writing out a dependency manifest, this is where it goes.
+    dependency_manifest_path: Option<PathBuf>,
+
+    /// The digest of the primary input, if it was needed.
+    primary_input_digest: Option<DigestData>,
+
+    /// The digest of the backing bundle.
+    bundle_digest: DigestData,
+
     /// This is the path that the processed file will be saved at. It defaults
     /// to the path of `primary_input_path` or `.` if STDIN is used. If set to
     /// None, the output files will not be saved to disk — in which case, the
@@ -1248,24 +2731,77 @@ pub struct ProcessingSession {
     /// to the output files.
     output_path: Option<PathBuf>,
 
+    /// If set, the main output file is written to this stream once processing
+    /// has succeeded.
+    output_writer: Option<Box<dyn Write>>,
+
     pass: PassSetting,
     output_format: OutputFormat,
     tex_rerun_specification: Option<usize>,
+    rerun_policy: RerunPolicy,
+
+    /// Files that were still changing when we stopped rerunning TeX.
+    unconverged_files: Vec<String>,
+
     keep_intermediates: bool,
     keep_logs: bool,
+
+    /// Where to save the logs if processing fails, if not the output
+    /// directory.
+    failed_logs_dir: Option<PathBuf>,
+
+    /// Whether undefined references and citations are errors.
+    fail_on_undefined_references: bool,
+
     synctex_enabled: bool,
 
     /// See `TexEngine::with_date` and `XdvipdfmxEngine::with_date`.
     build_date: SystemTime,
 
+    /// Whether to produce byte-identical outputs for the same inputs.
+    reproducible: bool,
+
+    /// A token that can be used to stop the session from another thread.
+    cancellation: Option<CancellationToken>,
+
+    /// How long the session may run for.
+    timeout: Option<Duration>,
+
+    /// When the session must stop, computed from `timeout` when it starts
+    /// running.
+    deadline: Option<Instant>,
+
     unstables: UnstableOptions,
 
     /// How to handle shell-escape. The `Defaulted` option will never
     /// be used here.
     shell_escape_mode: ShellEscapeMode,
+
+    /// The passes that have been run so far, for the build report.
+    passes: Vec<PassReport>,
+
+    /// Passes supplied by the application, and where they go in the pipeline.
+    custom_passes: Vec<(PassPhase, Box<dyn Pass>)>,
+
+    /// The index style file to pass to makeindex, if any.
+    index_style: Option<String>,
+
+    /// The external programs that `logreq` requests may run.
+    external_tools: HashSet<String>,
+
+    /// If we're doing incremental rebuilds, how to go about it.
+    incremental: Option<IncrementalSetup>,
+
+    /// Where intermediate files are saved for reuse by the next session, if
+    /// anywhere.
+    intermediates_cache_dir: Option<PathBuf>,
+
+    /// The HTML files written by the spx2html pass, if it ran.
+    html_output_files: Vec<HtmlOutputFile>,
 }
 
-const DEFAULT_MAX_TEX_PASSES: usize = 6;
+/// The first TeX pass plus up to six reruns.
+const DEFAULT_MAX_TEX_PASSES: usize = 7;
 const ALWAYS_INTERMEDIATE_EXTENSIONS: &[&str] = &[
     ".snm", ".toc", // generated by Beamer
 ];
@@ -1275,10 +2811,22 @@ impl ProcessingSession {
     /// was a file that the engine read and then rewrote, and the rewritten
     /// version is different than the version that it read in.
     fn is_rerun_needed(&self, status: &mut dyn StatusBackend) -> Option<RerunReason> {
+        self.changed_files(status)
+            .into_iter()
+            .next()
+            .map(RerunReason::FileChange)
+    }
+
+    /// Get the names of the files that the engine read and then rewrote with
+    /// different contents, in sorted order. Files that the rerun policy
+    /// ignores are left out.
+    fn changed_files(&self, status: &mut dyn StatusBackend) -> Vec<String> {
         // TODO: we should probably wire up diagnostics since I expect this
         // stuff could get finicky and we're going to want to be able to
         // figure out why rerun detection is breaking.
 
+        let mut changed = Vec::new();
+
         for (name, info) in &self.bs.events {
             if info.access_pattern == AccessPattern::ReadThenWritten {
                 let file_changed = match (&info.read_digest, &info.write_digest) {
@@ -1295,404 +2843,1591 @@ impl ProcessingSession {
                     }
                 };
 
-                if file_changed {
-                    return Some(RerunReason::FileChange(name.clone()));
+                if file_changed && !self.rerun_policy.is_ignored(name) {
+                    changed.push(name.clone());
                 }
             }
         }
 
-        None
+        changed.sort();
+        changed
     }
 
-    #[allow(dead_code)]
-    fn _dump_access_info(&self, status: &mut dyn StatusBackend) {
-        for (name, info) in &self.bs.events {
-            if info.access_pattern != AccessPattern::Read {
-                let r = match info.read_digest {
-                    Some(ref d) => d.to_string(),
-                    None => "-".into(),
-                };
-                let w = match info.write_digest {
-                    Some(ref d) => d.to_string(),
-                    None => "-".into(),
-                };
-                tt_note!(
-                    status,
-                    "ACCESS: {} {:?} {:?} {:?}",
-                    name,
-                    info.access_pattern,
-                    r,
-                    w
-                );
-            }
-        }
+    /// Check whether any of the files that just changed went back to contents
+    /// that were written in an earlier pass, then remember the new contents.
+    /// *history* records the contents written in each pass.
+    fn find_oscillation(
+        &self,
+        history: &mut HashMap<String, Vec<DigestData>>,
+        status: &mut dyn StatusBackend,
+    ) -> Option<String> {
+        let written = self
+            .changed_files(status)
+            .into_iter()
+            .filter_map(|name| {
+                let digest = self.bs.events.get(&name).and_then(|i| i.write_digest)?;
+                Some((name, digest))
+            })
+            .collect();
+
+        record_written_digests(history, written)
     }
 
-    /// Runs the session, generating the desired outputs.
-    ///
-    /// What this does depends on which [`PassSetting`] you asked for. The most common choice is
-    /// `PassSetting::Default`, in which case this method does the following:
-    ///
-    /// - if a `.fmt` file does not yet exist, generate one and cache it
-    /// - run the TeX engine once
-    /// - run BibTeX, if it seems to be required
-    /// - repeat the last two steps as often as needed
-    /// - write the output files to disk, including a Makefile if it was requested.
-    pub fn run(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
-        // Pre-invocation setup that requires cleanup even if the processing errors out.
-
-        let (shell_escape_work, clean_up_shell_escape) = match self.shell_escape_mode {
-            ShellEscapeMode::Disabled => (None, false),
-
-            ShellEscapeMode::ExternallyManagedDir(ref p) => (
-                Some(FilesystemIo::new(p, false, false, HashSet::new())),
-                false,
-            ),
-
-            ShellEscapeMode::TempDir => {
-                let tempdir = ctry!(tempfile::Builder::new().tempdir(); "can't create temporary directory for shell-escape work");
-                (
-                    Some(FilesystemIo::new(
-                        &tempdir.into_path(),
-                        false,
-                        false,
-                        HashSet::new(),
-                    )),
-                    true,
-                )
-            }
-
-            ShellEscapeMode::Defaulted => unreachable!(),
-        };
-
-        self.bs.shell_escape_work = shell_escape_work;
-
-        // Go-time!
-        let result = self.run_inner(status);
-
-        // Do that cleanup.
-
-        if clean_up_shell_escape {
-            let shell_escape_work = self.bs.shell_escape_work.take().unwrap();
-            let shell_escape_err = std::fs::remove_dir_all(shell_escape_work.root());
-
-            if let Err(e) = shell_escape_err {
-                tt_warning!(status, "an error occurred while cleaning up the \
-                    shell-escape temporary directory `{}`", shell_escape_work.root().display(); e.into());
-            }
-        }
-
-        // Propagate the actual result.
-        result
+    /// Get the names of the files that were still changing when the session
+    /// stopped rerunning the TeX engine, either because it reached the
+    /// maximum number of passes or because the files were oscillating. This
+    /// is empty if everything converged.
+    pub fn unconverged_files(&self) -> &[String] {
+        &self.unconverged_files[..]
     }
 
-    /// The bulk of the `run` implementation. We need to wrap it to manage the
-    /// lifecycle of resources like the shell-escape temporary directory, if
-    /// needed.
-    fn run_inner(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
-        // Do we need to generate the format file?
-
-        let generate_format = if self.output_format == OutputFormat::Format {
-            false
-        } else {
-            match self.bs.input_open_format(&self.format_name, status) {
-                OpenResult::Ok(_) => false,
-                OpenResult::NotAvailable => true,
-                OpenResult::Err(e) => {
-                    return Err(e)
-                        .chain_err(|| format!("could not open format file {}", self.format_name));
-                }
-            }
+    /// Figure out how much work can be skipped thanks to the state saved by a
+    /// previous session, if incremental rebuilds are enabled. If processing
+    /// can start with BibTeX, this loads the saved `.aux` files into the
+    /// memory layer.
+    fn plan_incremental(&mut self, status: &mut dyn StatusBackend) -> IncrementalPlan {
+        let setup = match self.incremental {
+            Some(ref s) => s,
+            None => return IncrementalPlan::Rebuild,
         };
 
-        if generate_format {
-            tt_note!(status, "generating format \"{}\"", self.format_name);
-            self.make_format_pass(status)?;
+        // Shell-escape commands could do anything at all.
+        if self.shell_escape_mode != ShellEscapeMode::Disabled {
+            return IncrementalPlan::Rebuild;
         }
 
-        // Do the meat of the work.
-
-        let result = match self.pass {
-            PassSetting::Tex => match self.tex_pass(None, status) {
-                Ok(Some(warnings)) => {
-                    tt_warning!(status, "{}", warnings);
-                    Ok(0)
-                }
-                Ok(None) => Ok(0),
-                Err(e) => Err(e),
-            },
-            PassSetting::Default => self.default_pass(false, status),
-            PassSetting::BibtexFirst => self.default_pass(true, status),
-        };
-
-        if let Err(e) = result {
-            self.write_files(None, status, true)?;
-            return Err(e);
+        let root = match self.output_path {
+            Some(ref p) => p,
+            None => return IncrementalPlan::Rebuild,
         };
 
-        // Write output files and the first line of our Makefile output.
+        let state_path = setup.state_dir.join(BUILD_STATE_FILE);
 
-        let mut mf_dest_maybe = match self.makefile_output_path {
-            Some(ref p) => {
-                if self.output_path.is_none() {
+        let state = match std::fs::read_to_string(&state_path) {
+            Ok(text) => match BuildState::parse(&text) {
+                Some(s) => s,
+                None => {
                     tt_warning!(
                         status,
-                        "requested to generate Makefile rules, but no files written to disk!"
+                        "ignoring malformed incremental build state file `{}`",
+                        state_path.display()
                     );
-                    None
-                } else {
-                    Some(File::create(p)?)
+                    return IncrementalPlan::Rebuild;
                 }
-            }
-
-            None => None,
+            },
+            Err(_) => return IncrementalPlan::Rebuild,
         };
 
-        let n_skipped_intermediates = self.write_files(mf_dest_maybe.as_mut(), status, false)?;
+        if state.config != setup.config {
+            return IncrementalPlan::Rebuild;
+        }
 
-        if n_skipped_intermediates > 0 {
-            status.note_highlighted(
-                "Skipped writing ",
-                &format!("{}", n_skipped_intermediates),
-                " intermediate files (use --keep-intermediates to keep them)",
-            );
+        for (name, digest) in &state.outputs {
+            match std::fs::read(root.join(name)) {
+                Ok(data) if digest_of(&data) == *digest => {}
+                _ => return IncrementalPlan::Rebuild,
+            }
         }
 
-        // Finish Makefile rules, maybe.
+        let mut changed = Vec::new();
 
-        if let Some(ref mut mf_dest) = mf_dest_maybe {
-            ctry!(write!(mf_dest, ": "); "couldn't write to Makefile-rules file");
+        for (name, digest) in &state.inputs {
+            if self.bs.current_filesystem_digest(name, status) != Some(*digest) {
+                changed.push(name);
+            }
+        }
 
-            if let Some(ref pip) = self.primary_input_path {
-                let opip = ctry!(pip.to_str(); "Makefile-rules file path must be Unicode-able");
-                ctry!(mf_dest.write_all(opip.as_bytes()); "couldn't write to Makefile-rules file");
+        if changed.is_empty() {
+            // We'll need to rewrite the side outputs, since build tools like
+            // Make and Ninja may delete them after reading them.
+            let missing_copy = self
+                .side_outputs()
+                .iter()
+                .any(|(_, copy_name)| !setup.state_dir.join(copy_name).exists());
+
+            if missing_copy {
+                return IncrementalPlan::Rebuild;
             }
 
-            // The check above ensures that this is never None.
-            let root = self.output_path.as_ref().unwrap();
+            return IncrementalPlan::UpToDate;
+        }
 
-            for (name, info) in &self.bs.events {
-                if info.input_origin != InputOrigin::Filesystem {
-                    continue;
-                }
+        if self.pass != PassSetting::Default
+            || !self.jobs.is_empty()
+            || state.intermediates.is_empty()
+            || !changed.iter().all(|name| name.ends_with(".bib"))
+        {
+            return IncrementalPlan::Rebuild;
+        }
 
-                if info.got_written_to_disk {
-                    // If the file originally came from the filesystem, and it
-                    // was written as well as read, and we actually wrote it
-                    // to disk, there's a circular dependency that's
-                    // inappropriate to express in a Makefile. If it was
-                    // "written" by the engine but we didn't actually write
-                    // those modifications to disk, we're OK. If there's a
-                    // two-stage compilation involving the .aux file, the
-                    // latter case is what arises unless --keep-intermediates
-                    // is specified.
-                    tt_warning!(status, "omitting circular Makefile dependency for {}", name);
-                    continue;
-                }
+        // Only bibliography databases changed. We can start with BibTeX if we
+        // can get back the .aux files that it needs.
+
+        let int_dir = setup.state_dir.join(BUILD_STATE_INTERMEDIATES_DIR);
+        let mut loaded = Vec::new();
 
-                ctry!(write!(mf_dest, " \\\n  {}", root.join(name).display()); "couldn't write to Makefile-rules file");
+        for name in &state.intermediates {
+            // The state file could have been tampered with, so make sure that
+            // we only read from inside the state directory.
+            if !is_plain_relative_path(name) {
+                return IncrementalPlan::Rebuild;
             }
 
-            ctry!(writeln!(mf_dest, ""); "couldn't write to Makefile-rules file");
+            match std::fs::read(int_dir.join(name)) {
+                Ok(data) => loaded.push((name, data)),
+                Err(_) => return IncrementalPlan::Rebuild,
+            }
         }
 
-        // All done.
+        for (name, data) in loaded {
+            self.bs.mem.create_entry(name, data);
+        }
 
-        Ok(())
+        IncrementalPlan::FromBibtex
     }
 
-    fn write_files(
-        &mut self,
-        mut mf_dest_maybe: Option<&mut File>,
-        status: &mut dyn StatusBackend,
-        only_logs: bool,
-    ) -> Result<u32> {
-        let root = match self.output_path {
-            Some(ref p) => p,
-
-            None => {
-                // We were told not to write anything!
-                return Ok(0);
-            }
+    /// Save the state of this session for use by later incremental rebuilds,
+    /// if they are enabled.
+    fn save_build_state(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let setup = match self.incremental {
+            Some(ref s) => s.clone(),
+            None => return Ok(()),
         };
 
-        let mut n_skipped_intermediates = 0;
+        // We can't track what external tools like biber read, nor what
+        // shell-escape commands do.
+        if self.shell_escape_mode != ShellEscapeMode::Disabled
+            || self.output_path.is_none()
+            || self.passes.iter().any(|p| p.kind == PassKind::ExternalTool)
+        {
+            return Ok(());
+        }
 
-        for (name, file) in &*self.bs.mem.files.borrow() {
-            if name == self.bs.mem.stdout_key() {
-                continue;
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        let mut names: Vec<_> = self.bs.events.keys().cloned().collect();
+        names.sort();
+
+        for name in names {
+            if name.is_empty() {
+                continue; // this is stdout
             }
 
-            let sname = name;
-            let summ = self.bs.events.get_mut(name).unwrap();
+            let summ = &self.bs.events[&name];
 
-            if !only_logs && (self.output_format == OutputFormat::Aux) {
-                // In this mode we're only writing the .aux file. I initially
-                // wanted to be clever-ish and output all auxiliary-type
-                // files, but doing so ended up causing non-obvious problems
-                // for my use case, which involves using Ninja to manage
-                // dependencies.
-                if !sname.ends_with(".aux") {
-                    continue;
+            if summ.got_written_to_disk {
+                if let Some(d) = summ.write_digest {
+                    outputs.push((name, d));
                 }
-            } else if !self.keep_intermediates
-                && (summ.access_pattern != AccessPattern::Written
-                    || ALWAYS_INTERMEDIATE_EXTENSIONS
-                        .iter()
-                        .any(|ext| sname.ends_with(ext)))
-            {
-                n_skipped_intermediates += 1;
                 continue;
             }
 
-            let is_logfile = sname.ends_with(".log") || sname.ends_with(".blg");
+            let is_input = match (summ.access_pattern, summ.input_origin) {
+                (AccessPattern::Read, InputOrigin::Filesystem)
+                | (AccessPattern::ReadThenWritten, InputOrigin::Filesystem) => true,
 
-            if is_logfile && !self.keep_logs {
-                continue;
-            }
+                // This is a file that was looked for, but not found. If it
+                // appears later, things may change.
+                (AccessPattern::Read, InputOrigin::NotInput) => true,
 
-            if !is_logfile && only_logs {
-                continue;
-            }
+                _ => false,
+            };
 
-            if file.data.is_empty() {
-                status.note_highlighted(
-                    "Not writing ",
-                    &format!("`{}`", sname),
-                    ": it would be empty.",
-                );
+            if !is_input {
                 continue;
             }
 
-            let real_path = root.join(name);
-            let byte_len = Byte::from_bytes(file.data.len() as u128);
-            status.note_highlighted(
-                "Writing ",
-                &format!("`{}`", real_path.display()),
-                &format!(" ({})", byte_len.get_appropriate_unit(true)),
-            );
+            // Read digests get cleared when the engine is rerun, so we may
+            // need to go back to the file.
+            let digest = match summ.read_digest {
+                Some(d) => Some(d),
+                None => self.bs.current_filesystem_digest(&name, status),
+            };
 
-            let mut f = File::create(&real_path)?;
-            f.write_all(&file.data)?;
-            summ.got_written_to_disk = true;
+            match digest {
+                Some(d) => inputs.push((name, d)),
 
-            if let Some(ref mut mf_dest) = mf_dest_maybe {
-                // Maybe it'd be better to have this just be a warning? But if
-                // the program is supposed to write the file, you don't want
-                // it exiting with error code zero if it couldn't do that
-                // successfully.
-                //
-                // Not quite sure why, but I can't pull out the target path
-                // here. I think 'self' is borrow inside the loop?
-                ctry!(write!(mf_dest, "{} ", real_path.display()); "couldn't write to Makefile-rules file");
+                // If we can't characterize an input, it's not safe to skip
+                // any work in the next session.
+                None => return Ok(()),
             }
         }
 
-        Ok(n_skipped_intermediates)
-    }
+        let int_dir = setup.state_dir.join(BUILD_STATE_INTERMEDIATES_DIR);
+        let mut intermediates = Vec::new();
+        let mem_files = &*self.bs.mem.files.borrow();
 
-    /// The "default" pass really runs a bunch of sub-passes. It is a "Do What
-    /// I Mean" operation.
-    fn default_pass(&mut self, bibtex_first: bool, status: &mut dyn StatusBackend) -> Result<i32> {
-        // If `bibtex_first` is true, we start by running bibtex, and run
-        // proceed with the standard rerun logic. Otherwise, we run TeX,
-        // auto-detect whether we need to run bibtex, possibly run it, and
-        // then go ahead.
+        // If some .aux file can't be saved inside of the state directory, we
+        // save none of them, so that the next session does a full rebuild.
+        let all_savable = mem_files
+            .keys()
+            .filter(|name| name.ends_with(".aux"))
+            .all(|name| is_plain_relative_path(name));
 
-        let mut warnings = None;
-        let mut rerun_result = if bibtex_first {
-            self.bibtex_pass(status)?;
-            Some(RerunReason::Bibtex)
-        } else {
-            warnings = self.tex_pass(None, status)?;
-            let maybe_biber = self.check_biber_requirement()?;
+        for (name, file) in mem_files {
+            if !all_savable || !name.ends_with(".aux") {
+                continue;
+            }
 
-            if let Some(biber) = maybe_biber {
-                self.bs.external_tool_pass(&biber, status)?;
-                Some(RerunReason::Biber)
-            } else if self.is_bibtex_needed() {
-                self.bibtex_pass(status)?;
-                Some(RerunReason::Bibtex)
-            } else {
-                self.is_rerun_needed(status)
+            let path = int_dir.join(name);
+
+            if let Some(parent) = path.parent() {
+                ctry!(std::fs::create_dir_all(parent); "couldn't create directory `{}`", parent.display());
             }
-        };
 
-        // Now we enter the main rerun loop.
+            ctry!(std::fs::write(&path, &file.data); "couldn't write `{}`", path.display());
+            intermediates.push(name.clone());
+        }
+
+        intermediates.sort();
 
-        let (pass_count, reruns_fixed) = match self.tex_rerun_specification {
-            Some(n) => (n, true),
-            None => (DEFAULT_MAX_TEX_PASSES, false),
+        let state = BuildState {
+            config: setup.config,
+            inputs,
+            outputs,
+            intermediates,
         };
 
-        for i in 0..pass_count {
-            let rerun_explanation = if reruns_fixed {
-                "I was told to".to_owned()
-            } else {
-                match rerun_result {
-                    Some(RerunReason::Biber) => "biber was run".to_owned(),
-                    Some(RerunReason::Bibtex) => "bibtex was run".to_owned(),
-                    Some(RerunReason::FileChange(ref s)) => format!("\"{}\" changed", s),
-                    None => break,
+        // Keep copies of the side outputs, so that an up-to-date session can
+        // write them out again. Copies that weren't made this time around
+        // would be out of date.
+        let side_outputs = self.side_outputs();
+
+        for copy_name in &[BUILD_STATE_MAKEFILE_RULES, BUILD_STATE_DEPENDENCY_MANIFEST] {
+            let copy_path = setup.state_dir.join(copy_name);
+
+            // The dependency manifest isn't written if support for it wasn't
+            // compiled in.
+            match side_outputs
+                .iter()
+                .find(|(path, n)| n == copy_name && path.exists())
+            {
+                Some((path, _)) => {
+                    ctry!(std::fs::copy(path, &copy_path); "couldn't copy `{}` to `{}`", path.display(), copy_path.display());
                 }
-            };
 
-            // We're restarting the engine afresh, so clear the read inputs.
-            // We do *not* clear the entire HashMap since we want to remember,
-            // e.g., that bibtex wrote out the .bbl file, since that way we
-            // can later know that it's OK to delete. I am not super confident
-            // that the access_pattern data can just be left as-is when we do
-            // this, but, uh, so far it seems to work.
-            for summ in self.bs.events.values_mut() {
-                summ.read_digest = None;
+                None => {
+                    if copy_path.exists() {
+                        ctry!(std::fs::remove_file(&copy_path); "couldn't remove `{}`", copy_path.display());
+                    }
+                }
             }
+        }
 
-            warnings = self.tex_pass(Some(&rerun_explanation), status)?;
+        let state_path = setup.state_dir.join(BUILD_STATE_FILE);
+        let f = ctry!(File::create(&state_path); "couldn't create `{}`", state_path.display());
+        ctry!(state.write(f); "couldn't write `{}`", state_path.display());
+        Ok(())
+    }
 
-            if !reruns_fixed {
-                rerun_result = self.is_rerun_needed(status);
+    /// The files describing the dependencies of the outputs that this session
+    /// has been asked to write, along with the names under which copies of
+    /// them are kept in the incremental-build state directory.
+    fn side_outputs(&self) -> Vec<(PathBuf, &'static str)> {
+        let mut result = Vec::new();
 
-                if rerun_result.is_some() && i == DEFAULT_MAX_TEX_PASSES - 1 {
-                    tt_warning!(
-                        status,
-                        "TeX rerun seems needed, but stopping at {} passes",
-                        DEFAULT_MAX_TEX_PASSES
-                    );
-                    break;
-                }
+        if self.output_path.is_some() {
+            if let Some(ref p) = self.makefile_output_path {
+                result.push((p.clone(), BUILD_STATE_MAKEFILE_RULES));
             }
         }
 
-        // The last tex pass generated warnings.
-        if let Some(warnings) = warnings {
-            tt_warning!(status, "{}", warnings);
+        if let Some(ref p) = self.dependency_manifest_path {
+            result.push((p.clone(), BUILD_STATE_DEPENDENCY_MANIFEST));
         }
 
-        // And finally, xdvipdfmx or spx2html. Maybe.
+        result
+    }
 
-        if let OutputFormat::Pdf = self.output_format {
-            self.xdvipdfmx_pass(status)?;
-        } else if let OutputFormat::Html = self.output_format {
-            self.spx2html_pass(status)?;
+    /// Write out the side outputs of an up-to-date session, using the copies
+    /// saved by the previous session.
+    fn restore_side_outputs(&self) -> Result<()> {
+        let setup = match self.incremental {
+            Some(ref s) => s,
+            None => return Ok(()),
+        };
+
+        for (path, copy_name) in self.side_outputs() {
+            let copy_path = setup.state_dir.join(copy_name);
+            ctry!(std::fs::copy(&copy_path, &path); "couldn't copy `{}` to `{}`", copy_path.display(), path.display());
         }
 
-        Ok(0)
+        Ok(())
     }
 
-    fn is_bibtex_needed(&self) -> bool {
-        const BIBDATA: &[u8] = b"\\bibdata";
+    /// Load the intermediate files saved by a previous session into the
+    /// memory layer, if reusing them is enabled. Files that are already in
+    /// the memory layer are left alone.
+    fn load_intermediates(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let cache_dir = match self.intermediates_cache_dir {
+            Some(ref d) => d,
+            None => return Ok(()),
+        };
 
-        self.bs
-            .mem
-            .files
-            .borrow()
-            .get(&self.tex_aux_path)
-            .map(|file| {
-                // We used to use aho-corasick crate here, but it was removed to reduce the code
-                // size.
-                file.data.windows(BIBDATA.len()).any(|s| s == BIBDATA)
-            })
-            .unwrap_or(false)
+        let manifest_path = cache_dir.join(INTERMEDIATES_MANIFEST_FILE);
+
+        let manifest = match std::fs::read_to_string(&manifest_path) {
+            Ok(text) => text,
+            Err(_) => return Ok(()),
+        };
+
+        // Until this session succeeds, the saved files can't be trusted.
+        let stale_path = cache_dir.join(INTERMEDIATES_STALE_FILE);
+        let mut stale = read_name_list(&stale_path);
+        stale.extend(manifest.lines().map(|l| l.to_owned()));
+        ctry!(write_name_list(&stale_path, &stale); "couldn't write `{}`", stale_path.display());
+        ctry!(std::fs::remove_file(&manifest_path); "couldn't remove `{}`", manifest_path.display());
+
+        let mut n_loaded = 0;
+
+        for name in manifest.lines() {
+            if name.is_empty()
+                || escapes_directory(name)
+                || self.bs.mem.files.borrow().contains_key(name)
+            {
+                continue;
+            }
+
+            let path = cache_dir.join(name);
+
+            match std::fs::read(&path) {
+                Ok(data) => {
+                    self.bs.mem.create_entry(name, data);
+                    n_loaded += 1;
+                }
+
+                Err(e) => {
+                    tt_warning!(status, "couldn't read saved intermediate file `{}`", path.display(); e.into());
+                }
+            }
+        }
+
+        if n_loaded > 0 {
+            tt_note!(
+                status,
+                "reusing {} intermediate files from the previous run",
+                n_loaded
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Save the intermediate files of this session for reuse by the next
+    /// one, if that is enabled. These are the files that the engines both
+    /// wrote and read back, other than the main XDV or SPX output.
+    fn save_intermediates(&mut self) -> Result<()> {
+        let cache_dir = match self.intermediates_cache_dir {
+            Some(ref d) => d,
+            None => return Ok(()),
+        };
+
+        ctry!(std::fs::create_dir_all(cache_dir); "couldn't create directory `{}`", cache_dir.display());
+
+        let mem_files = self.bs.mem.files.borrow();
+        let mut saved = Vec::new();
+
+        for (name, file) in &*mem_files {
+            if name == self.bs.mem.stdout_key()
+                || *name == self.tex_xdv_path
+                || !is_plain_relative_path(name)
+            {
+                continue;
+            }
+
+            let is_intermediate = match self.bs.events.get(name) {
+                Some(summ) => matches!(
+                    summ.access_pattern,
+                    AccessPattern::ReadThenWritten | AccessPattern::WrittenThenRead
+                ),
+                None => false,
+            };
+
+            if is_intermediate {
+                saved.push((name.clone(), &file.data));
+            }
+        }
+
+        saved.sort();
+        let names: Vec<_> = saved.iter().map(|(name, _)| name.clone()).collect();
+
+        // The files that earlier sessions saved. We only ever delete files
+        // listed here, since the directory might contain other things.
+        let manifest_path = cache_dir.join(INTERMEDIATES_MANIFEST_FILE);
+        let stale_path = cache_dir.join(INTERMEDIATES_STALE_FILE);
+        let mut old_names = read_name_list(&stale_path);
+        old_names.extend(read_name_list(&manifest_path));
+
+        // Before touching anything, make sure that everything we might leave
+        // behind is listed, in case we don't get to finish.
+        let mut all_names = old_names.clone();
+        all_names.extend(names.iter().cloned());
+        ctry!(write_name_list(&stale_path, &all_names); "couldn't write `{}`", stale_path.display());
+
+        if manifest_path.exists() {
+            ctry!(std::fs::remove_file(&manifest_path); "couldn't remove `{}`", manifest_path.display());
+        }
+
+        for (name, data) in &saved {
+            let path = cache_dir.join(name);
+
+            if let Some(parent) = path.parent() {
+                ctry!(std::fs::create_dir_all(parent); "couldn't create directory `{}`", parent.display());
+            }
+
+            ctry!(std::fs::write(&path, data); "couldn't write `{}`", path.display());
+        }
+
+        // Get rid of the files saved by earlier sessions that weren't
+        // produced this time around, so that they don't pile up.
+        let kept: HashSet<_> = names.iter().map(|n| normalize_name(n)).collect();
+
+        for name in &old_names {
+            let rel = normalize_name(name);
+
+            if !is_plain_relative_path(name) || kept.contains(&rel) {
+                continue;
+            }
+
+            let path = cache_dir.join(&rel);
+
+            if path.exists() {
+                ctry!(std::fs::remove_file(&path); "couldn't remove `{}`", path.display());
+            }
+
+            // Tidy up the subdirectories that this leaves empty, if any.
+            for dir in rel.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() || std::fs::remove_dir(cache_dir.join(dir)).is_err() {
+                    break;
+                }
+            }
+        }
+
+        ctry!(write_name_list(&manifest_path, &names); "couldn't write `{}`", manifest_path.display());
+        ctry!(std::fs::remove_file(&stale_path); "couldn't remove `{}`", stale_path.display());
+        Ok(())
+    }
+
+    /// Record that a pass has finished, for the build report.
+    fn record_pass(
+        &mut self,
+        kind: PassKind,
+        detail: Option<String>,
+        rerun_reason: Option<RerunReason>,
+        started: Instant,
+        outcome: PassOutcome,
+    ) {
+        self.passes.push(PassReport {
+            kind,
+            detail,
+            rerun_reason,
+            outcome,
+            elapsed_secs: started.elapsed().as_secs_f64(),
+            diagnostics: Vec::new(),
+        });
+    }
+
+    /// Get the diagnostics found in the log file of the most recent TeX pass.
+    ///
+    /// These are extracted from the log with [`texlog::parse_log`], so that
+    /// errors and warnings can be shown in detail even if the log file isn't
+    /// saved. The diagnostics of earlier passes are available in the
+    /// [`BuildReport`].
+    pub fn log_diagnostics(&self) -> &[LogDiagnostic] {
+        self.passes
+            .iter()
+            .rev()
+            .find(|p| p.kind == PassKind::Tex)
+            .map(|p| &p.diagnostics[..])
+            .unwrap_or(&[])
+    }
+
+    /// Get the undefined references and citations found in the log file of
+    /// the most recent TeX pass.
+    pub fn undefined_references(&self) -> Vec<&LogDiagnostic> {
+        self.log_diagnostics()
+            .iter()
+            .filter(|d| d.is_undefined_reference())
+            .collect()
+    }
+
+    /// Get the SyncTeX data written by the most recent TeX pass, if SyncTeX
+    /// was enabled with [`ProcessingSessionBuilder::synctex`].
+    pub fn synctex(&self) -> Result<Option<SyncTex>> {
+        let name = Path::new(&self.tex_aux_path).with_extension("synctex.gz");
+        let name = name.to_string_lossy();
+
+        match self.bs.mem.files.borrow().get(&*name) {
+            Some(file) => Ok(Some(ctry!(
+                SyncTex::parse(&file.data);
+                "couldn't parse the SyncTeX data in `{}`", name
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the HTML files written by the spx2html pass. This is empty unless
+    /// the session produced HTML output.
+    pub fn html_output_files(&self) -> &[HtmlOutputFile] {
+        &self.html_output_files[..]
+    }
+
+    /// Get a mapping between source lines and the HTML files written by the
+    /// spx2html pass. Returns None unless the session produced HTML output
+    /// with SyncTeX enabled.
+    pub fn html_sync_map(&self) -> Result<Option<HtmlSyncMap>> {
+        if self.html_output_files.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(self
+            .synctex()?
+            .map(|st| HtmlSyncMap::new(st, self.html_output_files.clone())))
+    }
+
+    /// Parse the log file written by the TeX pass that was just recorded.
+    fn capture_log_diagnostics(&mut self) {
+        let log_name = Path::new(&self.tex_aux_path).with_extension("log");
+        let log_name = log_name.display().to_string();
+
+        let diagnostics = match self.bs.mem.files.borrow().get(&log_name) {
+            Some(file) => texlog::parse_log(&String::from_utf8_lossy(&file.data)),
+            None => return,
+        };
+
+        if let Some(pass) = self.passes.last_mut() {
+            pass.diagnostics = diagnostics;
+        }
+    }
+
+    /// Report the diagnostics from the log of the most recent TeX pass.
+    ///
+    /// Box warnings are left out, since there are usually lots of them and
+    /// they're rarely a problem.
+    fn report_log_diagnostics(&self, errors_only: bool, status: &mut dyn StatusBackend) {
+        for diag in self.log_diagnostics() {
+            match diag.kind {
+                DiagnosticKind::Error => tt_error!(status, "{}", diag),
+                DiagnosticKind::OverfullBox | DiagnosticKind::UnderfullBox => {}
+                _ if errors_only => {}
+                _ if diag.is_undefined_reference() && self.fail_on_undefined_references => {
+                    tt_error!(status, "{}", diag)
+                }
+                _ => tt_warning!(status, "{}", diag),
+            }
+        }
+    }
+
+    /// Fail if the most recent TeX pass left references or citations
+    /// undefined, and we've been asked to treat that as an error.
+    fn check_undefined_references(&self) -> Result<()> {
+        if !self.fail_on_undefined_references {
+            return Ok(());
+        }
+
+        let n = self.undefined_references().len();
+
+        if n > 0 {
+            return Err(errmsg!(
+                "the document has {} undefined reference{} or citation{}",
+                n,
+                if n == 1 { "" } else { "s" },
+                if n == 1 { "" } else { "s" }
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get a machine-readable report of the processing that has been done so
+    /// far.
+    ///
+    /// After [`Self::run`] returns, this describes the whole session,
+    /// including the files that were written to disk.
+    pub fn build_report(&self) -> BuildReport {
+        BuildReport {
+            passes: self.passes.clone(),
+            files: self
+                .bs
+                .events
+                .iter()
+                .map(|(name, summ)| (name.clone(), summ.into()))
+                .collect(),
+            unconverged_files: self.unconverged_files.clone(),
+        }
+    }
+
+    /// Write out the JSON build report, if one was requested.
+    fn write_report(&self, status: &mut dyn StatusBackend) -> Result<()> {
+        let path = match self.report_output_path {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+
+        #[cfg(feature = "serialization")]
+        {
+            status.note_highlighted(
+                "Writing ",
+                &format!("`{}`", path.display()),
+                " (build report)",
+            );
+            let f =
+                ctry!(File::create(path); "couldn't create build report file `{}`", path.display());
+            ctry!(self.build_report().write_json(f); "couldn't write build report file `{}`", path.display());
+        }
+
+        #[cfg(not(feature = "serialization"))]
+        tt_warning!(
+            status,
+            "not writing build report `{}`: this build of Tectonic lacks the \"serialization\" feature",
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Get a list of the inputs that were read during processing so far.
+    ///
+    /// Digests that aren't known because the engines were rerun are filled
+    /// in by reading the files again.
+    pub fn dependency_manifest(&mut self, status: &mut dyn StatusBackend) -> DependencyManifest {
+        let mut inputs = vec![ManifestInput {
+            name: self
+                .primary_input_path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+            source: InputSource::Primary,
+            path: self.primary_input_path.clone(),
+            digest: self.primary_input_digest,
+        }];
+
+        for job in &self.jobs {
+            inputs.push(ManifestInput {
+                name: job
+                    .primary_input_path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| job.primary_input_tex_path.clone()),
+                source: InputSource::Primary,
+                path: job.primary_input_path.clone(),
+                digest: job.primary_input_digest,
+            });
+        }
+
+        let mut names: Vec<_> = self.bs.events.keys().cloned().collect();
+        names.sort();
+
+        for name in names {
+            let summ = &self.bs.events[&name];
+
+            match summ.access_pattern {
+                AccessPattern::Read | AccessPattern::ReadThenWritten => {}
+                _ => continue,
+            }
+
+            let source = match summ.input_origin {
+                InputOrigin::NotInput => continue,
+                InputOrigin::Filesystem => InputSource::Filesystem,
+                InputOrigin::Other if summ.from_bundle => InputSource::Bundle,
+                InputOrigin::Other => InputSource::Other,
+            };
+
+            let path = summ.abspath.clone();
+
+            let digest = match (summ.read_digest, source) {
+                (Some(d), _) => Some(d),
+                (None, InputSource::Filesystem) => self.bs.current_filesystem_digest(&name, status),
+                (None, InputSource::Bundle) => self.bs.current_bundle_digest(&name, status),
+                (None, _) => None,
+            };
+
+            inputs.push(ManifestInput {
+                name,
+                source,
+                path,
+                digest,
+            });
+        }
+
+        DependencyManifest {
+            bundle_digest: self.bundle_digest,
+            inputs,
+        }
+    }
+
+    /// Write out the JSON dependency manifest, if one was requested.
+    fn write_dependency_manifest(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let path = match self.dependency_manifest_path {
+            Some(ref p) => p.clone(),
+            None => return Ok(()),
+        };
+
+        #[cfg(feature = "serialization")]
+        {
+            let manifest = self.dependency_manifest(status);
+            status.note_highlighted(
+                "Writing ",
+                &format!("`{}`", path.display()),
+                " (dependency manifest)",
+            );
+            let f = ctry!(File::create(&path); "couldn't create dependency manifest file `{}`", path.display());
+            ctry!(manifest.write_json(f); "couldn't write dependency manifest file `{}`", path.display());
+        }
+
+        #[cfg(not(feature = "serialization"))]
+        tt_warning!(
+            status,
+            "not writing dependency manifest `{}`: this build of Tectonic lacks the \"serialization\" feature",
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Runs the session, generating the desired outputs.
+    ///
+    /// What this does depends on which [`PassSetting`] you asked for. The most common choice is
+    /// `PassSetting::Default`, in which case this method does the following:
+    ///
+    /// - if a `.fmt` file does not yet exist, generate one and cache it
+    /// - run the TeX engine once
+    /// - run BibTeX, if it seems to be required
+    /// - repeat the last two steps as often as needed
+    /// - convert the TeX output to the final output format
+    /// - write the output files to disk, including a Makefile if it was requested.
+    ///
+    /// Passes added with [`ProcessingSessionBuilder::add_pass`] are run at the
+    /// points described in the documentation of [`PassPhase`].
+    pub fn run(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        self.deadline = self.timeout.map(|t| Instant::now() + t);
+
+        // Pre-invocation setup that requires cleanup even if the processing errors out.
+
+        let (shell_escape_work, clean_up_shell_escape) = match self.shell_escape_mode {
+            ShellEscapeMode::Disabled => (None, false),
+
+            ShellEscapeMode::ExternallyManagedDir(ref p) => (
+                Some(FilesystemIo::new(p, false, false, HashSet::new())),
+                false,
+            ),
+
+            ShellEscapeMode::TempDir => {
+                let tempdir = ctry!(tempfile::Builder::new().tempdir(); "can't create temporary directory for shell-escape work");
+                (
+                    Some(FilesystemIo::new(
+                        &tempdir.into_path(),
+                        false,
+                        false,
+                        HashSet::new(),
+                    )),
+                    true,
+                )
+            }
+
+            ShellEscapeMode::Defaulted => unreachable!(),
+        };
+
+        self.bs.shell_escape_work = shell_escape_work;
+
+        // Go-time!
+        let result = self.run_inner(status);
+        let report_result = self.write_report(status);
+
+        // Do that cleanup.
+
+        if clean_up_shell_escape {
+            let shell_escape_work = self.bs.shell_escape_work.take().unwrap();
+            let shell_escape_err = std::fs::remove_dir_all(shell_escape_work.root());
+
+            if let Err(e) = shell_escape_err {
+                tt_warning!(status, "an error occurred while cleaning up the \
+                    shell-escape temporary directory `{}`", shell_escape_work.root().display(); e.into());
+            }
+        }
+
+        // Propagate the actual result. A processing error takes precedence
+        // over a failure to write the report.
+        result.and(report_result)
+    }
+
+    /// The bulk of the `run` implementation. We need to wrap it to manage the
+    /// lifecycle of resources like the shell-escape temporary directory, if
+    /// needed.
+    fn run_inner(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        // Can we skip some or all of the work?
+
+        let plan = self.plan_incremental(status);
+
+        if plan == IncrementalPlan::UpToDate {
+            status.note_highlighted(
+                "Skipping ",
+                "processing",
+                ": no inputs have changed since the last run",
+            );
+            return self.restore_side_outputs();
+        }
+
+        if let Some(ref setup) = self.incremental {
+            // Until this session succeeds, the saved state can't be trusted.
+            let state_path = setup.state_dir.join(BUILD_STATE_FILE);
+
+            if state_path.exists() {
+                ctry!(std::fs::remove_file(&state_path); "couldn't remove `{}`", state_path.display());
+            }
+
+            ctry!(std::fs::create_dir_all(&setup.state_dir); "couldn't create directory `{}`", setup.state_dir.display());
+        }
+
+        // Do we need to generate the format file?
+
+        let generate_format = if self.output_format == OutputFormat::Format {
+            false
+        } else {
+            match self.bs.input_open_format(&self.format_name, status) {
+                OpenResult::Ok(_) => false,
+                OpenResult::NotAvailable => true,
+                OpenResult::Err(e) => {
+                    return Err(e)
+                        .chain_err(|| format!("could not open format file {}", self.format_name));
+                }
+            }
+        };
+
+        if generate_format {
+            tt_note!(status, "generating format \"{}\"", self.format_name);
+            self.make_format_pass(status)?;
+        }
+
+        // Bring back the intermediates from last time, maybe. This has to
+        // happen after any format generation, which clears the memory layer.
+
+        if self.output_format != OutputFormat::Format {
+            self.load_intermediates(status)?;
+        }
+
+        // Do the meat of the work.
+
+        let result = self.run_jobs(plan, status);
+
+        if let Err(e) = result {
+            self.write_files(None, status, true)?;
+            return Err(e);
+        };
+
+        // Write output files and the first line of our Makefile output.
+
+        let mut mf_dest_maybe = match self.makefile_output_path {
+            Some(ref p) => {
+                if self.output_path.is_none() {
+                    tt_warning!(
+                        status,
+                        "requested to generate Makefile rules, but no files written to disk!"
+                    );
+                    None
+                } else {
+                    Some(File::create(p)?)
+                }
+            }
+
+            None => None,
+        };
+
+        let n_skipped_intermediates = self.write_files(mf_dest_maybe.as_mut(), status, false)?;
+        self.write_main_output_to_stream(status)?;
+
+        if n_skipped_intermediates > 0 {
+            status.note_highlighted(
+                "Skipped writing ",
+                &format!("{}", n_skipped_intermediates),
+                " intermediate files (use --keep-intermediates to keep them)",
+            );
+        }
+
+        // Finish Makefile rules, maybe.
+
+        if let Some(ref mut mf_dest) = mf_dest_maybe {
+            // The engine reports absolute paths, so make ours absolute too,
+            // to avoid listing the same file twice.
+            let mut deps = Vec::new();
+
+            if let Some(ref pip) = self.primary_input_path {
+                deps.push(absolute_path(pip));
+            }
+
+            for job in &self.jobs {
+                if let Some(ref pip) = job.primary_input_path {
+                    let pip = absolute_path(pip);
+
+                    if !deps.contains(&pip) {
+                        deps.push(pip);
+                    }
+                }
+            }
+
+            let mut names: Vec<_> = self.bs.events.keys().collect();
+            names.sort();
+
+            for name in names {
+                let info = &self.bs.events[name];
+
+                let path = match info.abspath {
+                    Some(ref p) => p,
+                    None => continue,
+                };
+
+                match info.input_origin {
+                    InputOrigin::Filesystem => {}
+                    InputOrigin::Other if !self.makefile_exclude_bundle_files => {}
+                    _ => continue,
+                }
+
+                if info.got_written_to_disk {
+                    // If the file originally came from the filesystem, and it
+                    // was written as well as read, and we actually wrote it
+                    // to disk, there's a circular dependency that's
+                    // inappropriate to express in a Makefile. If it was
+                    // "written" by the engine but we didn't actually write
+                    // those modifications to disk, we're OK. If there's a
+                    // two-stage compilation involving the .aux file, the
+                    // latter case is what arises unless --keep-intermediates
+                    // is specified.
+                    tt_warning!(status, "omitting circular Makefile dependency for {}", name);
+                    continue;
+                }
+
+                let path = absolute_path(path);
+
+                if !deps.contains(&path) {
+                    deps.push(path);
+                }
+            }
+
+            ctry!(write!(mf_dest, ":"); "couldn't write to Makefile-rules file");
+
+            for dep in &deps {
+                ctry!(write!(mf_dest, " \\\n  {}", makefile_escape(dep)); "couldn't write to Makefile-rules file");
+            }
+
+            ctry!(writeln!(mf_dest); "couldn't write to Makefile-rules file");
+
+            // Phony targets, so that deleting a prerequisite doesn't break
+            // the build.
+
+            for dep in &deps {
+                ctry!(write!(mf_dest, "\n{}:\n", makefile_escape(dep)); "couldn't write to Makefile-rules file");
+            }
+        }
+
+        self.write_dependency_manifest(status)?;
+
+        // Save state for incremental rebuilds, maybe. This is a nice-to-have,
+        // so don't make a fuss if it doesn't work out.
+
+        if let Err(e) = self.save_build_state(status) {
+            tt_warning!(status, "couldn't save the state for incremental rebuilds"; SyncError::new(e).into());
+        }
+
+        if let Err(e) = self.save_intermediates() {
+            tt_warning!(status, "couldn't save intermediate files for reuse"; SyncError::new(e).into());
+        }
+
+        // All done.
+
+        Ok(())
+    }
+
+    /// Process the main job, according to the incremental-build plan, and
+    /// then the additional jobs, if there are any.
+    fn run_jobs(&mut self, plan: IncrementalPlan, status: &mut dyn StatusBackend) -> Result<()> {
+        if self.jobs.is_empty() {
+            return self.run_job(plan, status);
+        }
+
+        let mut jobs = std::mem::take(&mut self.jobs);
+        let result = self.run_all_jobs(plan, &mut jobs, status);
+
+        for job in &mut jobs {
+            merge_events(&mut self.bs.events, std::mem::take(&mut job.events));
+        }
+
+        self.jobs = jobs;
+        result
+    }
+
+    /// Process the main job and then the additional *jobs*, in order, and
+    /// then process them again as needed.
+    fn run_all_jobs(
+        &mut self,
+        plan: IncrementalPlan,
+        jobs: &mut [Job],
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        self.note_job(None, status);
+        self.run_job(plan, status)?;
+
+        for job in jobs.iter_mut() {
+            self.run_swapped_job(job, None, status)?;
+        }
+
+        // A job that read the `.aux` file of another job that has changed
+        // since, as happens with the `xr` package, needs to be processed
+        // again to pick up the changes.
+
+        let aux_names: Vec<_> = std::iter::once(self.tex_aux_path.clone())
+            .chain(jobs.iter().map(|j| j.tex_aux_path.clone()))
+            .collect();
+
+        for _ in 1..MAX_JOB_ROUNDS {
+            let mut reran = false;
+
+            if let Some(name) = self.stale_job_input(&self.bs.events, &aux_names) {
+                self.note_job(Some(&name), status);
+                self.clear_read_digests();
+                self.run_job(IncrementalPlan::Rebuild, status)?;
+                reran = true;
+            }
+
+            for job in jobs.iter_mut() {
+                if let Some(name) = self.stale_job_input(&job.events, &aux_names) {
+                    self.run_swapped_job(job, Some(&name), status)?;
+                    reran = true;
+                }
+            }
+
+            if !reran {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Process an additional job by swapping its state in, running it, and
+    /// swapping its state back out again.
+    fn run_swapped_job(
+        &mut self,
+        job: &mut Job,
+        changed: Option<&str>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        self.swap_job(job);
+        self.note_job(changed, status);
+        self.clear_read_digests();
+        let result = self.run_job(IncrementalPlan::Rebuild, status);
+        self.swap_job(job);
+        result
+    }
+
+    /// Exchange the state of the job that is swapped in with that of *job*.
+    fn swap_job(&mut self, job: &mut Job) {
+        std::mem::swap(&mut self.bs.primary_input, &mut job.primary_input);
+        std::mem::swap(&mut self.bs.events, &mut job.events);
+        std::mem::swap(&mut self.primary_input_path, &mut job.primary_input_path);
+        std::mem::swap(
+            &mut self.primary_input_tex_path,
+            &mut job.primary_input_tex_path,
+        );
+        std::mem::swap(
+            &mut self.primary_input_digest,
+            &mut job.primary_input_digest,
+        );
+        std::mem::swap(&mut self.tex_aux_path, &mut job.tex_aux_path);
+        std::mem::swap(&mut self.tex_xdv_path, &mut job.tex_xdv_path);
+        std::mem::swap(&mut self.tex_pdf_path, &mut job.tex_pdf_path);
+    }
+
+    /// Announce that the job whose state is currently swapped in is about to
+    /// be processed, maybe again because the named file changed.
+    fn note_job(&self, changed: Option<&str>, status: &mut dyn StatusBackend) {
+        match changed {
+            Some(name) => status.note_highlighted(
+                "Reprocessing ",
+                &format!("`{}`", self.primary_input_tex_path),
+                &format!(" because `{}` changed ...", name),
+            ),
+            None => status.note_highlighted(
+                "Processing ",
+                &format!("`{}`", self.primary_input_tex_path),
+                " ...",
+            ),
+        }
+    }
+
+    /// Find the first of the named `.aux` files that, according to *events*,
+    /// a job read with different contents than it has now. Files that the
+    /// job wrote, such as its own `.aux` file, are taken care of by the usual
+    /// rerun logic, so they're skipped.
+    fn stale_job_input(
+        &self,
+        events: &HashMap<String, FileSummary>,
+        aux_names: &[String],
+    ) -> Option<String> {
+        let files = self.bs.mem.files.borrow();
+
+        for name in aux_names {
+            let summ = match events.get(name) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            if summ.access_pattern != AccessPattern::Read {
+                continue;
+            }
+
+            let current = files
+                .get(name)
+                .map(|f| digest_of(&f.data))
+                .unwrap_or_else(DigestData::of_nothing);
+
+            if summ.read_digest != Some(current) {
+                return Some(name.clone());
+            }
+        }
+
+        None
+    }
+
+    /// Run the engines for the job whose state is currently swapped in.
+    fn run_job(&mut self, plan: IncrementalPlan, status: &mut dyn StatusBackend) -> Result<()> {
+        match self.pass {
+            PassSetting::Tex => match self.tex_pass(None, status) {
+                Ok(Some(warnings)) => {
+                    self.report_log_diagnostics(false, status);
+                    tt_warning!(status, "{}", warnings);
+                    self.check_undefined_references()
+                }
+                Ok(None) => {
+                    self.report_log_diagnostics(false, status);
+                    self.check_undefined_references()
+                }
+                Err(e) => Err(e),
+            },
+            PassSetting::Default => {
+                if plan == IncrementalPlan::FromBibtex {
+                    tt_note!(
+                        status,
+                        "only bibliography files have changed; starting with BibTeX"
+                    );
+                }
+
+                self.default_pass(plan == IncrementalPlan::FromBibtex, status)
+                    .map(|_| ())
+            }
+            PassSetting::BibtexFirst => self.default_pass(true, status).map(|_| ()),
+        }
+    }
+
+    fn write_files(
+        &mut self,
+        mut mf_dest_maybe: Option<&mut File>,
+        status: &mut dyn StatusBackend,
+        only_logs: bool,
+    ) -> Result<u32> {
+        // If processing failed, we might have been asked to put the logs
+        // somewhere else, so that they don't clobber those of the last good
+        // build.
+        let failed_logs_dir = if only_logs {
+            self.failed_logs_dir.as_ref()
+        } else {
+            None
+        };
+
+        let root = match (failed_logs_dir, self.output_path.as_ref()) {
+            (Some(p), _) => {
+                ctry!(std::fs::create_dir_all(p); "couldn't create directory `{}`", p.display());
+                p
+            }
+
+            (None, Some(p)) => p,
+
+            (None, None) => {
+                // We were told not to write anything!
+                return Ok(0);
+            }
+        };
+
+        let mut n_skipped_intermediates = 0;
+
+        for (name, file) in &*self.bs.mem.files.borrow() {
+            if name == self.bs.mem.stdout_key() {
+                continue;
+            }
+
+            let sname = name;
+
+            // Files saved by a previous session might never have been touched.
+            let summ = match self.bs.events.get_mut(name) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            if !only_logs && (self.output_format == OutputFormat::Aux) {
+                // In this mode we're only writing the .aux file. I initially
+                // wanted to be clever-ish and output all auxiliary-type
+                // files, but doing so ended up causing non-obvious problems
+                // for my use case, which involves using Ninja to manage
+                // dependencies.
+                if !sname.ends_with(".aux") {
+                    continue;
+                }
+            } else if !self.keep_intermediates
+                && (summ.access_pattern != AccessPattern::Written
+                    || ALWAYS_INTERMEDIATE_EXTENSIONS
+                        .iter()
+                        .any(|ext| sname.ends_with(ext)))
+            {
+                n_skipped_intermediates += 1;
+                continue;
+            }
+
+            let is_logfile =
+                sname.ends_with(".log") || sname.ends_with(".blg") || sname.ends_with(".ilg");
+
+            if is_logfile && !self.keep_logs && failed_logs_dir.is_none() {
+                continue;
+            }
+
+            if !is_logfile && only_logs {
+                continue;
+            }
+
+            if file.data.is_empty() {
+                status.note_highlighted(
+                    "Not writing ",
+                    &format!("`{}`", sname),
+                    ": it would be empty.",
+                );
+                continue;
+            }
+
+            if !self.security.allow_writes_outside_output_dir() && escapes_directory(name) {
+                tt_warning!(
+                    status,
+                    "not writing `{}`: it would land outside of the output directory",
+                    sname
+                );
+                continue;
+            }
+
+            let real_path = root.join(name);
+            let byte_len = Byte::from_bytes(file.data.len() as u128);
+            status.note_highlighted(
+                "Writing ",
+                &format!("`{}`", real_path.display()),
+                &format!(" ({})", byte_len.get_appropriate_unit(true)),
+            );
+
+            ctry!(write_output_atomically(&real_path, &file.data); "couldn't write `{}`", real_path.display());
+            summ.got_written_to_disk = true;
+
+            if let Some(ref mut mf_dest) = mf_dest_maybe {
+                // Maybe it'd be better to have this just be a warning? But if
+                // the program is supposed to write the file, you don't want
+                // it exiting with error code zero if it couldn't do that
+                // successfully.
+                //
+                // Not quite sure why, but I can't pull out the target path
+                // here. I think 'self' is borrow inside the loop?
+                ctry!(write!(mf_dest, "{} ", makefile_escape(&real_path)); "couldn't write to Makefile-rules file");
+            }
+        }
+
+        // Outputs that were streamed to disk just need to be moved into place.
+
+        if let Some(ref mut streaming) = self.bs.streaming {
+            for name in streaming.written_names() {
+                if only_logs {
+                    streaming.discard(&name);
+                    continue;
+                }
+
+                let summ = match self.bs.events.get_mut(&name) {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                let size = ctry!(streaming.size(&name); "couldn't check the size of `{}`", name);
+
+                if size == 0 {
+                    status.note_highlighted(
+                        "Not writing ",
+                        &format!("`{}`", name),
+                        ": it would be empty.",
+                    );
+                    streaming.discard(&name);
+                    continue;
+                }
+
+                let real_path = root.join(&name);
+                let byte_len = Byte::from_bytes(size as u128);
+                status.note_highlighted(
+                    "Writing ",
+                    &format!("`{}`", real_path.display()),
+                    &format!(" ({})", byte_len.get_appropriate_unit(true)),
+                );
+
+                ctry!(streaming.persist(&name, &real_path); "couldn't write `{}`", real_path.display());
+                summ.got_written_to_disk = true;
+
+                if let Some(ref mut mf_dest) = mf_dest_maybe {
+                    ctry!(write!(mf_dest, "{} ", makefile_escape(&real_path)); "couldn't write to Makefile-rules file");
+                }
+            }
+        }
+
+        Ok(n_skipped_intermediates)
+    }
+
+    /// Write the main output file to the caller's stream, if there is one.
+    fn write_main_output_to_stream(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let dest = match self.output_writer {
+            Some(ref mut w) => w,
+            None => return Ok(()),
+        };
+
+        let name = if self.output_format == OutputFormat::Xdv {
+            &self.tex_xdv_path
+        } else {
+            &self.tex_pdf_path
+        };
+
+        let files = self.bs.mem.files.borrow();
+
+        let file = match files.get(name) {
+            Some(f) => f,
+            None => return Err(errmsg!("no `{}` was produced to write to the stream", name)),
+        };
+
+        let byte_len = Byte::from_bytes(file.data.len() as u128);
+        status.note_highlighted(
+            "Writing ",
+            &format!("`{}`", name),
+            &format!(
+                " to the output stream ({})",
+                byte_len.get_appropriate_unit(true)
+            ),
+        );
+
+        ctry!(dest.write_all(&file.data); "couldn't write `{}` to the output stream", name);
+        ctry!(dest.flush(); "couldn't write `{}` to the output stream", name);
+        Ok(())
+    }
+
+    /// The "default" pass really runs a bunch of sub-passes. It is a "Do What
+    /// I Mean" operation.
+    fn default_pass(&mut self, bibtex_first: bool, status: &mut dyn StatusBackend) -> Result<i32> {
+        // If `bibtex_first` is true, we start by running bibtex, and run
+        // proceed with the standard rerun logic. Otherwise, we run TeX,
+        // auto-detect whether we need to run bibtex, possibly run it, and
+        // then go ahead.
+
+        let mut bibliography = BibliographyPass::new(bibtex_first, self.external_tools.clone());
+        let mut index = MakeindexPass::new(self.index_style.clone());
+        self.run_custom_passes(PassPhase::BeforeTex, status)?;
+
+        let mut warnings = None;
+        let mut rerun_result = if bibtex_first {
+            self.run_pass(&mut bibliography, status)?
+        } else {
+            warnings = self.tex_pass(None, status)?;
+            self.after_tex_passes(&mut bibliography, &mut index, status)?
+        };
+
+        // Now we enter the main rerun loop.
+
+        if let Some(n) = self.tex_rerun_specification {
+            for _ in 0..n {
+                self.clear_read_digests();
+                warnings = self.tex_pass(Some(RerunReason::Requested), status)?;
+                self.after_tex_passes(&mut bibliography, &mut index, status)?;
+            }
+        } else {
+            let max_passes = self.rerun_policy.max_passes;
+            let mut n_tex_passes = if bibtex_first { 0 } else { 1 };
+            let mut history = HashMap::new();
+
+            if self.rerun_policy.detect_oscillation {
+                self.find_oscillation(&mut history, status);
+            }
+
+            while let Some(rerun_reason) = rerun_result.take() {
+                if n_tex_passes >= max_passes {
+                    tt_warning!(
+                        status,
+                        "TeX rerun seems needed, but stopping at {} passes",
+                        max_passes
+                    );
+                    self.unconverged_files = self.changed_files(status);
+                    break;
+                }
+
+                self.clear_read_digests();
+                warnings = self.tex_pass(Some(rerun_reason), status)?;
+                n_tex_passes += 1;
+                rerun_result = self.after_tex_passes(&mut bibliography, &mut index, status)?;
+
+                if !self.rerun_policy.detect_oscillation {
+                    continue;
+                }
+
+                if let Some(name) = self.find_oscillation(&mut history, status) {
+                    tt_warning!(
+                        status,
+                        "`{}` keeps changing back to contents from an earlier pass, so \
+                         rerunning TeX won't help; stopping at {} passes",
+                        name,
+                        n_tex_passes
+                    );
+                    self.unconverged_files = self.changed_files(status);
+                    break;
+                }
+            }
+
+            if !self.unconverged_files.is_empty() {
+                tt_warning!(
+                    status,
+                    "these files never converged: {}",
+                    self.unconverged_files.join(", ")
+                );
+            }
+        }
+
+        // Report what the last TeX pass complained about.
+        self.report_log_diagnostics(false, status);
+
+        if let Some(warnings) = warnings {
+            tt_warning!(status, "{}", warnings);
+        }
+
+        self.check_undefined_references()?;
+
+        // And finally, xdvipdfmx or spx2html. Maybe.
+
+        self.run_custom_passes(PassPhase::BeforeOutput, status)?;
+
+        if let OutputFormat::Pdf = self.output_format {
+            self.run_pass(&mut XdvipdfmxPass, status)?;
+        } else if let OutputFormat::Html = self.output_format {
+            self.run_pass(&mut Spx2HtmlPass, status)?;
+        }
+
+        self.run_custom_passes(PassPhase::AfterOutput, status)?;
+        Ok(0)
+    }
+
+    /// We're restarting the engine afresh, so clear the read inputs. We do
+    /// *not* clear the entire HashMap since we want to remember, e.g., that
+    /// bibtex wrote out the .bbl file, since that way we can later know that
+    /// it's OK to delete. I am not super confident that the access_pattern
+    /// data can just be left as-is when we do this, but, uh, so far it seems
+    /// to work.
+    fn clear_read_digests(&mut self) {
+        for summ in self.bs.events.values_mut() {
+            summ.read_digest = None;
+        }
+    }
+
+    /// Run the passes that follow a TeX pass, then figure out whether TeX
+    /// needs to be rerun.
+    fn after_tex_passes(
+        &mut self,
+        bibliography: &mut BibliographyPass,
+        index: &mut MakeindexPass,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<RerunReason>> {
+        // The makeindex pass doesn't force a rerun: if the `.ind` file
+        // changed, the usual checks will notice.
+        let mut forced = self.run_pass(bibliography, status)?;
+
+        for mut pass in std::mem::take(&mut bibliography.logreq_passes) {
+            forced = forced.or(self.run_pass(&mut pass, status)?);
+        }
+
+        self.run_pass(index, status)?;
+        let custom_forced = self.run_custom_passes(PassPhase::AfterTex, status)?;
+
+        match forced.or(custom_forced) {
+            Some(r) => Ok(Some(r)),
+            None => Ok(self.is_rerun_needed(status)),
+        }
+    }
+
+    /// Run the application-supplied passes for one phase of the pipeline.
+    /// Returns the first reason that one of them gave for rerunning TeX.
+    fn run_custom_passes(
+        &mut self,
+        phase: PassPhase,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<RerunReason>> {
+        // Take the passes out of `self` so that we can lend `self` to them.
+        let mut custom_passes = std::mem::take(&mut self.custom_passes);
+        let mut result = Ok(None);
+
+        for (pass_phase, pass) in &mut custom_passes {
+            if *pass_phase != phase {
+                continue;
+            }
+
+            match self.run_pass(pass.as_mut(), status) {
+                Ok(r) => {
+                    if let Ok(ref mut reason @ None) = result {
+                        *reason = r;
+                    }
+                }
+
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.custom_passes = custom_passes;
+        result
+    }
+
+    /// Run one pass of the pipeline, if it's needed, recording it in the
+    /// build report. Returns the pass's reason for rerunning TeX, if it ran
+    /// and gave one.
+    fn run_pass(
+        &mut self,
+        pass: &mut dyn Pass,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<RerunReason>> {
+        let mut ctx = PassContext {
+            bs: &mut self.bs,
+            security: &self.security,
+            primary_input_tex_path: &self.primary_input_tex_path,
+            tex_aux_path: &self.tex_aux_path,
+            tex_xdv_path: &self.tex_xdv_path,
+            tex_pdf_path: &self.tex_pdf_path,
+            output_path: self.output_path.as_deref(),
+            output_format: self.output_format,
+            build_date: self.build_date,
+            reproducible: self.reproducible,
+            cancellation: self.cancellation.clone(),
+            deadline: self.deadline,
+            unstables: &self.unstables,
+            html_output_files: &mut self.html_output_files,
+        };
+
+        if !pass.is_needed(&mut ctx, status)? {
+            return Ok(None);
+        }
+
+        check_cancelled(ctx.cancellation.as_ref(), ctx.deadline)?;
+        let started = Instant::now();
+        let result = pass.run(&mut ctx, status);
+
+        let outcome = match result {
+            Ok(o) => o,
+            Err(_) => PassOutcome::Failed,
+        };
+        self.record_pass(pass.kind(), Some(pass.name()), None, started, outcome);
+        result?;
+
+        Ok(pass.forces_rerun())
     }
 
     /// Use the TeX engine to generate a format file.
@@ -1708,13 +4443,23 @@ impl ProcessingSession {
             ))
             .into()
         });
-        let stem = r?;
+        let stem = r?.to_owned();
+        let started = Instant::now();
 
         let result = {
-            self.bs
-                .enter_format_mode(&format!("tectonic-format-{}.tex", stem));
+            match self.format_source {
+                Some(ref source) => self.bs.enter_custom_format_mode(source),
+                None => self
+                    .bs
+                    .enter_format_mode(&format!("tectonic-format-{}.tex", stem)),
+            }
+
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
+            launcher
+                .fixed_input_mtime(fixed_input_mtime(self.reproducible, self.build_date))
+                .cancellation_token(self.cancellation.clone())
+                .deadline(self.deadline);
             let r = TexEngine::default()
                 .halt_on_error_mode(true)
                 .initex_mode(true)
@@ -1724,6 +4469,18 @@ impl ProcessingSession {
             r
         };
 
+        let outcome = match result {
+            Ok(ref o) => (*o).into(),
+            Err(_) => PassOutcome::Failed,
+        };
+        self.record_pass(
+            PassKind::Format,
+            Some(self.format_name.clone()),
+            None,
+            started,
+            outcome,
+        );
+
         match result {
             Ok(TexOutcome::Spotless) => {}
             Ok(TexOutcome::Warnings) => {
@@ -1753,364 +4510,1037 @@ impl ProcessingSession {
                 continue;
             }
 
-            // Note that we intentionally pass 'stem', not 'name'.
-            ctry!(self.bs.format_cache.write_format(stem, &file.data, status); "cannot write format file {}", sname);
+            // Note that we intentionally pass 'stem', not 'name'.
+            ctry!(self.bs.format_cache.write_format(&stem, &file.data, status); "cannot write format file {}", sname);
+        }
+
+        // All done. Clear the memory layer since this was a special preparatory step.
+        self.bs.mem.files.borrow_mut().clear();
+
+        Ok(0)
+    }
+
+    /// Run one pass of the TeX engine.
+    fn tex_pass(
+        &mut self,
+        rerun_reason: Option<RerunReason>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<&'static str>> {
+        check_cancelled(self.cancellation.as_ref(), self.deadline)?;
+        let started = Instant::now();
+
+        let result = {
+            if let Some(ref r) = rerun_reason {
+                status.note_highlighted(
+                    "Rerunning ",
+                    "TeX",
+                    &format!(" because {} ...", r.explanation()),
+                );
+            } else {
+                status.note_highlighted("Running ", "TeX", " ...");
+            }
+
+            let mut launcher =
+                CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
+            launcher
+                .fixed_input_mtime(fixed_input_mtime(self.reproducible, self.build_date))
+                .cancellation_token(self.cancellation.clone())
+                .deadline(self.deadline);
+
+            TexEngine::default()
+                .halt_on_error_mode(true)
+                .initex_mode(self.output_format == OutputFormat::Format)
+                .synctex(self.synctex_enabled)
+                .semantic_pagination(self.output_format == OutputFormat::Html)
+                .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
+                .build_date(self.build_date)
+                .utc_dates(self.reproducible)
+                .process(
+                    &mut launcher,
+                    &self.format_name,
+                    &self.primary_input_tex_path,
+                )
+        };
+
+        let outcome = match result {
+            Ok(o) => o,
+            Err(e) => {
+                self.record_pass(
+                    PassKind::Tex,
+                    None,
+                    rerun_reason,
+                    started,
+                    PassOutcome::Failed,
+                );
+                self.capture_log_diagnostics();
+                self.report_log_diagnostics(true, status);
+                return Err(e.into());
+            }
+        };
+
+        self.record_pass(PassKind::Tex, None, rerun_reason, started, outcome.into());
+        self.capture_log_diagnostics();
+
+        let warnings = match outcome {
+            TexOutcome::Spotless => None,
+            TexOutcome::Warnings =>
+                    Some("warnings were issued by the TeX engine; use --print and/or --keep-logs for details."),
+            TexOutcome::Errors =>
+                    Some("errors were issued by the TeX engine, but were ignored; \
+                         use --print and/or --keep-logs for details."),
+        };
+
+        let streamed = self
+            .bs
+            .streaming
+            .as_ref()
+            .map(|s| s.contains(&self.tex_xdv_path))
+            .unwrap_or(false);
+
+        if !streamed && !self.bs.mem.files.borrow().contains_key(&self.tex_xdv_path) {
+            // TeX did not produce the expected output file
+            tt_warning!(
+                status,
+                "did not produce \"{}\"; this may mean that your document is empty",
+                self.tex_xdv_path
+            )
+        }
+
+        Ok(warnings)
+    }
+
+    /// Get what was printed to standard output, if anything.
+    pub fn get_stdout_content(&self) -> Vec<u8> {
+        self.bs
+            .mem
+            .files
+            .borrow()
+            .get(self.bs.mem.stdout_key())
+            .map(|mfi| mfi.data.clone())
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Consume this session and return the current set of files in memory.
+    ///
+    /// This convenience function tries to help with the annoyances of getting
+    /// access to the in-memory file data after the engine has been run.
+    pub fn into_file_data(self) -> MemoryFileCollection {
+        Rc::try_unwrap(self.bs.mem.files)
+            .expect("multiple strong refs to MemoryIo files")
+            .into_inner()
+    }
+}
+
+/// Helpers for the built-in passes.
+impl<'a> PassContext<'a> {
+    fn is_bibtex_needed(&self) -> bool {
+        const BIBDATA: &[u8] = b"\\bibdata";
+
+        self.bs
+            .mem
+            .files
+            .borrow()
+            .get(self.tex_aux_path)
+            .map(|file| {
+                // We used to use aho-corasick crate here, but it was removed to reduce the code
+                // size.
+                file.data.windows(BIBDATA.len()).any(|s| s == BIBDATA)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Run BibTeX on one `.aux` file.
+    fn bibtex_pass_for_one_aux_file(
+        &mut self,
+        status: &mut dyn StatusBackend,
+        aux_file: &str,
+    ) -> Result<PassOutcome> {
+        let result = {
+            status.note_highlighted("Running ", "BibTeX", &format!(" on {} ...", aux_file));
+            let unstables = self.unstables;
+            let mut launcher = self.launcher(status);
+            let mut engine = BibtexEngine::new();
+            engine.process(&mut launcher, aux_file, unstables)
+        };
+
+        let outcome = match result {
+            Ok(o) => o,
+            Err(e) => {
+                return Err(e.chain_err(|| ErrorKind::EngineError("BibTeX")));
+            }
+        };
+
+        match outcome {
+            TexOutcome::Spotless => {}
+            TexOutcome::Warnings => {
+                tt_note!(
+                    status,
+                    "warnings were issued by BibTeX; use --print and/or --keep-logs for details."
+                );
+            }
+            TexOutcome::Errors => {
+                tt_warning!(
+                    status,
+                    "errors were issued by BibTeX, but were ignored; \
+                     use --print and/or --keep-logs for details."
+                );
+            }
+        }
+
+        Ok(outcome.into())
+    }
+
+    /// Run BibTeX on the main `.aux` file and any others generated by TeX.
+    fn bibtex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<PassOutcome> {
+        let mut aux_files = vec![self.tex_aux_path.to_owned()];
+
+        // find other .aux files generated by tex_pass
+        for f in self.bs.get_intermediate_file_names() {
+            if f.ends_with(".aux") && f != self.tex_aux_path {
+                aux_files.push(f);
+            }
+        }
+
+        let mut outcome = PassOutcome::Spotless;
+
+        for f in aux_files {
+            outcome = outcome.max(self.bibtex_pass_for_one_aux_file(status, &f)?);
+        }
+
+        Ok(outcome)
+    }
+
+    /// Read the requests that the `logreq` package left in the `.run.xml`
+    /// file, if there is one. Only the active, external requests are
+    /// returned, highest priority first.
+    fn logreq_requests(&self, status: &mut dyn StatusBackend) -> Result<Vec<logreq::Request>> {
+        let mut run_xml_path = PathBuf::from(&self.primary_input_tex_path);
+        run_xml_path.set_extension("run.xml");
+        let run_xml_path = run_xml_path.display().to_string();
+
+        let mem_files = &*self.bs.mem.files.borrow();
+        let run_xml_entry = match mem_files.get(&run_xml_path) {
+            Some(e) => e,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut requests: Vec<_> = logreq::parse_run_xml(&run_xml_entry.data, status)?
+            .into_iter()
+            .filter(|r| r.kind == logreq::RequestKind::External && r.active)
+            .collect();
+
+        // The sort is stable, so requests with equal priorities stay in the
+        // order that they were written.
+        requests.sort_by_key(|r| std::cmp::Reverse(r.priority));
+        Ok(requests)
+    }
+}
+
+/// Does this program produce bibliographies?
+fn is_bibliography_tool(program: &str) -> bool {
+    matches!(program, "biber" | "bibtex" | "bibtex8" | "bibtexu")
+}
+
+/// Add an extension to a filename if it doesn't already have one. `logreq`
+/// requests often name their input files without extensions.
+fn with_default_extension(name: &str, ext: &str) -> String {
+    if Path::new(name).extension().is_some() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", name, ext)
+    }
+}
+
+/// Fail if processing has been cancelled, or has run past its deadline.
+fn check_cancelled(
+    cancellation: Option<&CancellationToken>,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    if let Some(token) = cancellation {
+        if token.is_cancelled() {
+            return Err(errmsg!("processing was cancelled"));
+        }
+    }
+
+    if let Some(deadline) = deadline {
+        if Instant::now() >= deadline {
+            return Err(errmsg!("processing timed out"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the names of the `.aux`, `.xdv` (or `.spx`), and `.pdf` files that TeX
+/// produces from the named input.
+fn job_file_names(tex_input_name: &str, output_format: OutputFormat) -> (String, String, String) {
+    let mut aux_path = PathBuf::from(tex_input_name);
+    aux_path.set_extension("aux");
+    let mut xdv_path = aux_path.clone();
+    xdv_path.set_extension(if output_format == OutputFormat::Html {
+        "spx"
+    } else {
+        "xdv"
+    });
+    let mut pdf_path = aux_path.clone();
+    pdf_path.set_extension("pdf");
+
+    (
+        aux_path.display().to_string(),
+        xdv_path.display().to_string(),
+        pdf_path.display().to_string(),
+    )
+}
+
+/// Fold the I/O events of one job into those of another.
+fn merge_events(into: &mut HashMap<String, FileSummary>, from: HashMap<String, FileSummary>) {
+    for (name, summ) in from {
+        let cur = match into.get_mut(&name) {
+            Some(c) => c,
+            None => {
+                into.insert(name, summ);
+                continue;
+            }
+        };
+
+        cur.access_pattern = match (cur.access_pattern, summ.access_pattern) {
+            (a, b) if a == b => a,
+            (AccessPattern::Written, AccessPattern::Read) => AccessPattern::WrittenThenRead,
+            _ => AccessPattern::ReadThenWritten,
+        };
+
+        if cur.input_origin == InputOrigin::NotInput {
+            cur.input_origin = summ.input_origin;
+        }
+
+        cur.read_digest = cur.read_digest.or(summ.read_digest);
+        cur.write_digest = summ.write_digest.or(cur.write_digest);
+        cur.abspath = cur.abspath.take().or(summ.abspath);
+        cur.from_bundle |= summ.from_bundle;
+        cur.got_written_to_disk |= summ.got_written_to_disk;
+    }
+}
+
+/// Remember the contents written to each of the changed files in one TeX
+/// pass, returning the first file whose contents were already written in an
+/// earlier pass, if any.
+fn record_written_digests(
+    history: &mut HashMap<String, Vec<DigestData>>,
+    written: Vec<(String, DigestData)>,
+) -> Option<String> {
+    let mut oscillating = None;
+
+    for (name, digest) in written {
+        let seen = history.entry(name.clone()).or_default();
+
+        if seen.contains(&digest) && oscillating.is_none() {
+            oscillating = Some(name);
+        }
+
+        seen.push(digest);
+    }
+
+    oscillating
+}
+
+/// Get the build date for reproducible builds from the `SOURCE_DATE_EPOCH`
+/// environment variable, falling back to the Unix epoch.
+fn source_date_epoch(status: &mut dyn StatusBackend) -> Result<SystemTime> {
+    let text = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(t) => t,
+        Err(_) => {
+            tt_note!(
+                status,
+                "SOURCE_DATE_EPOCH is not set; using the Unix epoch as the build date"
+            );
+            return Ok(SystemTime::UNIX_EPOCH);
+        }
+    };
+
+    let secs = match text.trim().parse::<u64>() {
+        Ok(n) => n,
+        Err(_) => {
+            return Err(errmsg!(
+                "invalid SOURCE_DATE_EPOCH `{}`: it must be a nonnegative integer",
+                text
+            ));
+        }
+    };
+
+    SystemTime::UNIX_EPOCH
+        .checked_add(std::time::Duration::from_secs(secs))
+        .ok_or_else(|| errmsg!("SOURCE_DATE_EPOCH `{}` is too far in the future", text))
+}
+
+/// Get the modification time that the engines should see for every input
+/// file, if it should be fixed.
+fn fixed_input_mtime(reproducible: bool, build_date: SystemTime) -> Option<i64> {
+    if !reproducible {
+        return None;
+    }
+
+    build_date
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+/// Check whether a file name, taken relative to some directory, points outside
+/// of that directory. This is a purely textual check.
+fn escapes_directory(name: &str) -> bool {
+    let mut depth = 0usize;
+
+    for c in Path::new(name).components() {
+        match c {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return true;
+                }
+
+                depth -= 1;
+            }
+            Component::RootDir | Component::Prefix(_) => return true,
+        }
+    }
+
+    false
+}
+
+/// Read a file listing one file name per line. Returns an empty list if the
+/// file can't be read.
+fn read_name_list(path: &Path) -> Vec<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => text
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Write a file listing one file name per line, without duplicates.
+fn write_name_list(path: &Path, names: &[String]) -> std::io::Result<()> {
+    let mut names: Vec<_> = names.iter().collect();
+    names.sort();
+    names.dedup();
+
+    let mut text = String::new();
+
+    for name in names {
+        text.push_str(name);
+        text.push('\n');
+    }
+
+    std::fs::write(path, text)
+}
+
+/// Drop the `.` components of a relative file name, so that different
+/// spellings of the same name compare equal.
+fn normalize_name(name: &str) -> PathBuf {
+    Path::new(name)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Check whether a file name is a relative path without any `..` components,
+/// so that it can safely be joined onto a directory that we control.
+fn is_plain_relative_path(name: &str) -> bool {
+    let path = Path::new(name);
+
+    !name.is_empty()
+        && !path.is_absolute()
+        && !path.has_root()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Make a path absolute, relative to the current directory, and resolve its
+/// `.` and `..` components lexically. If the current directory can't be
+/// determined, the path is only normalized.
+fn absolute_path(path: &Path) -> PathBuf {
+    let joined = match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_owned(),
+    };
+
+    let mut result = PathBuf::new();
+
+    for c in joined.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            c => result.push(c.as_os_str()),
+        }
+    }
+
+    result
+}
+
+/// Format a path for use in a Makefile rule, escaping the characters that
+/// `make` would otherwise treat specially.
+fn makefile_escape(path: &Path) -> String {
+    let mut escaped = String::new();
+
+    for c in path.display().to_string().chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// The built-in pass that runs BibTeX after the first TeX pass, if it looks
+/// like the document needs it.
+///
+/// If the document asked for tools to be run through the `logreq` package,
+/// this pass also sets up a [`LogreqPass`] for each request, to be run right
+/// after it. In that case, the `logreq` requests take care of the
+/// bibliography, if there is one.
+struct BibliographyPass {
+    /// If true, run BibTeX unconditionally. This is used to run BibTeX before
+    /// the first TeX pass.
+    bibtex_first: bool,
+
+    /// The external programs that `logreq` requests may run.
+    allowed_tools: HashSet<String>,
+
+    /// The passes for the `logreq` requests, which are taken and run by the
+    /// session.
+    logreq_passes: Vec<LogreqPass>,
+
+    /// Whether the need for this pass has been checked yet. We only check
+    /// once.
+    checked: bool,
+
+    /// Whether TeX read the main `.bbl` file before BibTeX ran, as happens
+    /// when it was saved by a previous session. If so, the usual checks will
+    /// notice if BibTeX changes it, and there's no need to force a rerun.
+    bbl_was_read: bool,
+}
+
+impl BibliographyPass {
+    fn new(bibtex_first: bool, allowed_tools: HashSet<String>) -> Self {
+        BibliographyPass {
+            bibtex_first,
+            allowed_tools,
+            logreq_passes: Vec::new(),
+            checked: false,
+            bbl_was_read: false,
         }
+    }
+}
 
-        // All done. Clear the memory layer since this was a special preparatory step.
-        self.bs.mem.files.borrow_mut().clear();
+impl Pass for BibliographyPass {
+    fn name(&self) -> String {
+        "bibtex".to_owned()
+    }
 
-        Ok(0)
+    fn kind(&self) -> PassKind {
+        PassKind::Bibtex
     }
 
-    /// Run one pass of the TeX engine.
-    fn tex_pass(
+    fn is_needed(
         &mut self,
-        rerun_explanation: Option<&str>,
+        ctx: &mut PassContext<'_>,
         status: &mut dyn StatusBackend,
-    ) -> Result<Option<&'static str>> {
-        let result = {
-            if let Some(s) = rerun_explanation {
-                status.note_highlighted("Rerunning ", "TeX", &format!(" because {} ...", s));
-            } else {
-                status.note_highlighted("Running ", "TeX", " ...");
-            }
+    ) -> Result<bool> {
+        if self.checked {
+            return Ok(false);
+        }
 
-            let mut launcher =
-                CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
+        self.checked = true;
 
-            TexEngine::default()
-                .halt_on_error_mode(true)
-                .initex_mode(self.output_format == OutputFormat::Format)
-                .synctex(self.synctex_enabled)
-                .semantic_pagination(self.output_format == OutputFormat::Html)
-                .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
-                .build_date(self.build_date)
-                .process(
-                    &mut launcher,
-                    &self.format_name,
-                    &self.primary_input_tex_path,
-                )
-        };
+        if self.bibtex_first {
+            return Ok(true);
+        }
 
-        let warnings = match result {
-            Ok(TexOutcome::Spotless) => None,
-            Ok(TexOutcome::Warnings) =>
-                    Some("warnings were issued by the TeX engine; use --print and/or --keep-logs for details."),
-            Ok(TexOutcome::Errors) =>
-                    Some("errors were issued by the TeX engine, but were ignored; \
-                         use --print and/or --keep-logs for details."),
-            Err(e) =>
-                return Err(e.into()),
-        };
+        let mut bbl_path = PathBuf::from(ctx.tex_aux_path);
+        bbl_path.set_extension("bbl");
+        self.bbl_was_read = ctx
+            .bs
+            .events
+            .get(&bbl_path.display().to_string())
+            .and_then(|summ| summ.read_digest)
+            .is_some();
 
-        if !self.bs.mem.files.borrow().contains_key(&self.tex_xdv_path) {
-            // TeX did not produce the expected output file
-            tt_warning!(
-                status,
-                "did not produce \"{}\"; this may mean that your document is empty",
-                self.tex_xdv_path
-            )
+        let mut handles_bibliography = false;
+
+        for request in ctx.logreq_requests(status)? {
+            handles_bibliography |=
+                is_bibliography_tool(request.program()) || is_bibliography_tool(&request.generic);
+
+            let package = request.package.clone();
+            let program = request.program().to_owned();
+
+            match LogreqPass::new(request, &self.allowed_tools) {
+                Some(pass) => self.logreq_passes.push(pass),
+
+                None => {
+                    tt_warning!(
+                        status,
+                        "ignoring the request from the `{}` package to run `{}`, \
+                         which is not an allowed tool",
+                        package,
+                        program
+                    );
+                }
+            }
         }
 
-        Ok(warnings)
+        Ok(!handles_bibliography && ctx.is_bibtex_needed())
     }
 
-    // Run Bibtex process for one .aux file.
-    fn bibtex_pass_for_one_aux_file(
+    fn run(
         &mut self,
+        ctx: &mut PassContext<'_>,
         status: &mut dyn StatusBackend,
-        aux_file: &String,
-    ) -> Result<i32> {
-        let result = {
-            status.note_highlighted("Running ", "BibTeX", &format!(" on {} ...", aux_file));
-            let mut launcher =
-                CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
-            let mut engine = BibtexEngine::new();
-            engine.process(&mut launcher, aux_file, &self.unstables)
-        };
+    ) -> Result<PassOutcome> {
+        ctx.bibtex_pass(status)
+    }
 
-        match result {
-            Ok(TexOutcome::Spotless) => {}
-            Ok(TexOutcome::Warnings) => {
-                tt_note!(
-                    status,
-                    "warnings were issued by BibTeX; use --print and/or --keep-logs for details."
-                );
-            }
-            Ok(TexOutcome::Errors) => {
-                tt_warning!(
-                    status,
-                    "errors were issued by BibTeX, but were ignored; \
-                     use --print and/or --keep-logs for details."
-                );
-            }
-            Err(e) => {
-                return Err(e.chain_err(|| ErrorKind::EngineError("BibTeX")));
-            }
+    fn forces_rerun(&self) -> Option<RerunReason> {
+        if self.bbl_was_read {
+            return None;
         }
 
-        Ok(0)
+        Some(RerunReason::Bibtex)
     }
+}
 
-    fn bibtex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
-        let mut aux_files = vec![self.tex_aux_path.clone()];
+/// How to carry out a `logreq` request.
+#[derive(Debug)]
+enum LogreqTool {
+    /// Run the built-in BibTeX engine on the named `.aux` file.
+    Bibtex(String),
 
-        // find other .aux files generated by tex_pass
-        for f in self.bs.get_intermediate_file_names() {
-            if f.ends_with(".aux") && f != self.tex_aux_path {
-                aux_files.push(f);
+    /// Run the built-in makeindex engine on the named `.idx` file, with the
+    /// named style file if one was given.
+    Makeindex(String, Option<String>),
+
+    /// Run an external program.
+    External(ExternalToolPass),
+}
+
+/// A built-in pass that carries out one request from the `.run.xml` file
+/// written by the `logreq` package.
+struct LogreqPass {
+    request: logreq::Request,
+    tool: LogreqTool,
+}
+
+impl LogreqPass {
+    /// Figure out how to carry out a request. Programs that have built-in
+    /// equivalents use those. Other programs are only run if they are in the
+    /// list of allowed tools.
+    fn new(request: logreq::Request, allowed_tools: &HashSet<String>) -> Option<Self> {
+        let program = request.program().to_owned();
+        let infile = request.infile().unwrap_or_default();
+
+        let tool = match program.as_str() {
+            "bibtex" | "bibtex8" | "bibtexu" => {
+                LogreqTool::Bibtex(with_default_extension(infile, "aux"))
             }
-        }
 
-        for f in aux_files {
-            let r = self.bibtex_pass_for_one_aux_file(status, &f);
-            if let Err(e) = r {
-                return Err(e);
+            "makeindex" => {
+                let mut style = None;
+                let mut options = request.arguments.iter().filter_map(|a| match a {
+                    logreq::Argument::Option(o) => Some(o.trim()),
+                    _ => None,
+                });
+
+                while let Some(o) = options.next() {
+                    if o == "-s" {
+                        style = options.next().map(|s| s.to_owned());
+                    } else if let Some(s) = o.strip_prefix("-s ") {
+                        style = Some(s.trim().to_owned());
+                    }
+                }
+
+                LogreqTool::Makeindex(with_default_extension(infile, "idx"), style)
             }
-        }
 
-        Ok(0)
-    }
+            _ if allowed_tools.contains(&program) => {
+                // For testing support, we let the rig specify a custom
+                // executable to use for biber, which lets us exercise
+                // different pieces of the external-tool behavior.
 
-    fn xdvipdfmx_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
-        {
-            status.note_highlighted("Running ", "xdvipdfmx", " ...");
+                let s = (
+                    crate::config::is_config_test_mode_activated(),
+                    std::env::var("TECTONIC_TEST_FAKE_BIBER"),
+                );
 
-            let mut launcher =
-                CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
-            let mut engine = XdvipdfmxEngine::default();
+                let mut argv = match s {
+                    (true, Ok(text)) if program == "biber" => {
+                        text.split_whitespace().map(|x| x.to_owned()).collect()
+                    }
+                    _ => vec![program],
+                };
 
-            engine.build_date(self.build_date);
+                argv.extend(request.arguments.iter().map(|a| a.text().to_owned()));
 
-            if let Some(ref ps) = self.unstables.paper_size {
-                engine.paper_spec(ps.clone());
+                LogreqTool::External(ExternalToolPass {
+                    argv,
+                    extra_requires: request.required_files().map(|f| f.to_owned()).collect(),
+                })
             }
 
-            engine.process(&mut launcher, &self.tex_xdv_path, &self.tex_pdf_path)?;
-        }
+            _ => return None,
+        };
 
-        self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
-        Ok(0)
+        Some(LogreqPass { request, tool })
     }
+}
 
-    fn spx2html_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
-        let op = match self.output_path {
-            Some(ref p) => p,
-            None => return Err(errmsg!("HTML output must be saved directly to disk")),
-        };
+impl Pass for LogreqPass {
+    fn name(&self) -> String {
+        self.request.program().to_owned()
+    }
 
-        {
-            let mut engine = Spx2HtmlEngine::default();
-            status.note_highlighted("Running ", "spx2html", " ...");
-            engine.process_to_filesystem(&mut self.bs, status, &self.tex_xdv_path, op)?;
+    fn kind(&self) -> PassKind {
+        match self.tool {
+            LogreqTool::Bibtex(_) => PassKind::Bibtex,
+            LogreqTool::Makeindex(..) => PassKind::Makeindex,
+            LogreqTool::External(_) => PassKind::ExternalTool,
         }
-
-        self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
-        Ok(0)
     }
 
-    /// Get what was printed to standard output, if anything.
-    pub fn get_stdout_content(&self) -> Vec<u8> {
-        self.bs
-            .mem
-            .files
-            .borrow()
-            .get(self.bs.mem.stdout_key())
-            .map(|mfi| mfi.data.clone())
-            .unwrap_or_else(Vec::new)
-    }
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome> {
+        match self.tool {
+            LogreqTool::Bibtex(ref aux) => ctx.bibtex_pass_for_one_aux_file(status, aux),
 
-    /// Consume this session and return the current set of files in memory.
-    ///
-    /// This convenience function tries to help with the annoyances of getting
-    /// access to the in-memory file data after the engine has been run.
-    pub fn into_file_data(self) -> MemoryFileCollection {
-        Rc::try_unwrap(self.bs.mem.files)
-            .expect("multiple strong refs to MemoryIo files")
-            .into_inner()
-    }
+            LogreqTool::Makeindex(ref idx, ref style) => {
+                let mut engine = MakeindexEngine::default();
 
-    /// See if we need to run `biber`, and parse the `.run.xml` file from the
-    /// `loqreq` package to figure out what files `biber` needs. This
-    /// functionality should probably become more generic, but I don't have a
-    /// great sense as to how widely-used `logreq` is.
-    fn check_biber_requirement(&self) -> Result<Option<ExternalToolPass>> {
-        // Is there a `.run.xml` file?
+                if let Some(style) = style {
+                    engine.style(style);
+                }
 
-        let mut run_xml_path = PathBuf::from(&self.primary_input_tex_path);
-        run_xml_path.set_extension("run.xml");
-        let run_xml_path = run_xml_path.display().to_string();
+                status.note_highlighted("Running ", "makeindex", &format!(" on {} ...", idx));
 
-        let mem_files = &*self.bs.mem.files.borrow();
-        let run_xml_entry = match mem_files.get(&run_xml_path) {
-            Some(e) => e,
-            None => return Ok(None),
-        };
+                Ok(match engine.process(ctx.bs, status, idx)? {
+                    MakeindexOutcome::Spotless => PassOutcome::Spotless,
+                    MakeindexOutcome::Warnings => PassOutcome::Warnings,
+                })
+            }
 
-        // Yes, there is. Set up to potentially run biber. For testing support,
-        // we let the rig specify a custom executable to use, which lets us
-        // exercise different pieces of the external-tool behavior.
+            LogreqTool::External(ref tool) => {
+                ctx.bs.external_tool_pass(tool, status)?;
 
-        let s = (
-            crate::config::is_config_test_mode_activated(),
-            std::env::var("TECTONIC_TEST_FAKE_BIBER"),
-        );
+                let mem_files = &*ctx.bs.mem.files.borrow();
 
-        let mut argv = match s {
-            (true, Ok(text)) => text.split_whitespace().map(|x| x.to_owned()).collect(),
-            _ => vec!["biber".to_owned()],
-        };
+                for name in self.request.provided_files() {
+                    if !mem_files.contains_key(name) {
+                        tt_warning!(
+                            status,
+                            "`{}` was expected to create the file `{}`, but it didn’t",
+                            self.request.program(),
+                            name
+                        );
+                    }
+                }
+
+                Ok(PassOutcome::Spotless)
+            }
+        }
+    }
+
+    fn forces_rerun(&self) -> Option<RerunReason> {
+        match self.tool {
+            LogreqTool::Bibtex(_) => Some(RerunReason::Bibtex),
+            // If the index changed, the usual checks will notice.
+            LogreqTool::Makeindex(..) => None,
+            LogreqTool::External(_) if self.request.program() == "biber" => {
+                Some(RerunReason::Biber)
+            }
+            LogreqTool::External(_) => Some(RerunReason::Pass(self.request.program().to_owned())),
+        }
+    }
+}
+
+/// The built-in pass that runs makeindex on the raw index files written by
+/// TeX. An index file is processed whenever its contents have changed since
+/// the last time this pass ran.
+struct MakeindexPass {
+    /// The index style file to use, if any.
+    style: Option<String>,
 
-        let mut extra_requires = HashSet::new();
+    /// The digests of the index files as of the last time they were
+    /// processed.
+    processed: HashMap<String, DigestData>,
 
-        // Do a sketchy XML parse to see if there's info about a biber
-        // invocation.
+    /// The index files that need processing this time around.
+    pending: Vec<String>,
+}
 
-        #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-        enum State {
-            /// Searching for the biber section
-            Searching,
+impl MakeindexPass {
+    fn new(style: Option<String>) -> Self {
+        MakeindexPass {
+            style,
+            processed: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+}
 
-            /// In a <binary> element. Will its value be "biber"??!?
-            InBinaryName,
+impl Pass for MakeindexPass {
+    fn name(&self) -> String {
+        "makeindex".to_owned()
+    }
 
-            /// In the <cmdline> part of the biber section.
-            InBiberCmdline,
+    fn kind(&self) -> PassKind {
+        PassKind::Makeindex
+    }
 
-            /// About to read an argument to the biber command.
-            InBiberArgument,
+    fn is_needed(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<bool> {
+        self.pending.clear();
 
-            /// Reading through the post-cmdline part of the biber section.
-            InBiberRemainder,
+        for (name, file) in &*ctx.bs.mem.files.borrow() {
+            if !name.ends_with(".idx") {
+                continue;
+            }
 
-            /// In a "requirement" section like <input> or <requires> that contains
-            /// filenames we should provide
-            InBiberRequirementSection,
+            let digest = digest_of(&file.data);
 
-            /// In a <file> requirement
-            InBiberFileRequirement,
+            if self.processed.get(name) != Some(&digest) {
+                self.processed.insert(name.clone(), digest);
+                self.pending.push(name.clone());
+            }
         }
 
-        let curs = Cursor::new(&run_xml_entry.data[..]);
-        let mut reader = Reader::from_reader(curs);
-        let mut buf = Vec::new();
-        let mut state = State::Searching;
+        self.pending.sort();
+        Ok(!self.pending.is_empty())
+    }
 
-        loop {
-            let event = ctry!(
-                reader.read_event(&mut buf);
-                "error parsing run.xml file"
-            );
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome> {
+        let mut engine = MakeindexEngine::default();
 
-            if let Event::Eof = event {
-                break;
+        if let Some(ref style) = self.style {
+            engine.style(style);
+        }
+
+        let mut outcome = PassOutcome::Spotless;
+
+        for idx in &self.pending {
+            status.note_highlighted("Running ", "makeindex", &format!(" on {} ...", idx));
+
+            if engine.process(ctx.bs, status, idx)? == MakeindexOutcome::Warnings {
+                tt_note!(
+                    status,
+                    "warnings were issued by makeindex; use --keep-logs for details."
+                );
+                outcome = PassOutcome::Warnings;
             }
+        }
 
-            match (state, event) {
-                (State::Searching, Event::Start(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+        Ok(outcome)
+    }
+}
 
-                    if name == "binary" {
-                        state = State::InBinaryName;
-                    }
-                }
+/// The built-in pass that converts the XDV file to PDF.
+struct XdvipdfmxPass;
 
-                (State::InBinaryName, Event::Text(ref e)) => {
-                    let text = e.unescape_and_decode(&reader)?;
+impl Pass for XdvipdfmxPass {
+    fn name(&self) -> String {
+        "xdvipdfmx".to_owned()
+    }
 
-                    state = if &text == "biber" {
-                        State::InBiberCmdline
-                    } else {
-                        State::Searching
-                    };
-                }
+    fn kind(&self) -> PassKind {
+        PassKind::Xdvipdfmx
+    }
 
-                (State::InBinaryName, _) => {
-                    state = State::Searching;
-                }
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome> {
+        status.note_highlighted("Running ", "xdvipdfmx", " ...");
 
-                (State::InBiberCmdline, Event::Start(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+        let mut engine = XdvipdfmxEngine::default();
 
-                    // Note that the "infile" might be `foo` without the `.bcf`
-                    // extension, so we can't use it for file-finding.
-                    state = match name {
-                        "infile" | "outfile" | "option" => State::InBiberArgument,
-                        _ => State::InBiberRemainder,
-                    }
-                }
+        engine
+            .build_date(ctx.build_date)
+            .enable_deterministic_tags(ctx.reproducible);
 
-                (State::InBiberCmdline, Event::End(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+        if let Some(ref ps) = ctx.unstables.paper_size {
+            engine.paper_spec(ps.clone());
+        }
 
-                    if name == "cmdline" {
-                        state = State::InBiberRemainder;
-                    }
-                }
+        let (xdv_path, pdf_path) = (ctx.tex_xdv_path, ctx.tex_pdf_path);
+        engine.process(&mut ctx.launcher(status), xdv_path, pdf_path)?;
 
-                (State::InBiberArgument, Event::Text(ref e)) => {
-                    argv.push(e.unescape_and_decode(&reader)?);
-                    state = State::InBiberCmdline;
-                }
+        ctx.bs.mem.files.borrow_mut().remove(xdv_path);
+        Ok(PassOutcome::Spotless)
+    }
+}
 
-                (State::InBiberRemainder, Event::Start(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+/// The built-in pass that converts the SPX file to HTML.
+struct Spx2HtmlPass;
 
-                    state = match name {
-                        "input" | "requires" => State::InBiberRequirementSection,
-                        _ => State::InBiberRemainder,
-                    }
-                }
+impl Pass for Spx2HtmlPass {
+    fn name(&self) -> String {
+        "spx2html".to_owned()
+    }
 
-                (State::InBiberRemainder, Event::End(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+    fn kind(&self) -> PassKind {
+        PassKind::Spx2html
+    }
 
-                    if name == "external" {
-                        break;
-                    }
-                }
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome> {
+        let op = match ctx.output_path {
+            Some(p) => p,
+            None => return Err(errmsg!("HTML output must be saved directly to disk")),
+        };
 
-                (State::InBiberRequirementSection, Event::Start(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+        let mut engine = Spx2HtmlEngine::default();
+        status.note_highlighted("Running ", "spx2html", " ...");
+        engine.process_to_filesystem(ctx.bs, status, ctx.tex_xdv_path, op)?;
+        *ctx.html_output_files = engine.output_files().to_vec();
 
-                    state = match name {
-                        "file" => State::InBiberFileRequirement,
-                        _ => State::InBiberRemainder,
-                    }
-                }
+        ctx.bs.mem.files.borrow_mut().remove(ctx.tex_xdv_path);
+        Ok(PassOutcome::Spotless)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_state_roundtrip() {
+        let state = BuildState {
+            config: digest_of(b"config"),
+            inputs: vec![
+                ("main.tex".to_owned(), digest_of(b"hello")),
+                ("with space.bib".to_owned(), DigestData::of_nothing()),
+            ],
+            outputs: vec![("main.pdf".to_owned(), digest_of(b"%PDF"))],
+            intermediates: vec!["main.aux".to_owned()],
+        };
 
-                (State::InBiberRequirementSection, Event::End(ref e)) => {
-                    let name = reader.decode(e.local_name())?;
+        let mut buf = Vec::new();
+        state.write(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(BuildState::parse(&text), Some(state));
+    }
 
-                    if name == "input" || name == "requires" {
-                        state = State::InBiberRemainder;
-                    }
-                }
+    #[test]
+    fn build_state_malformed() {
+        assert_eq!(BuildState::parse(""), None);
+        assert_eq!(BuildState::parse("tectonic-build-state 1\n"), None);
+        assert_eq!(
+            BuildState::parse("tectonic-build-state 1\nconfig zzz\n"),
+            None
+        );
+    }
 
-                (State::InBiberFileRequirement, Event::Text(ref e)) => {
-                    extra_requires.insert(e.unescape_and_decode(&reader)?);
-                    state = State::InBiberRequirementSection;
-                }
+    #[test]
+    fn plain_relative_paths() {
+        assert!(is_plain_relative_path("doc.aux"));
+        assert!(is_plain_relative_path("./chapters/one.aux"));
+        assert!(!is_plain_relative_path(""));
+        assert!(!is_plain_relative_path("/etc/passwd"));
+        assert!(!is_plain_relative_path("../doc.aux"));
+        assert!(!is_plain_relative_path("chapters/../doc.aux"));
+    }
 
-                (State::InBiberFileRequirement, _) => {
-                    state = State::InBiberRequirementSection;
-                }
+    #[test]
+    fn makefile_escaping() {
+        assert_eq!(makefile_escape(Path::new("plain.tex")), "plain.tex");
+        assert_eq!(
+            makefile_escape(Path::new("my doc#1$.tex")),
+            "my\\ doc\\#1$$.tex"
+        );
+    }
 
-                _ => {}
-            }
-        }
+    #[test]
+    fn rerun_policy_ignored_files() {
+        let mut policy = RerunPolicy::default();
+        policy.ignore_file("*.aux").ignore_file("stamp.tex");
+        assert!(policy.is_ignored("main.aux"));
+        assert!(policy.is_ignored("stamp.tex"));
+        assert!(!policy.is_ignored("main.toc"));
+        assert!(!policy.is_ignored("other-stamp.tex"));
+    }
 
-        // All done!
+    #[test]
+    fn rerun_policy_max_passes() {
+        assert_eq!(RerunPolicy::default().max_passes, 7);
+        assert_eq!(RerunPolicy::default().max_passes(0).max_passes, 1);
+        assert_eq!(RerunPolicy::default().max_passes(3).max_passes, 3);
+    }
+
+    #[test]
+    fn oscillation_detection() {
+        let a = digest_of(b"A");
+        let b = digest_of(b"B");
+        let c = digest_of(b"C");
+        let mut history = HashMap::new();
 
-        Ok(if state == State::Searching {
-            // No biber invocation, in the end.
+        // A file that converges, slowly ...
+        assert_eq!(
+            record_written_digests(&mut history, vec![("x.aux".to_owned(), a)]),
             None
-        } else {
-            Some(ExternalToolPass {
-                argv,
-                extra_requires,
-            })
-        })
+        );
+        assert_eq!(
+            record_written_digests(&mut history, vec![("x.aux".to_owned(), b)]),
+            None
+        );
+        assert_eq!(
+            record_written_digests(&mut history, vec![("x.aux".to_owned(), c)]),
+            None
+        );
+
+        // ... and one that flips back and forth, which is caught as soon as
+        // it goes back to earlier contents.
+        let mut history = HashMap::new();
+        let results: Vec<_> = [a, b, a, b]
+            .iter()
+            .map(|d| record_written_digests(&mut history, vec![("y.aux".to_owned(), *d)]))
+            .collect();
+
+        let y = Some("y.aux".to_owned());
+        assert_eq!(results, vec![None, None, y.clone(), y]);
+    }
+
+    #[test]
+    fn cancellation_checks() {
+        let token = CancellationToken::new();
+        assert!(check_cancelled(Some(&token), None).is_ok());
+
+        let future = Instant::now() + Duration::from_secs(3600);
+        assert!(check_cancelled(None, Some(future)).is_ok());
+        assert!(check_cancelled(None, Some(Instant::now())).is_err());
+
+        token.clone().cancel();
+        assert!(check_cancelled(Some(&token), Some(future)).is_err());
     }
 }
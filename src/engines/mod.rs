@@ -4,16 +4,19 @@
 //! Access to Tectonic’s processing backends.
 //!
 //! These backends subsume the functionality of programs such as `bibtex`,
-//! `xetex`, and `xdvipdfmx`. This module is historical — the API for each of
-//! these is defined in crates with names like `tectonic_engine_xetex`.
+//! `makeindex`, `xetex`, and `xdvipdfmx`. This module is historical — the API
+//! for each of these is defined in crates with names like
+//! `tectonic_engine_xetex`.
 
 // Public sub-modules and reexports.
 
 pub mod bibtex;
+pub mod makeindex;
 pub mod spx2html;
 pub mod tex;
 pub mod xdvipdfmx;
 
 pub use self::{
-    bibtex::BibtexEngine, spx2html::Spx2HtmlEngine, tex::TexEngine, xdvipdfmx::XdvipdfmxEngine,
+    bibtex::BibtexEngine, makeindex::MakeindexEngine, spx2html::Spx2HtmlEngine, tex::TexEngine,
+    xdvipdfmx::XdvipdfmxEngine,
 };
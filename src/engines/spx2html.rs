@@ -1,4 +1,4 @@
 // Copyright 2018-2021 the Tectonic Project
 // Licensed under the MIT License.
 
-pub use tectonic_engine_spx2html::Spx2HtmlEngine;
+pub use tectonic_engine_spx2html::{HtmlOutputFile, Spx2HtmlEngine};
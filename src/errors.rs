@@ -62,6 +62,7 @@ error_chain! {
     foreign_links {
         Io(io::Error);
         Fmt(fmt::Error);
+        Json(serde_json::Error) #[cfg(feature = "serde_json")];
         Nul(ffi::NulError);
         ParseInt(num::ParseIntError);
         Persist(tempfile::PersistError);
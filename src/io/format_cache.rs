@@ -4,10 +4,18 @@
 #![deny(missing_docs)]
 
 //! Code for locally caching compiled format files.
+//!
+//! Format files are cached under names of the form
+//! `<bundle-digest>-<name>-<serial>.fmt`. Since nothing ever removes them,
+//! formats for old bundles and old versions of Tectonic pile up over time.
+//! The [`list_formats`] and [`prune_formats`] functions help keep the cache
+//! under control.
 
 use std::{
     io::{BufReader, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
 };
 use tectonic_errors::{anyhow::bail, Result};
 
@@ -63,6 +71,135 @@ impl FormatCache {
     }
 }
 
+/// A format file found in a format cache directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CachedFormat {
+    /// The path of the format file.
+    pub path: PathBuf,
+
+    /// The digest of the bundle that the format was generated from.
+    pub bundle_digest: DigestData,
+
+    /// The name of the format, such as `latex`.
+    pub name: String,
+
+    /// The serial number of the format file layout.
+    pub serial: u32,
+
+    /// The size of the format file, in bytes.
+    pub size: u64,
+
+    /// When the format file was last modified, if known.
+    pub modified: Option<SystemTime>,
+}
+
+impl CachedFormat {
+    /// Whether this version of Tectonic can load this format. Formats with
+    /// other serial numbers were generated by other versions.
+    pub fn is_usable(&self) -> bool {
+        self.serial == crate::FORMAT_SERIAL
+    }
+
+    /// Parse the name of a format file, returning its bundle digest, format
+    /// name, and serial number.
+    fn parse_file_name(file_name: &str) -> Option<(DigestData, String, u32)> {
+        let rest = file_name.strip_suffix(".fmt")?;
+        let (digest, rest) = rest.split_once('-')?;
+        let (name, serial) = rest.rsplit_once('-')?;
+        let digest = DigestData::from_str(digest).ok()?;
+        let serial = serial.parse().ok()?;
+        Some((digest, name.to_owned(), serial))
+    }
+}
+
+/// List the format files in a format cache directory, sorted by file name.
+///
+/// Files whose names don't look like those of cached formats are ignored. If
+/// the directory doesn't exist, the list is empty.
+pub fn list_formats(dir: &Path) -> Result<Vec<CachedFormat>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut formats = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        let (bundle_digest, name, serial) =
+            match file_name.to_str().and_then(CachedFormat::parse_file_name) {
+                Some(t) => t,
+                None => continue,
+            };
+
+        let md = entry.metadata()?;
+
+        if !md.is_file() {
+            continue;
+        }
+
+        formats.push(CachedFormat {
+            path: entry.path(),
+            bundle_digest,
+            name,
+            serial,
+            size: md.len(),
+            modified: md.modified().ok(),
+        });
+    }
+
+    formats.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(formats)
+}
+
+/// Delete unneeded format files from a format cache directory, returning the
+/// ones that were deleted.
+///
+/// Formats generated from the bundles with the digests in *stale_bundles*
+/// are deleted. Formats for other bundles are kept, since we can't know
+/// whether some document still uses them; likewise for formats that other
+/// versions of Tectonic generated. Then, if *max_total_bytes* is given,
+/// formats are deleted until the total size of the rest is within that
+/// limit: first those that this version can't load, then the oldest. A
+/// deleted format is simply generated again the next time that it's needed.
+pub fn prune_formats(
+    dir: &Path,
+    stale_bundles: &[DigestData],
+    max_total_bytes: Option<u64>,
+) -> Result<Vec<CachedFormat>> {
+    let (mut delete, mut keep): (Vec<_>, Vec<_>) = list_formats(dir)?
+        .into_iter()
+        .partition(|f| stale_bundles.contains(&f.bundle_digest));
+
+    if let Some(max) = max_total_bytes {
+        // Unusable first, then oldest first, with the formats of unknown age
+        // first of all.
+        keep.sort_by_key(|f| (f.is_usable(), f.modified));
+        let mut total: u64 = keep.iter().map(|f| f.size).sum();
+        let mut n_over = 0;
+
+        for f in &keep {
+            if total <= max {
+                break;
+            }
+
+            total -= f.size;
+            n_over += 1;
+        }
+
+        delete.extend(keep.drain(..n_over));
+    }
+
+    for f in &delete {
+        std::fs::remove_file(&f.path)?;
+    }
+
+    Ok(delete)
+}
+
 impl IoProvider for FormatCache {
     fn input_open_format(
         &mut self,
@@ -103,3 +240,73 @@ impl IoProvider for FormatCache {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn write_format(
+        dir: &Path,
+        digest: &DigestData,
+        name: &str,
+        serial: u32,
+        size: usize,
+        age: u64,
+    ) {
+        let path = dir.join(format!("{}-{}-{}.fmt", digest.to_string(), name, serial));
+        std::fs::write(&path, vec![0; size]).unwrap();
+        let mtime = SystemTime::now() - Duration::from_secs(age);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn list_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = DigestData::of_nothing();
+        let stale = DigestData::zeros();
+        let unknown = DigestData::from_str(&"1".repeat(64)).unwrap();
+        let serial = crate::FORMAT_SERIAL;
+
+        write_format(dir.path(), &current, "latex", serial, 100, 30);
+        write_format(dir.path(), &current, "plain", serial, 50, 10);
+        write_format(dir.path(), &current, "house-abc", serial, 40, 20);
+        write_format(dir.path(), &current, "latex", serial - 1, 100, 0);
+        write_format(dir.path(), &stale, "latex", serial, 100, 0);
+        write_format(dir.path(), &unknown, "plain", serial, 30, 5);
+        std::fs::write(dir.path().join("notes.txt"), b"hello").unwrap();
+
+        let formats = list_formats(dir.path()).unwrap();
+        assert_eq!(formats.len(), 6);
+        assert!(formats.iter().any(|f| f.name == "house-abc"));
+        assert_eq!(formats.iter().filter(|f| !f.is_usable()).count(), 1);
+
+        // Only the format for the stale bundle goes.
+        let deleted = prune_formats(dir.path(), &[stale], None).unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].bundle_digest, stale);
+        assert_eq!(list_formats(dir.path()).unwrap().len(), 5);
+
+        // To fit the limit, the unusable format goes first, then the oldest
+        // of the rest.
+        let deleted = prune_formats(dir.path(), &[stale], Some(200)).unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(!deleted[0].is_usable());
+        assert_eq!(deleted[1].name, "latex");
+
+        let remaining: Vec<_> = list_formats(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.bundle_digest, f.name))
+            .collect();
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.contains(&(current, "plain".to_owned())));
+        assert!(remaining.contains(&(current, "house-abc".to_owned())));
+        assert!(remaining.contains(&(unknown, "plain".to_owned())));
+    }
+}
@@ -5,13 +5,14 @@
 //! MemoryIo is an IoProvider that stores "files" in in-memory buffers.
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::HashMap,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     rc::Rc,
     time::SystemTime,
 };
 use tectonic_errors::Result;
+use tectonic_io_base::TectonicIoError;
 use tectonic_status_base::StatusBackend;
 
 use super::{
@@ -33,6 +34,20 @@ pub struct MemoryFileInfo {
 /// A collection of files created or used inside a memory-backed I/O provider.
 pub type MemoryFileCollection = HashMap<String, MemoryFileInfo>;
 
+/// Limits on the data that can be written into a memory-backed I/O provider.
+///
+/// Writes that would exceed a limit fail with a
+/// [`TectonicIoError::LimitExceeded`] error.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryLimits {
+    /// The maximum total number of bytes that may be written, summed over
+    /// all files. Data that overwrite earlier data count again.
+    pub max_total_bytes: Option<u64>,
+
+    /// The maximum size of any single file, in bytes.
+    pub max_file_bytes: Option<u64>,
+}
+
 /// When a file is "opened", we create a MemoryIoItem struct that tracks the
 /// data, seek cursor state, etc.
 struct MemoryIoItem {
@@ -44,6 +59,11 @@ struct MemoryIoItem {
     state: Cursor<Vec<u8>>,
     unix_mtime: Option<i64>,
     was_modified: bool,
+    limits: MemoryLimits,
+
+    /// The number of bytes written so far, shared among all of the items of
+    /// the parent provider.
+    bytes_written: Rc<Cell<u64>>,
 }
 
 /// Get the current time as a Unix time, in a manner consistent with our Unix
@@ -65,6 +85,8 @@ impl MemoryIoItem {
         files: &Rc<RefCell<MemoryFileCollection>>,
         name: &str,
         truncate: bool,
+        limits: MemoryLimits,
+        bytes_written: &Rc<Cell<u64>>,
     ) -> MemoryIoItem {
         let (cur_data, cur_mtime) = match files.borrow_mut().remove(name) {
             Some(info) => {
@@ -83,8 +105,37 @@ impl MemoryIoItem {
             state: Cursor::new(cur_data),
             unix_mtime: cur_mtime,
             was_modified: false,
+            limits,
+            bytes_written: bytes_written.clone(),
         }
     }
+
+    fn check_limits(&self, n: usize) -> io::Result<()> {
+        let n = n as u64;
+
+        if let Some(max) = self.limits.max_file_bytes {
+            let cur_len = self.state.get_ref().len() as u64;
+            let new_len = cur_len.max(self.state.position() + n);
+
+            if new_len > max {
+                return Err(io::Error::other(TectonicIoError::LimitExceeded(format!(
+                    "output `{}` would exceed the size limit of {} bytes",
+                    self.name, max
+                ))));
+            }
+        }
+
+        if let Some(max) = self.limits.max_total_bytes {
+            if self.bytes_written.get() + n > max {
+                return Err(io::Error::other(TectonicIoError::LimitExceeded(format!(
+                    "writing `{}` would exceed the limit of {} bytes written in total",
+                    self.name, max
+                ))));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Read for MemoryIoItem {
@@ -95,8 +146,11 @@ impl Read for MemoryIoItem {
 
 impl Write for MemoryIoItem {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_limits(buf.len())?;
         self.was_modified = true;
-        self.state.write(buf)
+        let n = self.state.write(buf)?;
+        self.bytes_written.set(self.bytes_written.get() + n as u64);
+        Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -149,6 +203,8 @@ impl Drop for MemoryIoItem {
 pub struct MemoryIo {
     pub files: Rc<RefCell<MemoryFileCollection>>,
     stdout_allowed: bool,
+    limits: MemoryLimits,
+    bytes_written: Rc<Cell<u64>>,
 }
 
 impl MemoryIo {
@@ -156,9 +212,21 @@ impl MemoryIo {
         MemoryIo {
             files: Rc::new(RefCell::new(HashMap::new())),
             stdout_allowed,
+            limits: MemoryLimits::default(),
+            bytes_written: Rc::new(Cell::new(0)),
         }
     }
 
+    /// Set the limits on the data that can be written into this provider.
+    pub fn set_limits(&mut self, limits: MemoryLimits) {
+        self.limits = limits;
+    }
+
+    /// Get the total number of bytes written into this provider so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.get()
+    }
+
     pub fn create_entry(&mut self, name: &str, data: Vec<u8>) {
         let mut mfiles = self.files.borrow_mut();
         mfiles.insert(
@@ -183,7 +251,10 @@ impl IoProvider for MemoryIo {
 
         let name = normalize_tex_path(name);
 
-        let oh = OutputHandle::new(name.to_owned(), MemoryIoItem::new(&self.files, &name, true));
+        let oh = OutputHandle::new(
+            name.to_owned(),
+            MemoryIoItem::new(&self.files, &name, true, self.limits, &self.bytes_written),
+        );
 
         // `hyperxmp.sty` does a thing where it tries to get today's date by
         // calling \filemoddate on `\jobname.log`. That essentially relies on it
@@ -204,7 +275,13 @@ impl IoProvider for MemoryIo {
 
         OpenResult::Ok(OutputHandle::new(
             self.stdout_key(),
-            MemoryIoItem::new(&self.files, self.stdout_key(), true),
+            MemoryIoItem::new(
+                &self.files,
+                self.stdout_key(),
+                true,
+                self.limits,
+                &self.bytes_written,
+            ),
         ))
     }
 
@@ -222,7 +299,7 @@ impl IoProvider for MemoryIo {
         if self.files.borrow().contains_key(&*name) {
             OpenResult::Ok(InputHandle::new(
                 name.to_owned(),
-                MemoryIoItem::new(&self.files, &name, false),
+                MemoryIoItem::new(&self.files, &name, false, self.limits, &self.bytes_written),
                 InputOrigin::Other,
             ))
         } else {
@@ -284,4 +361,29 @@ mod tests {
             assert_eq!(s.len(), 0);
         }
     }
+
+    #[test]
+    fn size_limits() {
+        let mut mem = MemoryIo::new(false);
+        mem.set_limits(MemoryLimits {
+            max_total_bytes: Some(12),
+            max_file_bytes: Some(8),
+        });
+
+        {
+            let mut h = mem.output_open_name("a.txt").unwrap();
+            h.write_all(b"01234567").unwrap();
+            assert!(h.write_all(b"8").is_err());
+        }
+
+        // Rewriting the file from scratch counts against the total.
+        {
+            let mut h = mem.output_open_name("a.txt").unwrap();
+            h.write_all(b"0123").unwrap();
+            assert!(h.write_all(b"4").is_err());
+        }
+
+        assert_eq!(mem.bytes_written(), 12);
+        assert_eq!(mem.files.borrow()["a.txt"].data, b"0123");
+    }
 }
@@ -7,6 +7,7 @@ use tectonic_status_base::StatusBackend;
 
 pub mod format_cache;
 pub mod memory;
+pub mod streaming;
 
 // Convenience re-exports.
 
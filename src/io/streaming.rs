@@ -0,0 +1,295 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+#![deny(missing_docs)]
+
+//! An I/O provider that streams selected output files straight to disk.
+//!
+//! Normally, everything that the engines write is buffered in memory and only
+//! written to disk at the very end of processing. For large outputs, such as
+//! PDFs with many embedded images, that can take a lot of memory. The
+//! [`StreamingOutputIo`] provider instead writes the outputs that it is told
+//! about into temporary files in the output directory. Once processing has
+//! succeeded, the temporary files can be atomically renamed into place. If
+//! the provider is dropped first, they are deleted.
+//!
+//! The same approach is used by [`write_output_atomically`] for outputs that
+//! were buffered in memory.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tectonic_errors::{anyhow::anyhow, Result};
+use tempfile::TempPath;
+
+use super::{InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle};
+use crate::status::StatusBackend;
+
+/// An I/O provider that writes selected outputs into temporary files.
+pub struct StreamingOutputIo {
+    dir: PathBuf,
+    names: HashSet<String>,
+    files: HashMap<String, TempPath>,
+}
+
+impl StreamingOutputIo {
+    /// Create a new provider that streams the named outputs into temporary
+    /// files in the directory `dir`.
+    ///
+    /// The final outputs will be placed in the same directory, so that they
+    /// can be renamed into place atomically. Outputs with other names aren't
+    /// handled by this provider.
+    pub fn new<I: IntoIterator<Item = String>>(dir: &Path, names: I) -> Self {
+        StreamingOutputIo {
+            dir: dir.to_owned(),
+            names: names.into_iter().collect(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Get the names of the outputs that have been written so far, sorted.
+    pub fn written_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.files.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Check whether the named output has been written.
+    pub fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    /// Get the current size of a streamed output, in bytes.
+    pub fn size(&self, name: &str) -> Result<u64> {
+        let path = self
+            .files
+            .get(name)
+            .ok_or_else(|| anyhow!("`{}` was not streamed to disk", name))?;
+        Ok(path.metadata()?.len())
+    }
+
+    /// Move a streamed output into its final location.
+    ///
+    /// The destination should be in the directory passed to [`Self::new`].
+    /// Once an output has been persisted, it is no longer tracked by this
+    /// provider.
+    pub fn persist(&mut self, name: &str, dest: &Path) -> Result<()> {
+        let path = self
+            .files
+            .remove(name)
+            .ok_or_else(|| anyhow!("`{}` was not streamed to disk", name))?;
+        persist_output(path, dest)
+    }
+
+    /// Delete a streamed output without persisting it.
+    pub fn discard(&mut self, name: &str) {
+        self.files.remove(name);
+    }
+}
+
+impl IoProvider for StreamingOutputIo {
+    fn output_open_name(&mut self, name: &str) -> OpenResult<OutputHandle> {
+        if !self.names.contains(name) {
+            return OpenResult::NotAvailable;
+        }
+
+        let (file, path) = match create_temp_output(&self.dir) {
+            Ok(t) => t,
+            Err(e) => return OpenResult::Err(e.into()),
+        };
+
+        // If the output is being rewritten, this drops and deletes the
+        // previous temporary file.
+        self.files.insert(name.to_owned(), path);
+        OpenResult::Ok(OutputHandle::new(name, BufWriter::new(file)))
+    }
+
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        let path = match self.files.get(name) {
+            Some(p) => p,
+            None => return OpenResult::NotAvailable,
+        };
+
+        match File::open(path) {
+            Ok(f) => OpenResult::Ok(InputHandle::new(
+                name,
+                BufReader::new(f),
+                InputOrigin::Other,
+            )),
+            Err(e) => OpenResult::Err(e.into()),
+        }
+    }
+}
+
+/// Create a temporary file in `dir` that can later become an output.
+///
+/// The `tempfile` crate creates files that only their owner can read, which
+/// isn't what we want for outputs. This file gets the usual permissions for
+/// a new file instead: 0666, less the umask.
+fn create_temp_output(dir: &Path) -> io::Result<(File, TempPath)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let path = dir.join(format!(
+            ".tectonic-{}-{}-{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(f) => return Ok((f, TempPath::from_path(path))),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Move a temporary file into its final location as an output.
+///
+/// If the output replaces an existing file, it takes on that file's
+/// permissions. The data are flushed to disk before the rename, and the
+/// rename itself afterwards, so that a crash can't leave behind an empty or
+/// truncated output in place of the old one.
+fn persist_output(temp: TempPath, dest: &Path) -> Result<()> {
+    if let Ok(md) = std::fs::metadata(dest) {
+        std::fs::set_permissions(&temp, md.permissions())?;
+    }
+
+    OpenOptions::new().write(true).open(&temp)?.sync_all()?;
+    temp.persist(dest)?;
+    sync_parent_dir(dest);
+    Ok(())
+}
+
+/// Flush a rename in the directory containing `path` to disk.
+///
+/// Not all platforms and filesystems support this, so failures are ignored.
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Write an output file atomically.
+///
+/// The data are written to a temporary file in the same directory, which is
+/// then renamed into place. So if something goes wrong along the way, any
+/// previous version of the file is left untouched, rather than being
+/// replaced by a truncated one.
+pub fn write_output_atomically(dest: &Path, data: &[u8]) -> Result<()> {
+    let dir = match dest.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    let (mut file, temp) = create_temp_output(dir)?;
+    file.write_all(data)?;
+    drop(file);
+    persist_output(temp, dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::NoopStatusBackend;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn stream_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut io = StreamingOutputIo::new(dir.path(), vec!["out.pdf".to_owned()]);
+        let mut sb = NoopStatusBackend::default();
+
+        assert!(io.output_open_name("out.aux").is_not_available());
+
+        {
+            let mut h = io.output_open_name("out.pdf").unwrap();
+            h.write_all(b"%PDF").unwrap();
+        }
+
+        {
+            let mut h = io.input_open_name("out.pdf", &mut sb).unwrap();
+            let mut s = String::new();
+            h.read_to_string(&mut s).unwrap();
+            assert_eq!(s, "%PDF");
+        }
+
+        assert_eq!(io.written_names(), vec!["out.pdf".to_owned()]);
+        assert_eq!(io.size("out.pdf").unwrap(), 4);
+
+        let dest = dir.path().join("out.pdf");
+        io.persist("out.pdf", &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"%PDF");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn dropped_outputs_are_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let mut io = StreamingOutputIo::new(dir.path(), vec!["out.pdf".to_owned()]);
+            let mut h = io.output_open_name("out.pdf").unwrap();
+            h.write_all(b"%PDF").unwrap();
+        }
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn atomic_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out.pdf");
+        write_output_atomically(&dest, b"first").unwrap();
+        write_output_atomically(&dest, b"second").unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"second");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+
+            // New outputs get the same permissions as any other new file ...
+            let plain = dir.path().join("plain");
+            File::create(&plain).unwrap();
+            assert_eq!(mode(&dest), mode(&plain));
+            std::fs::remove_file(&plain).unwrap();
+
+            // ... and replacements keep the permissions of what they replace.
+            std::fs::set_permissions(&dest, std::fs::Permissions::from_mode(0o600)).unwrap();
+            write_output_atomically(&dest, b"third").unwrap();
+            assert_eq!(mode(&dest), 0o600);
+        }
+    }
+}
@@ -55,6 +55,8 @@
 //!   for the `xdvipdfmx` engine.
 //! - [`tectonic_engine_bibtex`](https://docs.rs/tectonic_engine_bibtex) for the
 //!   BibTeX engine.
+//! - [`tectonic_engine_makeindex`](https://docs.rs/tectonic_engine_makeindex)
+//!   for the `makeindex` engine.
 //!
 //! The main module of this crate provides an all-in-wonder function for
 //! compiling LaTeX code to a PDF:
@@ -77,6 +79,7 @@
 //! The [`driver`] module provides a high-level interface for driving the
 //! engines in more realistic circumstances.
 
+pub mod batch;
 pub mod config;
 pub mod digest;
 #[cfg(feature = "serialization")]
@@ -85,7 +88,10 @@ pub mod driver;
 pub mod engines;
 pub mod errors;
 pub mod io;
+pub mod logreq;
 pub mod status;
+pub mod synctex;
+pub mod texlog;
 pub mod unstable_opts;
 
 // Note: this module is intentionally *not* gated by #[cfg(test)] -- see its
@@ -94,6 +100,7 @@ pub mod unstable_opts;
 pub mod test_util;
 
 pub use crate::engines::bibtex::BibtexEngine;
+pub use crate::engines::makeindex::MakeindexEngine;
 pub use crate::engines::spx2html::Spx2HtmlEngine;
 pub use crate::engines::tex::{TexEngine, TexOutcome};
 pub use crate::engines::xdvipdfmx::XdvipdfmxEngine;
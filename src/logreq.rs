@@ -0,0 +1,442 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Parsing the `.run.xml` files written by the [logreq] LaTeX package.
+//!
+//! [logreq]: https://ctan.org/pkg/logreq
+//!
+//! Packages such as `biblatex` use `logreq` to tell the world which
+//! programs need to be run on a document, and on which files. Each request
+//! is either *external*, asking for a program like `biber` to be run, or
+//! *internal*, describing files that the TeX engine itself depends on. The
+//! [`crate::driver::ProcessingSession`] uses these requests to decide which
+//! auxiliary tools to run between TeX passes.
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::io::Cursor;
+
+use crate::{ctry, errors::Result, status::StatusBackend, tt_warning};
+
+/// Whether a request concerns an external program or the TeX engine itself.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RequestKind {
+    /// An `<internal>` request, for the TeX engine.
+    Internal,
+
+    /// An `<external>` request, for some other program.
+    External,
+}
+
+/// One item of the command line of an external request.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Argument {
+    /// An `<option>`, such as `--min-crossrefs=3`.
+    Option(String),
+
+    /// An `<infile>`. This is often given without its extension.
+    Infile(String),
+
+    /// An `<outfile>`.
+    Outfile(String),
+}
+
+impl Argument {
+    /// The text of this argument.
+    pub fn text(&self) -> &str {
+        match self {
+            Argument::Option(s) | Argument::Infile(s) | Argument::Outfile(s) => s,
+        }
+    }
+}
+
+/// A group of files in a `<provides>` or `<requires>` section.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileGroup {
+    /// The `type` of the section, such as `dynamic`, `static`, or
+    /// `editable`. Empty if not given.
+    pub kind: String,
+
+    /// The names of the files in the section.
+    pub files: Vec<String>,
+}
+
+/// One request from a `.run.xml` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Request {
+    /// Whether this is an internal or external request.
+    pub kind: RequestKind,
+
+    /// The LaTeX package that made the request.
+    pub package: String,
+
+    /// The priority of the request. Requests with higher priorities should
+    /// be handled first.
+    pub priority: i32,
+
+    /// Whether the request is active. Inactive requests should be ignored.
+    pub active: bool,
+
+    /// The generic name of the program, such as `biber` or `latex`.
+    pub generic: String,
+
+    /// The name of the program binary, for external requests.
+    pub binary: Option<String>,
+
+    /// The command-line arguments for the program, in order.
+    pub arguments: Vec<Argument>,
+
+    /// The files that the program reads, from the `<input>` section.
+    pub input: Vec<String>,
+
+    /// The files that the program writes, from the `<output>` section.
+    pub output: Vec<String>,
+
+    /// The files that the program provides to the TeX engine.
+    pub provides: Vec<FileGroup>,
+
+    /// The files that the program needs.
+    pub requires: Vec<FileGroup>,
+}
+
+impl Request {
+    fn new(kind: RequestKind) -> Self {
+        Request {
+            kind,
+            package: String::new(),
+            priority: 0,
+            active: true,
+            generic: String::new(),
+            binary: None,
+            arguments: Vec::new(),
+            input: Vec::new(),
+            output: Vec::new(),
+            provides: Vec::new(),
+            requires: Vec::new(),
+        }
+    }
+
+    /// The name of the program to run: the binary if one was given, and
+    /// otherwise the generic name.
+    pub fn program(&self) -> &str {
+        self.binary.as_deref().unwrap_or(&self.generic)
+    }
+
+    /// The first input file named on the command line, if any.
+    pub fn infile(&self) -> Option<&str> {
+        self.arguments.iter().find_map(|a| match a {
+            Argument::Infile(s) => Some(s.as_str()),
+            _ => None,
+        })
+    }
+
+    /// All of the files that the program needs to read: the `<input>` files
+    /// and everything in the `<requires>` sections.
+    pub fn required_files(&self) -> impl Iterator<Item = &str> {
+        self.input
+            .iter()
+            .chain(self.requires.iter().flat_map(|g| g.files.iter()))
+            .map(|s| s.as_str())
+    }
+
+    /// All of the files that the program promises to provide.
+    pub fn provided_files(&self) -> impl Iterator<Item = &str> {
+        self.provides
+            .iter()
+            .flat_map(|g| g.files.iter())
+            .map(|s| s.as_str())
+    }
+}
+
+/// Parse the contents of a `.run.xml` file.
+///
+/// The requests are returned in the order in which they appear in the file.
+/// Unrecognized elements are ignored. A request with an invalid priority is
+/// given the default priority of zero, with a warning sent to *status*.
+pub fn parse_run_xml(data: &[u8], status: &mut dyn StatusBackend) -> Result<Vec<Request>> {
+    /// Which part of a request we're in.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Section {
+        Other,
+        Cmdline,
+        Input,
+        Output,
+        Provides,
+        Requires,
+    }
+
+    let mut reader = Reader::from_reader(Cursor::new(data));
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut requests = Vec::new();
+    let mut current: Option<Request> = None;
+    let mut section = Section::Other;
+    let mut element = String::new();
+
+    loop {
+        buf.clear();
+
+        let event = ctry!(
+            reader.read_event(&mut buf);
+            "error parsing run.xml file"
+        );
+
+        match event {
+            Event::Eof => break,
+
+            Event::Start(ref e) => {
+                let name = reader.decode(e.local_name())?.to_owned();
+
+                match current {
+                    None => {
+                        let kind = match name.as_str() {
+                            "internal" => RequestKind::Internal,
+                            "external" => RequestKind::External,
+                            _ => continue,
+                        };
+
+                        let mut req = Request::new(kind);
+                        read_request_attributes(&mut req, e, &reader, status)?;
+                        current = Some(req);
+                        section = Section::Other;
+                    }
+
+                    Some(ref mut req) if section == Section::Other => {
+                        section = match name.as_str() {
+                            "cmdline" => Section::Cmdline,
+                            "input" => Section::Input,
+                            "output" => Section::Output,
+                            "provides" => Section::Provides,
+                            "requires" => Section::Requires,
+                            _ => Section::Other,
+                        };
+
+                        if section == Section::Provides || section == Section::Requires {
+                            let mut group = FileGroup::default();
+
+                            for attr in e.attributes() {
+                                let attr = attr?;
+
+                                if attr.key == b"type" {
+                                    group.kind = attr.unescape_and_decode_value(&reader)?;
+                                }
+                            }
+
+                            if section == Section::Provides {
+                                req.provides.push(group);
+                            } else {
+                                req.requires.push(group);
+                            }
+                        }
+                    }
+
+                    _ => {}
+                }
+
+                element = name;
+            }
+
+            Event::Text(ref e) => {
+                let req = match current {
+                    Some(ref mut r) => r,
+                    None => continue,
+                };
+
+                let text = e.unescape_and_decode(&reader)?;
+
+                match (section, element.as_str()) {
+                    (Section::Other, "generic") => req.generic = text,
+                    (Section::Cmdline, "binary") => req.binary = Some(text),
+                    (Section::Cmdline, "option") => req.arguments.push(Argument::Option(text)),
+                    (Section::Cmdline, "infile") => req.arguments.push(Argument::Infile(text)),
+                    (Section::Cmdline, "outfile") => req.arguments.push(Argument::Outfile(text)),
+                    (Section::Input, "file") => req.input.push(text),
+                    (Section::Output, "file") => req.output.push(text),
+                    (Section::Provides, "file") => {
+                        if let Some(g) = req.provides.last_mut() {
+                            g.files.push(text);
+                        }
+                    }
+                    (Section::Requires, "file") => {
+                        if let Some(g) = req.requires.last_mut() {
+                            g.files.push(text);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            Event::End(ref e) => {
+                let name = reader.decode(e.local_name())?;
+
+                match name {
+                    "internal" | "external" => {
+                        if let Some(req) = current.take() {
+                            requests.push(req);
+                        }
+                    }
+
+                    "cmdline" | "input" | "output" | "provides" | "requires" => {
+                        section = Section::Other;
+                    }
+
+                    _ => {}
+                }
+
+                element.clear();
+            }
+
+            _ => {}
+        }
+    }
+
+    Ok(requests)
+}
+
+fn read_request_attributes(
+    req: &mut Request,
+    e: &BytesStart<'_>,
+    reader: &Reader<Cursor<&[u8]>>,
+    status: &mut dyn StatusBackend,
+) -> Result<()> {
+    let mut bad_priority = None;
+
+    for attr in e.attributes() {
+        let attr = attr?;
+        let value = attr.unescape_and_decode_value(reader)?;
+
+        match attr.key {
+            b"package" => req.package = value,
+            b"priority" => match value.trim().parse() {
+                Ok(p) => req.priority = p,
+                Err(e) => bad_priority = Some((value, e)),
+            },
+            b"active" => req.active = value.trim() != "0",
+            _ => {}
+        }
+    }
+
+    // Warn once all of the attributes have been read, so that we know which
+    // package made the request.
+    if let Some((value, e)) = bad_priority {
+        tt_warning!(
+            status,
+            "ignoring the invalid priority `{}` of a request from the `{}` package",
+            value,
+            req.package;
+            e.into()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::NoopStatusBackend;
+
+    const BIBLATEX: &str = r#"<?xml version="1.0" standalone="yes"?>
+<requests version="1.0">
+  <internal package="biblatex" priority="9" active="0">
+    <generic>latex</generic>
+    <provides type="dynamic">
+      <file>doc.bcf</file>
+    </provides>
+    <requires type="dynamic">
+      <file>doc.bbl</file>
+    </requires>
+    <requires type="static">
+      <file>blx-dm.def</file>
+      <file>english.lbx</file>
+    </requires>
+  </internal>
+  <external package="biblatex" priority="5" active="1">
+    <generic>biber</generic>
+    <cmdline>
+      <binary>biber</binary>
+      <option>--quiet</option>
+      <infile>doc</infile>
+    </cmdline>
+    <input>
+      <file>doc.bcf</file>
+    </input>
+    <output>
+      <file>doc.bbl</file>
+    </output>
+    <provides type="dynamic">
+      <file>doc.bbl</file>
+    </provides>
+    <requires type="dynamic">
+      <file>doc.bcf</file>
+    </requires>
+    <requires type="editable">
+      <file>refs.bib</file>
+    </requires>
+  </external>
+</requests>
+"#;
+
+    #[test]
+    fn biblatex() {
+        let reqs = parse_run_xml(BIBLATEX.as_bytes(), &mut NoopStatusBackend::default()).unwrap();
+        assert_eq!(reqs.len(), 2);
+
+        let internal = &reqs[0];
+        assert_eq!(internal.kind, RequestKind::Internal);
+        assert_eq!(internal.priority, 9);
+        assert!(!internal.active);
+        assert_eq!(internal.program(), "latex");
+        assert_eq!(internal.requires.len(), 2);
+        assert_eq!(internal.requires[1].kind, "static");
+        assert_eq!(
+            internal.requires[1].files,
+            vec!["blx-dm.def", "english.lbx"]
+        );
+
+        let external = &reqs[1];
+        assert_eq!(external.kind, RequestKind::External);
+        assert_eq!(external.package, "biblatex");
+        assert_eq!(external.priority, 5);
+        assert!(external.active);
+        assert_eq!(external.program(), "biber");
+        assert_eq!(
+            external.arguments,
+            vec![
+                Argument::Option("--quiet".to_owned()),
+                Argument::Infile("doc".to_owned())
+            ]
+        );
+        assert_eq!(external.infile(), Some("doc"));
+        assert_eq!(external.output, vec!["doc.bbl"]);
+        assert_eq!(
+            external.required_files().collect::<Vec<_>>(),
+            vec!["doc.bcf", "doc.bcf", "refs.bib"]
+        );
+        assert_eq!(
+            external.provided_files().collect::<Vec<_>>(),
+            vec!["doc.bbl"]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let mut status = NoopStatusBackend::default();
+        assert!(parse_run_xml(b"<requests><external></internal></requests>", &mut status).is_err());
+    }
+
+    #[test]
+    fn bad_priority() {
+        let reqs = parse_run_xml(
+            b"<requests><external package=\"x\" priority=\"high\"></external></requests>",
+            &mut NoopStatusBackend::default(),
+        )
+        .unwrap();
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0].package, "x");
+        assert_eq!(reqs[0].priority, 0);
+    }
+}
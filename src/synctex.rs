@@ -0,0 +1,762 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Reading SyncTeX data, to map between source lines and typeset output.
+//!
+//! When SyncTeX is enabled, the TeX engine writes a `.synctex.gz` file that
+//! records, for each page, the boxes, glue, kerns and so on that it shipped
+//! out, along with the source file and line that each one came from. A
+//! [`SyncTex`] holds the parsed contents of such a file and can answer
+//! *forward* queries, from a source line to positions on the pages, and
+//! *inverse* queries, from a position on a page to a source line. This saves
+//! editor integrations from having to run the `synctex` command-line tool.
+//!
+//! For HTML output, there are no pages to point at. Instead, an
+//! [`HtmlSyncMap`] combines the SyncTeX data with the list of files written
+//! by `spx2html` to map source lines to HTML files and back.
+//!
+//! Positions on pages are given in PostScript big points (1/72 inch),
+//! measured from the top left corner of the page, as PDF viewers usually
+//! want them. Like the `synctex` tool, we assume that the TeX origin is one
+//! inch from the top and left edges of the page.
+
+use flate2::read::GzDecoder;
+use std::{collections::HashMap, io::Read, path::Path};
+
+use crate::{ctry, engines::spx2html::HtmlOutputFile, errmsg, errors::Result};
+
+/// The number of scaled points in a big point.
+const SP_PER_BP: f64 = 65781.76;
+
+/// The position of the TeX origin, in big points from the edges of the page.
+const ORIGIN_BP: f64 = 72.0;
+
+/// The kind of a SyncTeX record.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecordKind {
+    /// A vertical box, written as `[`.
+    VBox,
+
+    /// A horizontal box, written as `(`.
+    HBox,
+
+    /// An empty vertical box, written as `v`.
+    VoidVBox,
+
+    /// An empty horizontal box, written as `h`.
+    VoidHBox,
+
+    /// A kern, written as `k`.
+    Kern,
+
+    /// Some glue, written as `g`.
+    Glue,
+
+    /// A math node, written as `$`.
+    Math,
+
+    /// A position recorded at some other node, written as `x`.
+    Current,
+}
+
+impl RecordKind {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '[' => RecordKind::VBox,
+            '(' => RecordKind::HBox,
+            'v' => RecordKind::VoidVBox,
+            'h' => RecordKind::VoidHBox,
+            'k' => RecordKind::Kern,
+            'g' => RecordKind::Glue,
+            '$' => RecordKind::Math,
+            'x' => RecordKind::Current,
+            _ => return None,
+        })
+    }
+
+    /// Whether records of this kind have a size, rather than just marking a
+    /// point.
+    pub fn is_box(self) -> bool {
+        matches!(
+            self,
+            RecordKind::VBox | RecordKind::HBox | RecordKind::VoidVBox | RecordKind::VoidHBox
+        )
+    }
+}
+
+/// One record of a SyncTeX file.
+///
+/// The dimensions are in the units of the file, measured from the TeX
+/// origin, with `v` increasing down the page.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Record {
+    kind: RecordKind,
+    tag: u32,
+    line: u32,
+    h: i32,
+    v: i32,
+    width: i32,
+    height: i32,
+    depth: i32,
+}
+
+impl Record {
+    /// Parse a record line such as `(1,12:4736286,5672861:26673152,655360,0`.
+    fn parse(text: &str) -> Option<Self> {
+        let kind = RecordKind::from_char(text.chars().next()?)?;
+        let mut fields = text[1..].split(':');
+
+        // Newer versions of SyncTeX may add a column after the line number.
+        let mut link = fields.next()?.split(',');
+        let tag = link.next()?.parse().ok()?;
+        let line = link.next()?.parse().ok()?;
+
+        let (h, v) = fields.next()?.split_once(',')?;
+        let h = h.parse().ok()?;
+        let v = v.parse().ok()?;
+
+        let mut size = fields
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.parse().unwrap_or(0));
+        let width = size.next().unwrap_or(0);
+        let height = size.next().unwrap_or(0);
+        let depth = size.next().unwrap_or(0);
+
+        let (height, depth) = if kind.is_box() {
+            (height, depth)
+        } else {
+            (0, 0)
+        };
+
+        Some(Record {
+            kind,
+            tag,
+            line,
+            h,
+            v,
+            width,
+            height,
+            depth,
+        })
+    }
+
+    /// The horizontal extent of this record.
+    fn x_range(&self) -> (i64, i64) {
+        let (a, b) = (self.h as i64, self.h as i64 + self.width as i64);
+        (a.min(b), a.max(b))
+    }
+
+    /// The vertical extent of this record.
+    fn y_range(&self) -> (i64, i64) {
+        (
+            self.v as i64 - self.height.max(0) as i64,
+            self.v as i64 + self.depth.max(0) as i64,
+        )
+    }
+
+    fn contains(&self, h: i64, v: i64) -> bool {
+        let (x0, x1) = self.x_range();
+        let (y0, y1) = self.y_range();
+        h >= x0 && h <= x1 && v >= y0 && v <= y1
+    }
+
+    fn area(&self) -> i64 {
+        let (x0, x1) = self.x_range();
+        let (y0, y1) = self.y_range();
+        (x1 - x0) * (y1 - y0)
+    }
+
+    /// The distance from a point to this record, by the "taxicab" metric.
+    fn distance(&self, h: i64, v: i64) -> i64 {
+        let (x0, x1) = self.x_range();
+        let (y0, y1) = self.y_range();
+        let dx = (x0 - h).max(h - x1).max(0);
+        let dy = (y0 - v).max(v - y1).max(0);
+        dx + dy
+    }
+}
+
+/// One page of a SyncTeX file.
+#[derive(Clone, Debug, Default)]
+struct Page {
+    number: u32,
+    records: Vec<Record>,
+}
+
+/// A rectangle on a typeset page, as found by [`SyncTex::forward`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageLocation {
+    /// The page number, starting at 1.
+    pub page: u32,
+
+    /// The distance of the left edge from the left of the page, in big
+    /// points.
+    pub x: f64,
+
+    /// The distance of the top edge from the top of the page, in big points.
+    pub y: f64,
+
+    /// The width, in big points.
+    pub width: f64,
+
+    /// The height, in big points.
+    pub height: f64,
+}
+
+/// A line of a source file, as found by [`SyncTex::inverse`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceLocation {
+    /// The path of the file, as recorded by the TeX engine. This is usually
+    /// absolute for files in the filesystem, and a bare name for files from
+    /// the bundle.
+    pub file: String,
+
+    /// The line number, starting at 1.
+    pub line: u32,
+}
+
+/// A range of lines of a source file, as found by [`HtmlSyncMap::inverse`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceRange {
+    /// The path of the file, as in [`SourceLocation::file`].
+    pub file: String,
+
+    /// The first line in the range.
+    pub first_line: u32,
+
+    /// The last line in the range.
+    pub last_line: u32,
+}
+
+/// The parsed contents of a SyncTeX file.
+#[derive(Clone, Debug, Default)]
+pub struct SyncTex {
+    inputs: Vec<(u32, String)>,
+    magnification: f64,
+    unit: f64,
+    x_offset: f64,
+    y_offset: f64,
+    pages: Vec<Page>,
+}
+
+impl SyncTex {
+    /// Parse SyncTeX data. The data may be compressed, as in `.synctex.gz`
+    /// files, or not.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut text = Vec::new();
+            ctry!(GzDecoder::new(data).read_to_end(&mut text); "couldn't decompress SyncTeX data");
+            Self::parse_text(&String::from_utf8_lossy(&text))
+        } else {
+            Self::parse_text(&String::from_utf8_lossy(data))
+        }
+    }
+
+    /// Read and parse a SyncTeX file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = ctry!(std::fs::read(path); "couldn't read SyncTeX file `{}`", path.display());
+        Self::parse(&data)
+    }
+
+    fn parse_text(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+
+        match lines.next() {
+            Some(l) if l.starts_with("SyncTeX Version:") => {}
+            _ => return Err(errmsg!("not a SyncTeX file")),
+        }
+
+        let mut st = SyncTex {
+            magnification: 1000.,
+            unit: 1.,
+            ..Default::default()
+        };
+
+        let mut page: Option<Page> = None;
+
+        for line in lines {
+            if let Some(rest) = line.strip_prefix("Input:") {
+                if let Some((tag, path)) = rest.split_once(':') {
+                    if let Ok(tag) = tag.parse() {
+                        st.inputs.push((tag, path.to_owned()));
+                    }
+                }
+            } else if let Some(rest) = line.strip_prefix("Magnification:") {
+                st.magnification = rest.trim().parse().unwrap_or(1000.);
+            } else if let Some(rest) = line.strip_prefix("Unit:") {
+                st.unit = rest.trim().parse().unwrap_or(1.);
+            } else if let Some(rest) = line.strip_prefix("X Offset:") {
+                st.x_offset = rest.trim().parse().unwrap_or(0.);
+            } else if let Some(rest) = line.strip_prefix("Y Offset:") {
+                st.y_offset = rest.trim().parse().unwrap_or(0.);
+            } else if line.starts_with("Postamble:") {
+                break;
+            } else if let Some(rest) = line.strip_prefix('{') {
+                page = Some(Page {
+                    number: rest.trim().parse().unwrap_or(st.pages.len() as u32 + 1),
+                    records: Vec::new(),
+                });
+            } else if line.starts_with('}') {
+                if let Some(p) = page.take() {
+                    st.pages.push(p);
+                }
+            } else if let Some(ref mut p) = page {
+                if let Some(r) = Record::parse(line) {
+                    p.records.push(r);
+                }
+            }
+        }
+
+        if let Some(p) = page.take() {
+            st.pages.push(p);
+        }
+
+        Ok(st)
+    }
+
+    /// Get the paths of the source files mentioned in the data.
+    pub fn input_files(&self) -> Vec<&str> {
+        self.inputs.iter().map(|(_, p)| &p[..]).collect()
+    }
+
+    /// Get the number of pages described by the data.
+    pub fn n_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Find where a line of a source file ended up on the typeset pages.
+    ///
+    /// The *file* may be given as the full path recorded by the engine, or
+    /// as a trailing part of it, such as `chapter1.tex`. If nothing was
+    /// typeset from the given line, the nearest following line that was is
+    /// used instead, or failing that, the nearest preceding one. The result
+    /// has one rectangle for each page on which the line appears, covering
+    /// all of the material that came from it on that page.
+    pub fn forward(&self, file: &str, line: u32) -> Vec<PageLocation> {
+        let mut locations: Vec<PageLocation> = Vec::new();
+
+        for (page, recs) in self.locate(file, line) {
+            // Prefer the lines of text, then the bits and pieces within them,
+            // and only use the enclosing vertical boxes as a last resort.
+            let hboxes: Vec<_> = recs
+                .iter()
+                .filter(|r| matches!(r.kind, RecordKind::HBox | RecordKind::VoidHBox))
+                .collect();
+            let points: Vec<_> = recs.iter().filter(|r| !r.kind.is_box()).collect();
+
+            let chosen = if !hboxes.is_empty() {
+                hboxes
+            } else if !points.is_empty() {
+                points
+            } else {
+                recs.iter().collect()
+            };
+
+            let mut x0 = i64::MAX;
+            let mut x1 = i64::MIN;
+            let mut y0 = i64::MAX;
+            let mut y1 = i64::MIN;
+
+            for r in chosen {
+                let (a, b) = r.x_range();
+                let (c, d) = r.y_range();
+                x0 = x0.min(a);
+                x1 = x1.max(b);
+                y0 = y0.min(c);
+                y1 = y1.max(d);
+            }
+
+            let x = self.x_to_bp(x0);
+            let y = self.y_to_bp(y0);
+
+            locations.push(PageLocation {
+                page: page.number,
+                x,
+                y,
+                width: self.x_to_bp(x1) - x,
+                height: self.y_to_bp(y1) - y,
+            });
+        }
+
+        locations
+    }
+
+    /// Find the source line that produced the material at a position on a
+    /// typeset page.
+    ///
+    /// The position is in big points from the top left corner of the page.
+    /// The innermost box containing the position is found, and then the
+    /// record within it nearest to the position, which usually pins down the
+    /// line better than the box itself. If no box contains the position, the
+    /// nearest record on the page is used. Returns None if the page doesn't
+    /// exist or has nothing on it from any known source file.
+    pub fn inverse(&self, page: u32, x: f64, y: f64) -> Option<SourceLocation> {
+        let page = self.pages.iter().find(|p| p.number == page)?;
+        let h = self.bp_to_x(x);
+        let v = self.bp_to_y(y);
+
+        let known: Vec<_> = page
+            .records
+            .iter()
+            .filter(|r| self.input_path(r.tag).is_some())
+            .collect();
+
+        let container = known
+            .iter()
+            .filter(|r| r.kind.is_box() && r.contains(h, v))
+            .min_by_key(|r| r.area());
+
+        let best = match container {
+            Some(b) => known
+                .iter()
+                .filter(|r| !r.kind.is_box() && b.contains(r.h as i64, r.v as i64))
+                .min_by_key(|r| r.distance(h, v))
+                .unwrap_or(b),
+
+            None => known.iter().min_by_key(|r| r.distance(h, v))?,
+        };
+
+        Some(SourceLocation {
+            file: self.input_path(best.tag)?.to_owned(),
+            line: best.line,
+        })
+    }
+
+    /// Find the records for a source line, grouped by page. See
+    /// [`Self::forward`] for how the line is chosen.
+    fn locate(&self, file: &str, line: u32) -> Vec<(&Page, Vec<Record>)> {
+        let tags: Vec<u32> = self
+            .inputs
+            .iter()
+            .filter(|(_, p)| input_matches(p, file))
+            .map(|(t, _)| *t)
+            .collect();
+
+        let all = || {
+            self.pages.iter().flat_map(|p| {
+                p.records
+                    .iter()
+                    .filter(|r| tags.contains(&r.tag))
+                    .map(move |r| (p, r))
+            })
+        };
+
+        let following = all().map(|(_, r)| r.line).filter(|l| *l >= line).min();
+        let target = match following {
+            Some(l) => l,
+            None => match all().map(|(_, r)| r.line).max() {
+                Some(l) => l,
+                None => return Vec::new(),
+            },
+        };
+
+        let mut found: Vec<(&Page, Vec<Record>)> = Vec::new();
+
+        for (page, r) in all().filter(|(_, r)| r.line == target) {
+            match found.last_mut() {
+                Some((p, recs)) if std::ptr::eq(*p, page) => recs.push(*r),
+                _ => found.push((page, vec![*r])),
+            }
+        }
+
+        found
+    }
+
+    fn input_path(&self, tag: u32) -> Option<&str> {
+        self.inputs
+            .iter()
+            .rev()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, p)| &p[..])
+    }
+
+    /// The scale factor from the units of the file to scaled points.
+    fn scale(&self) -> f64 {
+        self.unit * self.magnification / 1000.
+    }
+
+    fn x_to_bp(&self, h: i64) -> f64 {
+        (h as f64 * self.scale() + self.x_offset) / SP_PER_BP + ORIGIN_BP
+    }
+
+    fn y_to_bp(&self, v: i64) -> f64 {
+        (v as f64 * self.scale() + self.y_offset) / SP_PER_BP + ORIGIN_BP
+    }
+
+    fn bp_to_x(&self, x: f64) -> i64 {
+        (((x - ORIGIN_BP) * SP_PER_BP - self.x_offset) / self.scale()).round() as i64
+    }
+
+    fn bp_to_y(&self, y: f64) -> i64 {
+        (((y - ORIGIN_BP) * SP_PER_BP - self.y_offset) / self.scale()).round() as i64
+    }
+
+    /// Convert a vertical position in the file to the scaled points of the
+    /// SPX file, as used by [`HtmlOutputFile`].
+    ///
+    /// Unlike the conversions to PDF coordinates, this doesn't apply the
+    /// magnification: SPX positions are in TeX’s unmagnified units, and
+    /// `spx2html` doesn't support `\mag`.
+    fn v_to_sp(&self, v: i32) -> i32 {
+        (v as f64 * self.unit).round() as i32
+    }
+}
+
+/// Whether an input path recorded by the engine matches a path given by the
+/// user, which may be relative.
+fn input_matches(input: &str, file: &str) -> bool {
+    let file = file.trim_start_matches("./");
+    input == file || Path::new(input).ends_with(file)
+}
+
+/// A mapping between source lines and the files of HTML output.
+///
+/// The HTML files written by `spx2html` are made from the pages of the SPX
+/// file, and the SyncTeX data record where each source line landed on those
+/// pages. Combining the two tells us which HTML file each line went into.
+#[derive(Clone, Debug)]
+pub struct HtmlSyncMap {
+    synctex: SyncTex,
+    files: Vec<HtmlOutputFile>,
+}
+
+impl HtmlSyncMap {
+    /// Create a mapping from the SyncTeX data for an SPX file, and the list
+    /// of HTML files that `spx2html` made from it.
+    pub fn new(synctex: SyncTex, files: Vec<HtmlOutputFile>) -> Self {
+        HtmlSyncMap { synctex, files }
+    }
+
+    /// Get the HTML files, in the order that they were written.
+    pub fn output_files(&self) -> &[HtmlOutputFile] {
+        &self.files[..]
+    }
+
+    /// Find the HTML files that a line of a source file ended up in. Their
+    /// paths are relative to the output directory. The line is chosen as in
+    /// [`SyncTex::forward`].
+    pub fn forward(&self, file: &str, line: u32) -> Vec<&str> {
+        let mut paths: Vec<&str> = Vec::new();
+
+        for (page, recs) in self.synctex.locate(file, line) {
+            for r in recs {
+                let v = self.synctex.v_to_sp(r.v);
+
+                if let Some(f) = self.files.iter().find(|f| f.contains(page.number, v)) {
+                    if !paths.contains(&&f.path[..]) {
+                        paths.push(&f.path);
+                    }
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// Find the source lines that went into an HTML file, given by its path
+    /// relative to the output directory. The result has one range for each
+    /// source file, in the order that the engine first read them. Returns an
+    /// empty list if there's no such file.
+    pub fn inverse(&self, html_path: &str) -> Vec<SourceRange> {
+        let html_path = html_path.trim_start_matches('/');
+        let file = match self.files.iter().find(|f| f.path == html_path) {
+            Some(f) => f,
+            None => return Vec::new(),
+        };
+
+        let mut ranges: HashMap<u32, (u32, u32)> = HashMap::new();
+
+        for page in &self.synctex.pages {
+            for r in &page.records {
+                if r.line == 0 || !file.contains(page.number, self.synctex.v_to_sp(r.v)) {
+                    continue;
+                }
+
+                let range = ranges.entry(r.tag).or_insert((r.line, r.line));
+                range.0 = range.0.min(r.line);
+                range.1 = range.1.max(r.line);
+            }
+        }
+
+        let mut result = Vec::new();
+
+        for (tag, path) in &self.synctex.inputs {
+            if let Some((first_line, last_line)) = ranges.remove(tag) {
+                result.push(SourceRange {
+                    file: path.clone(),
+                    first_line,
+                    last_line,
+                });
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    const SAMPLE: &str = "SyncTeX Version:1
+Input:1:/home/knuth/doc/./main.tex
+Input:2:/home/knuth/doc/./chapter.tex
+Output:pdf
+Magnification:1000
+Unit:1
+X Offset:0
+Y Offset:0
+Content:
+!200
+{1
+[1,5:0,0:30000000,40000000,0
+(1,5:0,1000000:30000000,600000,200000
+g1,5:5000000,1000000
+k1,6:9000000,1000000:100000
+)
+(2,3:0,3000000:30000000,600000,200000
+x2,4:2000000,3000000
+)
+]
+!400
+}1
+{2
+[1,9:0,0:30000000,40000000,0
+(1,9:0,1000000:30000000,600000,200000
+)
+]
+}2
+Postamble:
+Count:12
+";
+
+    fn sample() -> SyncTex {
+        SyncTex::parse(SAMPLE.as_bytes()).unwrap()
+    }
+
+    /// Convert a vertical position in scaled points to big points.
+    fn bp(sp: i32) -> f64 {
+        sp as f64 / SP_PER_BP + ORIGIN_BP
+    }
+
+    #[test]
+    fn parse_header_and_pages() {
+        let st = sample();
+        assert_eq!(
+            st.input_files(),
+            vec![
+                "/home/knuth/doc/./main.tex",
+                "/home/knuth/doc/./chapter.tex"
+            ]
+        );
+        assert_eq!(st.n_pages(), 2);
+        assert_eq!(st.pages[0].records.len(), 6);
+        assert_eq!(st.pages[0].records[2].kind, RecordKind::Glue);
+    }
+
+    #[test]
+    fn compressed() {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(SAMPLE.as_bytes()).unwrap();
+        let st = SyncTex::parse(&enc.finish().unwrap()).unwrap();
+        assert_eq!(st.n_pages(), 2);
+        assert!(SyncTex::parse(b"not synctex").is_err());
+    }
+
+    #[test]
+    fn forward() {
+        let st = sample();
+
+        let locs = st.forward("main.tex", 5);
+        assert_eq!(locs.len(), 1);
+        assert_eq!(locs[0].page, 1);
+        assert!((locs[0].y - bp(400000)).abs() < 1e-6);
+        assert!((locs[0].x - ORIGIN_BP).abs() < 1e-6);
+
+        // Line 7 typeset nothing, so we get line 9, on the second page.
+        let locs = st.forward("./main.tex", 7);
+        assert_eq!(locs.len(), 1);
+        assert_eq!(locs[0].page, 2);
+
+        // Past the end, we get the last line.
+        assert_eq!(st.forward("chapter.tex", 100)[0].page, 1);
+        assert!(st.forward("other.tex", 1).is_empty());
+    }
+
+    #[test]
+    fn inverse() {
+        let st = sample();
+
+        // In the first line box, nearest the glue.
+        let loc = st.inverse(1, ORIGIN_BP + 70., bp(1000000)).unwrap();
+        assert_eq!(loc.file, "/home/knuth/doc/./main.tex");
+        assert_eq!(loc.line, 5);
+
+        // Nearer the kern.
+        let loc = st.inverse(1, ORIGIN_BP + 140., bp(1000000)).unwrap();
+        assert_eq!(loc.line, 6);
+
+        // In the second line box.
+        let loc = st.inverse(1, ORIGIN_BP + 10., bp(3000000)).unwrap();
+        assert_eq!(loc.file, "/home/knuth/doc/./chapter.tex");
+        assert_eq!(loc.line, 4);
+
+        assert!(st.inverse(3, 100., 100.).is_none());
+    }
+
+    #[test]
+    fn html() {
+        let files = vec![
+            HtmlOutputFile {
+                path: "index.html".to_owned(),
+                first_page: 1,
+                start_y: 0,
+                last_page: 1,
+                end_y: 2000000,
+            },
+            HtmlOutputFile {
+                path: "chapter/index.html".to_owned(),
+                first_page: 1,
+                start_y: 2000000,
+                last_page: 2,
+                end_y: i32::MAX,
+            },
+        ];
+        let map = HtmlSyncMap::new(sample(), files);
+
+        assert_eq!(map.forward("main.tex", 5), vec!["index.html"]);
+        assert_eq!(map.forward("chapter.tex", 3), vec!["chapter/index.html"]);
+        assert_eq!(map.forward("main.tex", 9), vec!["chapter/index.html"]);
+
+        assert_eq!(
+            map.inverse("chapter/index.html"),
+            vec![
+                SourceRange {
+                    file: "/home/knuth/doc/./main.tex".to_owned(),
+                    first_line: 9,
+                    last_line: 9,
+                },
+                SourceRange {
+                    file: "/home/knuth/doc/./chapter.tex".to_owned(),
+                    first_line: 3,
+                    last_line: 4,
+                },
+            ]
+        );
+        assert!(map.inverse("missing.html").is_empty());
+
+        // The magnification doesn't change positions in the SPX file.
+        let magnified = SAMPLE.replace("Magnification:1000", "Magnification:2000");
+        let map = HtmlSyncMap::new(
+            SyncTex::parse(magnified.as_bytes()).unwrap(),
+            map.output_files().to_vec(),
+        );
+        assert_eq!(map.forward("main.tex", 5), vec!["index.html"]);
+        assert_eq!(map.forward("chapter.tex", 3), vec!["chapter/index.html"]);
+    }
+}
@@ -0,0 +1,528 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Extracting structured diagnostics from TeX log files.
+//!
+//! TeX reports errors and warnings in its `.log` file in a format meant for
+//! humans, interleaved with the names of the files being read. The
+//! [`parse_log`] function picks out the interesting bits — errors, overfull
+//! and underfull boxes, and LaTeX and package warnings — along with the file
+//! and line that they came from, when that can be figured out. The parsing
+//! is heuristic: TeX logs have no formal structure, so some diagnostics may
+//! be attributed to the wrong file, or missed entirely.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::fmt;
+
+/// TeX wraps the lines in its log at this many characters.
+const MAX_PRINT_LINE: usize = 79;
+
+/// How many lines after an error message we look for the line number.
+const MAX_ERROR_CONTEXT_LINES: usize = 16;
+
+/// The kind of a [`LogDiagnostic`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum DiagnosticKind {
+    /// An error, reported by TeX with a line starting with `!`.
+    Error,
+
+    /// A warning from LaTeX, a class, or a package.
+    Warning,
+
+    /// A reference to a label that isn't defined.
+    UndefinedReference,
+
+    /// A citation of a bibliography entry that isn't defined.
+    UndefinedCitation,
+
+    /// A box that is too full.
+    OverfullBox,
+
+    /// A box that is not full enough.
+    UnderfullBox,
+}
+
+/// One diagnostic extracted from a TeX log file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LogDiagnostic {
+    /// What kind of diagnostic this is.
+    pub kind: DiagnosticKind,
+
+    /// The file that was being read when the diagnostic was issued, if it
+    /// could be determined. This is the name as TeX printed it, such as
+    /// `./chapter1.tex`.
+    pub file: Option<String>,
+
+    /// The line number that the diagnostic refers to, if known.
+    pub line: Option<u32>,
+
+    /// The message, joined onto a single line.
+    pub message: String,
+
+    /// Context lines that TeX printed along with an error, such as the text
+    /// of the offending line.
+    pub context: Vec<String>,
+}
+
+impl LogDiagnostic {
+    /// Whether this diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.kind == DiagnosticKind::Error
+    }
+
+    /// Whether this diagnostic is about an undefined reference or citation.
+    pub fn is_undefined_reference(&self) -> bool {
+        matches!(
+            self.kind,
+            DiagnosticKind::UndefinedReference | DiagnosticKind::UndefinedCitation
+        )
+    }
+
+    /// Get the label or citation key that an undefined reference or citation
+    /// refers to.
+    ///
+    /// This returns `None` for other kinds of diagnostics, and for the
+    /// summary warnings that LaTeX issues at the end of a run.
+    pub fn undefined_key(&self) -> Option<&str> {
+        if !self.is_undefined_reference() {
+            return None;
+        }
+
+        let (_, rest) = self.message.split_once(['`', '\'', '"'])?;
+        let (key, _) = rest.split_once(['\'', '"'])?;
+        Some(key)
+    }
+}
+
+impl fmt::Display for LogDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: ", file, line)?,
+            (Some(file), None) => write!(f, "{}: ", file)?,
+            (None, Some(line)) => write!(f, "line {}: ", line)?,
+            (None, None) => {}
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Extract the diagnostics from the text of a TeX log file.
+pub fn parse_log(text: &str) -> Vec<LogDiagnostic> {
+    let lines = unwrap_lines(text);
+    let mut diagnostics = Vec::new();
+    let mut files = FileStack::default();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = &lines[i];
+        i += 1;
+
+        if let Some(message) = line.strip_prefix("! ") {
+            let mut diag = LogDiagnostic {
+                kind: DiagnosticKind::Error,
+                file: files.current(),
+                line: None,
+                message: message.to_owned(),
+                context: Vec::new(),
+            };
+            i = parse_error_context(&lines, i, &mut diag);
+            diagnostics.push(diag);
+            continue;
+        }
+
+        if let Some((file, line_num, message)) = split_file_line_error(line) {
+            let mut diag = LogDiagnostic {
+                kind: DiagnosticKind::Error,
+                file: Some(file.to_owned()),
+                line: Some(line_num),
+                message: message.to_owned(),
+                context: Vec::new(),
+            };
+            i = parse_error_context(&lines, i, &mut diag);
+            diag.line = Some(line_num);
+            diagnostics.push(diag);
+            continue;
+        }
+
+        let box_kind = if line.starts_with("Overfull \\") {
+            Some(DiagnosticKind::OverfullBox)
+        } else if line.starts_with("Underfull \\") {
+            Some(DiagnosticKind::UnderfullBox)
+        } else {
+            None
+        };
+
+        if let Some(kind) = box_kind {
+            diagnostics.push(LogDiagnostic {
+                kind,
+                file: files.current(),
+                line: box_line_number(line),
+                message: line.to_owned(),
+                context: Vec::new(),
+            });
+            continue;
+        }
+
+        if let Some((source, first)) = split_warning(line) {
+            let mut message = first.to_owned();
+
+            // Warnings continue until the next blank line. Packages indent
+            // the continuation lines with their name in parentheses.
+            while i < lines.len() && !lines[i].trim().is_empty() {
+                let mut cont = lines[i].trim();
+
+                if let Some(rest) = cont.strip_prefix(&format!("({})", source)) {
+                    cont = rest.trim();
+                }
+
+                message.push(' ');
+                message.push_str(cont);
+                i += 1;
+            }
+
+            diagnostics.push(LogDiagnostic {
+                kind: warning_kind(&message),
+                file: files.current(),
+                line: input_line_number(&message),
+                message,
+                context: Vec::new(),
+            });
+            continue;
+        }
+
+        files.scan(line);
+    }
+
+    // At the end of the run, LaTeX summarizes with warnings like "There were
+    // undefined references." These are only worth keeping if the individual
+    // warnings didn't make it into the log.
+    let has_individual = |kind| {
+        diagnostics
+            .iter()
+            .any(|d: &LogDiagnostic| d.kind == kind && d.undefined_key().is_some())
+    };
+    let have_refs = has_individual(DiagnosticKind::UndefinedReference);
+    let have_cites = has_individual(DiagnosticKind::UndefinedCitation);
+
+    diagnostics.retain(|d| {
+        d.undefined_key().is_some()
+            || !match d.kind {
+                DiagnosticKind::UndefinedReference => have_refs,
+                DiagnosticKind::UndefinedCitation => have_cites,
+                _ => false,
+            }
+    });
+
+    diagnostics
+}
+
+/// Rejoin lines that TeX has wrapped.
+fn unwrap_lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        current.push_str(line);
+
+        if line.chars().count() != MAX_PRINT_LINE {
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Read the lines following an error message, up to the `l.NNN` line that
+/// gives the line number. Returns the index of the first line after the
+/// error.
+fn parse_error_context(lines: &[String], start: usize, diag: &mut LogDiagnostic) -> usize {
+    let end = lines.len().min(start + MAX_ERROR_CONTEXT_LINES);
+
+    for i in start..end {
+        let line = &lines[i];
+
+        if line.starts_with("! ") {
+            break;
+        }
+
+        if let Some((line_num, text)) = split_line_marker(line) {
+            diag.line = Some(line_num);
+            diag.context.push(text.to_owned());
+
+            // TeX prints the rest of the offending line on the next line,
+            // indented to just past the point of the error.
+            if let Some(next) = lines.get(i + 1) {
+                if !next.trim().is_empty() {
+                    diag.context.push(next.trim().to_owned());
+                    return i + 2;
+                }
+            }
+
+            return i + 1;
+        }
+
+        if !line.trim().is_empty() {
+            diag.context.push(line.to_owned());
+        }
+    }
+
+    // No line number. TeX didn't print any context we can be sure of.
+    diag.context.clear();
+    start
+}
+
+/// Parse a line like `l.12 \foo`.
+fn split_line_marker(line: &str) -> Option<(u32, &str)> {
+    let rest = line.strip_prefix("l.")?;
+    let n_digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let line_num = rest[..n_digits].parse().ok()?;
+    Some((line_num, rest[n_digits..].trim()))
+}
+
+/// Parse a `-file-line-error` style error like `./doc.tex:12: Undefined
+/// control sequence.`
+fn split_file_line_error(line: &str) -> Option<(&str, u32, &str)> {
+    let mut search_from = 0;
+
+    while let Some(pos) = line[search_from..].find(':') {
+        let colon = search_from + pos;
+        let rest = &line[colon + 1..];
+        let n_digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+
+        if n_digits > 0 && rest[n_digits..].starts_with(": ") {
+            let file = &line[..colon];
+
+            if file.is_empty() || file.contains(' ') || !file.contains('.') {
+                return None;
+            }
+
+            let line_num = rest[..n_digits].parse().ok()?;
+            return Some((file, line_num, &rest[n_digits + 2..]));
+        }
+
+        search_from = colon + 1;
+    }
+
+    None
+}
+
+/// Parse the start of a warning like `LaTeX Warning: ...` or `Package hyperref
+/// Warning: ...`, returning the source of the warning (`LaTeX` or the package
+/// name) and the first line of the message.
+fn split_warning(line: &str) -> Option<(&str, &str)> {
+    let (head, message) = line.split_once(" Warning: ")?;
+
+    if head == "LaTeX" || head == "LaTeX Font" {
+        return Some((head, message.trim()));
+    }
+
+    let source = head
+        .strip_prefix("Package ")
+        .or_else(|| head.strip_prefix("Class "))?;
+
+    if source.is_empty() || source.contains(' ') {
+        return None;
+    }
+
+    Some((source, message.trim()))
+}
+
+/// Classify a warning based on its message.
+fn warning_kind(message: &str) -> DiagnosticKind {
+    if !message.contains("undefined") {
+        DiagnosticKind::Warning
+    } else if message.starts_with("There were undefined references") {
+        DiagnosticKind::UndefinedReference
+    } else if message.starts_with("There were undefined citations") {
+        DiagnosticKind::UndefinedCitation
+    } else if message.starts_with("Reference `") || message.starts_with("Reference '") {
+        DiagnosticKind::UndefinedReference
+    } else if message.starts_with("Citation `")
+        || message.starts_with("Citation '")
+        || message.starts_with("Citation \"")
+    {
+        DiagnosticKind::UndefinedCitation
+    } else {
+        DiagnosticKind::Warning
+    }
+}
+
+/// Find the `on input line 12` at the end of a LaTeX warning.
+fn input_line_number(message: &str) -> Option<u32> {
+    let (_, rest) = message.rsplit_once("input line ")?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Find the line number in a message like `Overfull \hbox (1.0pt too wide) in
+/// paragraph at lines 10--12` or `Underfull \vbox ... detected at line 20`.
+fn box_line_number(message: &str) -> Option<u32> {
+    let rest = match message.rsplit_once(" at lines ") {
+        Some((_, r)) => r,
+        None => message.rsplit_once(" at line ")?.1,
+    };
+
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Tracks which file TeX is reading from the parentheses in the log. TeX
+/// prints `(` and the file name when it starts reading a file, and `)` when
+/// it's done with it.
+#[derive(Default)]
+struct FileStack {
+    /// Names of the files being read, or None for parentheses that didn't
+    /// seem to introduce a file name, which we still need to balance.
+    stack: Vec<Option<String>>,
+}
+
+impl FileStack {
+    fn current(&self) -> Option<String> {
+        self.stack.iter().rev().find_map(|f| f.clone())
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut rest = line;
+
+        while let Some(pos) = rest.find(['(', ')']) {
+            if rest[pos..].starts_with(')') {
+                self.stack.pop();
+                rest = &rest[pos + 1..];
+                continue;
+            }
+
+            let after = &rest[pos + 1..];
+            let len = after
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(after.len());
+            let token = &after[..len];
+
+            if looks_like_file_name(token) {
+                self.stack.push(Some(token.to_owned()));
+            } else {
+                self.stack.push(None);
+            }
+
+            rest = &after[len..];
+        }
+    }
+}
+
+fn looks_like_file_name(token: &str) -> bool {
+    if token.starts_with("./") || token.starts_with('/') || token.starts_with("../") {
+        return true;
+    }
+
+    match token.rsplit_once('.') {
+        Some((stem, ext)) => {
+            !stem.is_empty() && !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r"This is XeTeX, Version 3.141592653-2.6-0.999993 (Tectonic) (preloaded format=latex 2022.1.1)
+entering extended mode
+(./doc.tex
+LaTeX2e <2021-11-15> patch level 1
+(/bundle/article.cls
+Document Class: article 2021/10/04 v1.4n Standard LaTeX document class
+(/bundle/size10.clo))
+(./doc.aux)
+(./chapter.tex
+! Undefined control sequence.
+l.3 Some \badmacro
+                   text here.
+The control sequence at the end of the top line
+of your error message was never \def'ed.
+
+)
+Overfull \hbox (12.5pt too wide) in paragraph at lines 10--12
+[]\TU/lmr/m/n/10 Some wide text
+
+LaTeX Warning: Reference `fig:one' on page 1 undefined on input line 14.
+
+
+Package natbib Warning: Citation `knuth84' on page 1 undefined on input line 15
+.
+
+
+Package hyperref Warning: Token not allowed in a PDF string (Unicode):
+(hyperref)                removing `math shift' on input line 20.
+
+[1] (./doc.aux)
+
+LaTeX Warning: There were undefined references.
+
+ )
+";
+
+    #[test]
+    fn parse_sample_log() {
+        let diags = parse_log(LOG);
+        assert_eq!(diags.len(), 5);
+
+        assert_eq!(diags[0].kind, DiagnosticKind::Error);
+        assert_eq!(diags[0].file.as_deref(), Some("./chapter.tex"));
+        assert_eq!(diags[0].line, Some(3));
+        assert_eq!(diags[0].message, "Undefined control sequence.");
+        assert_eq!(diags[0].context, vec!["Some \\badmacro", "text here."]);
+        assert_eq!(
+            diags[0].to_string(),
+            "./chapter.tex:3: Undefined control sequence."
+        );
+
+        assert_eq!(diags[1].kind, DiagnosticKind::OverfullBox);
+        assert_eq!(diags[1].file.as_deref(), Some("./doc.tex"));
+        assert_eq!(diags[1].line, Some(10));
+
+        assert_eq!(diags[2].kind, DiagnosticKind::UndefinedReference);
+        assert_eq!(diags[2].line, Some(14));
+
+        assert_eq!(diags[2].undefined_key(), Some("fig:one"));
+
+        assert_eq!(diags[3].kind, DiagnosticKind::UndefinedCitation);
+        assert_eq!(diags[3].line, Some(15));
+        assert_eq!(diags[3].undefined_key(), Some("knuth84"));
+
+        assert_eq!(diags[4].kind, DiagnosticKind::Warning);
+        assert_eq!(diags[4].line, Some(20));
+        assert_eq!(
+            diags[4].message,
+            "Token not allowed in a PDF string (Unicode): removing `math shift' on input line 20."
+        );
+    }
+
+    #[test]
+    fn file_line_errors() {
+        let diags = parse_log("./doc.tex:7: LaTeX Error: Missing \\begin{document}.\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].kind, DiagnosticKind::Error);
+        assert_eq!(diags[0].file.as_deref(), Some("./doc.tex"));
+        assert_eq!(diags[0].line, Some(7));
+        assert_eq!(diags[0].message, "LaTeX Error: Missing \\begin{document}.");
+    }
+
+    #[test]
+    fn undefined_summaries() {
+        let diags = parse_log(
+            "(./doc.tex [1] (./doc.aux)\n\nLaTeX Warning: There were undefined references.\n\n)\n",
+        );
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].kind, DiagnosticKind::UndefinedReference);
+        assert!(diags[0].is_undefined_reference());
+        assert_eq!(diags[0].undefined_key(), None);
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Exercise the batch runner, which forks worker processes.
+//!
+//! This is its own test executable, with just one test, so that no other
+//! test threads are running engines (and holding the global engine lock)
+//! when the workers are forked.
+
+use tectonic::{batch::BatchRunner, driver::ProcessingSessionBuilder, status::NoopStatusBackend};
+
+mod util;
+
+#[test]
+fn batch_of_documents() {
+    util::set_test_root();
+    util::ensure_plain_format().unwrap();
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_batch_test")
+        .tempdir()
+        .unwrap();
+
+    let mut runner = BatchRunner::default();
+    runner.max_workers(2);
+
+    let inputs: &[(&str, &[u8])] = &[
+        ("first", b"first\\bye\n"),
+        ("second", b"second\\bye\n"),
+        ("third", b"third\\bye\n"),
+        ("broken", b"\\undefinedcontrolsequence\\bye\n"),
+    ];
+
+    for (name, text) in inputs {
+        let mut sb = ProcessingSessionBuilder::default();
+        sb.primary_input_buffer(text)
+            .tex_input_name(&format!("{}.tex", name))
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .output_dir(tempdir.path())
+            .bundle(Box::new(util::TestBundle::default()));
+        runner.add_job(name, sb);
+    }
+
+    let mut status = NoopStatusBackend::default();
+    let results = runner.run(&mut status);
+
+    let names: Vec<_> = results.iter().map(|r| &r.name[..]).collect();
+    assert_eq!(names, vec!["first", "second", "third", "broken"]);
+
+    for r in &results[..3] {
+        assert!(r.succeeded(), "`{}` failed: {:?}", r.name, r.error);
+        assert!(tempdir.path().join(format!("{}.pdf", r.name)).exists());
+    }
+
+    assert!(!results[3].succeeded());
+    assert!(!tempdir.path().join("broken.pdf").exists());
+}
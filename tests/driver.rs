@@ -9,14 +9,507 @@
 //! enable the reproducibility options used in the `tex-outputs` test rig.
 
 use tectonic::config::PersistentConfig;
-use tectonic::driver::ProcessingSessionBuilder;
+use tectonic::driver::{
+    Pass, PassContext, PassKind, PassOutcome, PassPhase, ProcessingSessionBuilder, RerunPolicy,
+};
+use tectonic::errors::Result;
 use tectonic::status::termcolor::TermcolorStatusBackend;
-use tectonic::status::ChatterLevel;
+use tectonic::status::{ChatterLevel, StatusBackend};
+use tectonic_bridge_core::{CancellationToken, SandboxSettings, SecuritySettings, SecurityStance};
 
 mod util;
 
 // Keep these alphabetized.
 
+/// A pass that records the size of the PDF output.
+struct PdfSizePass;
+
+impl Pass for PdfSizePass {
+    fn name(&self) -> String {
+        "pdfsize".to_owned()
+    }
+
+    fn run(
+        &mut self,
+        ctx: &mut PassContext<'_>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<PassOutcome> {
+        let pdf_path = ctx.pdf_path().to_owned();
+        let pdf = ctx.read_file(&pdf_path, status)?.expect("no PDF output");
+        ctx.write_file("pdfsize.txt", pdf.len().to_string().as_bytes(), status)?;
+        Ok(PassOutcome::Spotless)
+    }
+}
+
+/// Run a document that should be stopped early, returning the messages of
+/// the resulting error.
+fn stopped_document(text: &[u8], setup: impl FnOnce(&mut ProcessingSessionBuilder)) -> Vec<String> {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(text)
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .bundle(Box::new(util::TestBundle::default()));
+    setup(&mut pbuilder);
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    let err = session
+        .run(&mut status)
+        .expect_err("processing session should have been stopped");
+
+    assert!(!tempdir.path().join("texput.pdf").exists());
+    err.iter().map(|e| e.to_string()).collect()
+}
+
+/// Test that a session with a cancelled token doesn't get anywhere.
+#[test]
+fn cancellation() {
+    let token = CancellationToken::new();
+    token.cancel();
+
+    let messages = stopped_document(b"A\\bye\n", |pb| {
+        pb.cancellation_token(token);
+    });
+    assert!(
+        messages
+            .iter()
+            .any(|m| m.contains("processing was cancelled")),
+        "unexpected error: {:?}",
+        messages
+    );
+}
+
+/// Test that a custom format can be generated from initex source, and that
+/// it's cached under a name identifying that source.
+#[test]
+fn custom_format() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\housestyle\\bye\n")
+        .tex_input_name("texput.tex")
+        .format_name("house")
+        .format_source_buffer(b"\\input plain \\def\\housestyle{House}\\dump\n")
+        .format_cache_path(tempdir.path())
+        .output_dir(tempdir.path())
+        .bundle(Box::new(util::TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    assert!(tempdir.path().join("texput.pdf").exists());
+
+    let formats: Vec<_> = std::fs::read_dir(tempdir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|n| n.ends_with(".fmt"))
+        .collect();
+    assert_eq!(formats.len(), 1);
+    assert!(formats[0].contains("-house-"));
+}
+
+#[test]
+fn custom_pass() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_path(util::test_path(&["tex-outputs", "the_letter_a.tex"]))
+        .tex_input_name("the_letter_a.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .bundle(Box::new(util::TestBundle::default()))
+        .add_pass(PassPhase::AfterOutput, Box::new(PdfSizePass));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let pdf_size = std::fs::metadata(tempdir.path().join("the_letter_a.pdf"))
+        .unwrap()
+        .len();
+    let recorded = std::fs::read_to_string(tempdir.path().join("pdfsize.txt")).unwrap();
+    assert_eq!(recorded, pdf_size.to_string());
+
+    let report = session.build_report();
+    assert!(report
+        .passes
+        .iter()
+        .any(|p| p.detail.as_deref() == Some("pdfsize")));
+}
+
+/// Test that an up-to-date incremental build still writes out the Makefile
+/// rules, which build tools may delete after reading them.
+#[test]
+fn incremental_makefile_rules() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+    let input_path = tempdir.path().join("doc.tex");
+    std::fs::write(&input_path, "A\\bye\n").unwrap();
+    let rules_path = tempdir.path().join("doc.d");
+
+    let mut rules = Vec::new();
+    let mut n_passes = Vec::new();
+
+    for _ in 0..2 {
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_path(&input_path)
+            .tex_input_name("doc.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .output_dir(tempdir.path())
+            .makefile_output_path(&rules_path)
+            .incremental_state_dir(tempdir.path().join("state"))
+            .bundle(Box::new(util::TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session
+            .run(&mut status)
+            .expect("failed to execute processing session");
+
+        // Like Ninja, consume the rules.
+        rules.push(std::fs::read_to_string(&rules_path).unwrap());
+        std::fs::remove_file(&rules_path).unwrap();
+        n_passes.push(session.build_report().passes.len());
+    }
+
+    assert!(n_passes[0] > 0);
+    assert_eq!(n_passes[1], 0);
+    assert!(rules[0].contains("doc.tex"));
+    assert_eq!(rules[0], rules[1]);
+}
+
+/// Test that one job can read the `.aux` file of another one that comes after
+/// it, as with the `xr` package.
+#[test]
+fn multiple_jobs() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\def\\x{?}\\openin1=second.aux \\ifeof1 \\else \\closein1 \\input second.aux \\fi\n\
+              A\\x\\bye\n",
+        )
+        .tex_input_name("first.tex")
+        .add_job_buffer(
+            "second.tex",
+            b"\\immediate\\openout1=second.aux\n\
+              \\immediate\\write1{\\def\\noexpand\\x{B}}\n\
+              \\immediate\\closeout1\n\
+              B\\bye\n",
+        )
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .bundle(Box::new(util::TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    assert!(tempdir.path().join("first.pdf").exists());
+    assert!(tempdir.path().join("second.pdf").exists());
+
+    // The first job is processed again once `second.aux` exists.
+    let report = session.build_report();
+    let n_tex = report
+        .passes
+        .iter()
+        .filter(|p| p.kind == PassKind::Tex)
+        .count();
+    assert_eq!(n_tex, 3);
+}
+
+/// Run a document that rewrites a file that it reads on every pass, returning
+/// the number of TeX passes and the files that didn't converge.
+fn rerun_document(text: &[u8], policy: Option<RerunPolicy>) -> (usize, Vec<String>) {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(text)
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .bundle(Box::new(util::TestBundle::default()));
+
+    if let Some(policy) = policy {
+        pbuilder.rerun_policy(policy);
+    }
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let n_tex = session
+        .build_report()
+        .passes
+        .iter()
+        .filter(|p| p.kind == PassKind::Tex)
+        .count();
+    (n_tex, session.unconverged_files().to_vec())
+}
+
+/// Test that TeX stops being rerun after the maximum number of passes, or
+/// once a file oscillates between two versions.
+#[test]
+fn rerun_limits() {
+    // This document never converges, since it writes out a counter that it
+    // increments on every pass.
+    const COUNTER: &[u8] = b"\\newcount\\n\n\
+        \\openin1=count.tex \\ifeof1 \\else \\closein1 \\input count.tex \\fi\n\
+        \\advance\\n by 1\n\
+        \\immediate\\openout1=count.tex\n\
+        \\immediate\\write1{\\global\\n=\\the\\n}\n\
+        \\immediate\\closeout1\n\
+        A\\bye\n";
+
+    let count_tex = vec!["count.tex".to_owned()];
+
+    // The first pass plus six reruns.
+    assert_eq!(rerun_document(COUNTER, None), (7, count_tex.clone()));
+
+    let mut policy = RerunPolicy::default();
+    policy.max_passes(3);
+    assert_eq!(rerun_document(COUNTER, Some(policy)), (3, count_tex));
+
+    // This one flips between two versions of its file, which is noticed on
+    // the third pass.
+    const FLIP: &[u8] = b"\\def\\x{A}\n\
+        \\openin1=flip.tex \\ifeof1 \\else \\closein1 \\input flip.tex \\fi\n\
+        \\immediate\\openout1=flip.tex\n\
+        \\immediate\\write1{\\if A\\x \\def\\noexpand\\x{B}\\else \\def\\noexpand\\x{A}\\fi}\n\
+        \\immediate\\closeout1\n\
+        A\\bye\n";
+
+    assert_eq!(rerun_document(FLIP, None), (3, vec!["flip.tex".to_owned()]));
+}
+
+/// Test that the intermediates cache only keeps the files produced by the
+/// latest session, without touching files that it didn't save.
+#[test]
+fn reuse_intermediates() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+    let cache_dir = tempdir.path().join("cache");
+    std::fs::create_dir(&cache_dir).unwrap();
+    std::fs::write(cache_dir.join("unrelated.txt"), "keep me").unwrap();
+
+    for name in &["first", "second"] {
+        let text = format!(
+            "\\immediate\\openout1={0}.aux\n\
+             \\immediate\\write1{{\\relax}}\n\
+             \\immediate\\closeout1\n\
+             \\input {0}.aux\n\
+             A\\bye\n",
+            name
+        );
+
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_buffer(text.as_bytes())
+            .tex_input_name("texput.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .output_dir(tempdir.path())
+            .intermediates_cache_dir(&cache_dir)
+            .bundle(Box::new(util::TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session
+            .run(&mut status)
+            .expect("failed to execute processing session");
+    }
+
+    assert!(cache_dir.join("second.aux").exists());
+    assert!(!cache_dir.join("first.aux").exists());
+    assert!(cache_dir.join("unrelated.txt").exists());
+
+    let manifest = std::fs::read_to_string(cache_dir.join("manifest.txt")).unwrap();
+    assert!(manifest.lines().any(|l| l == "second.aux"));
+    assert!(!manifest.lines().any(|l| l == "first.aux"));
+}
+
+/// Test that shell-escape commands are run through the sandbox, which only
+/// runs the allowed programs, and only by name.
+#[cfg(unix)]
+#[test]
+fn shell_escape_sandbox() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut sandbox = SandboxSettings::default();
+    sandbox
+        .scrub_environment(true)
+        .allow_program("touch")
+        .time_limit(std::time::Duration::from_secs(30));
+
+    let mut security = SecuritySettings::new(SecurityStance::MaybeAllowInsecures);
+    security.set_sandbox(sandbox);
+
+    let mut pbuilder = ProcessingSessionBuilder::new_with_security(security);
+    pbuilder
+        .primary_input_buffer(
+            b"\\immediate\\write18{touch made-by-shell.tex}\n\
+              \\input made-by-shell\n\
+              \\immediate\\write18{/usr/bin/touch refused.tex}\n\
+              \\immediate\\write18{sh -c 'touch refused.tex'}\n\
+              \\openin1=refused.tex \\ifeof1 \\else \\closein1 \\sandboxwasescaped \\fi\n\
+              A\\bye\n",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .shell_escape_with_temp_dir()
+        .bundle(Box::new(util::TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    assert!(tempdir.path().join("texput.pdf").exists());
+}
+
+/// Test that the SyncTeX data can be queried in both directions.
+#[test]
+fn synctex() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"first\\par\nsecond\\par\n\\bye\n")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .synctex(true)
+        .bundle(Box::new(util::TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let synctex = session
+        .synctex()
+        .expect("couldn't parse SyncTeX data")
+        .expect("no SyncTeX data");
+    assert_eq!(synctex.n_pages(), 1);
+
+    let locs = synctex.forward("texput.tex", 2);
+    assert_eq!(locs.len(), 1);
+    assert_eq!(locs[0].page, 1);
+
+    let src = synctex
+        .inverse(1, locs[0].x + 1., locs[0].y + locs[0].height / 2.)
+        .expect("no source found");
+    assert!(src.file.ends_with("texput.tex"));
+    assert_eq!(src.line, 2);
+}
+
 #[test]
 fn the_letter_a() {
     util::set_test_root();
@@ -50,3 +543,20 @@ fn the_letter_a() {
         .run(&mut status)
         .expect("failed to execute processing session");
 }
+
+/// Test that a document that loops forever, without doing any I/O, is
+/// stopped by the timeout.
+#[test]
+fn timeout() {
+    let started = std::time::Instant::now();
+
+    let messages = stopped_document(b"\\def\\x{\\x}\\x\n", |pb| {
+        pb.timeout(std::time::Duration::from_secs(2));
+    });
+    assert!(
+        messages.iter().any(|m| m.contains("processing timed out")),
+        "unexpected error: {:?}",
+        messages
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(60));
+}
@@ -416,6 +416,20 @@ a
     success_or_panic(&output);
 }
 
+#[test]
+fn logreq_tool_not_allowed() {
+    let tex = format!(
+        "{}{}",
+        BIBER_TRIGGER_TEX.replace("<binary>biber</binary>", "<binary>notbiber</binary>"),
+        "\\bye"
+    );
+    let output = run_with_biber("failure", &tex);
+    success_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`notbiber`, which is not an allowed tool"));
+}
+
 /// #844: biber input with absolute path blows away the file
 ///
 /// We need to create a separate temporary directory to see if the abspath input
@@ -708,6 +722,80 @@ fn v2_build_multiple_outputs() {
     success_or_panic(&output);
 }
 
+/// Test that `-X cache gc` only deletes the formats of superseded bundles,
+/// unless it's given a size limit.
+#[test]
+fn v2_cache_gc() {
+    let formats = tempfile::Builder::new()
+        .prefix("tectonic_formats")
+        .tempdir()
+        .unwrap();
+    let cache = tempfile::Builder::new()
+        .prefix("tectonic_cache")
+        .tempdir()
+        .unwrap();
+
+    let current = "1".repeat(64);
+    let superseded = "2".repeat(64);
+    let unknown = "3".repeat(64);
+
+    // The cache has seen two versions of one bundle, and the URL now leads to
+    // the newer one. The third bundle never went through the cache.
+    fs::create_dir_all(cache.path().join("urls")).unwrap();
+    fs::create_dir_all(cache.path().join("indexes")).unwrap();
+    fs::write(cache.path().join("urls").join("bundle"), &current).unwrap();
+
+    for digest in &[&current, &superseded] {
+        fs::write(
+            cache.path().join("indexes").join(format!("{}.txt", digest)),
+            "",
+        )
+        .unwrap();
+    }
+
+    for digest in &[&current, &superseded, &unknown] {
+        let name = format!("{}-plain-{}.fmt", digest, tectonic::FORMAT_SERIAL);
+        fs::write(formats.path().join(name), vec![0; 100]).unwrap();
+    }
+
+    let run = |args: &[&str]| {
+        // In test mode, the format cache is the test root.
+        let mut command = prep_tectonic(formats.path(), args);
+        command
+            .env(tectonic::test_util::TEST_ROOT_ENV_VAR, formats.path())
+            .env("TECTONIC_CACHE_DIR", cache.path());
+        println!("running {:?}", command);
+        command.output().expect("tectonic failed to start")
+    };
+
+    let remaining = || {
+        let mut names: Vec<_> = fs::read_dir(formats.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy()[..64].to_owned())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let output = run(&["-X", "cache", "gc"]);
+    success_or_panic(&output);
+    assert_eq!(remaining(), vec![current.clone(), unknown.clone()]);
+
+    let output = run(&["-X", "show", "formats"]);
+    success_or_panic(&output);
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(listing.lines().count(), 2);
+    assert!(listing.contains(&unknown));
+
+    let output = run(&["-X", "cache", "gc", "--max-size", "150"]);
+    success_or_panic(&output);
+    assert_eq!(remaining().len(), 1);
+
+    let output = run(&["-X", "cache", "gc", "--max-size", "lots"]);
+    error_or_panic(&output);
+    assert_eq!(remaining().len(), 1);
+}
+
 #[test]
 #[cfg(feature = "serialization")]
 fn v2_dump_basic() {
@@ -896,6 +984,333 @@ fn extra_search_paths() {
     error_or_panic(&output);
 }
 
+/// Test that untrusted documents can't read files from outside of their
+/// directory
+#[test]
+fn untrusted_reads_confined() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+    std::fs::write(tempdir.path().join("secret.tex"), "secret\n").unwrap();
+    let workdir = tempdir.path().join("subdirectory");
+
+    let output = run_tectonic_with_stdin(&workdir, &[&fmt_arg, "-"], "\\input ../secret\n\\bye");
+    success_or_panic(&output);
+
+    let output = run_tectonic_with_stdin(
+        &workdir,
+        &[&fmt_arg, "-", "--untrusted"],
+        "\\input ../secret\n\\bye",
+    );
+    error_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("because it is outside of the directory"));
+}
+
+/// Test that a security policy file can stop writes outside the output
+/// directory, and can turn features back on that `--untrusted` disables.
+#[cfg(feature = "serialization")]
+#[test]
+fn security_policy_file() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+    std::fs::create_dir(tempdir.path().join("out")).unwrap();
+
+    let policy = tempdir.path().join("policy.toml");
+    std::fs::write(
+        &policy,
+        "writes_outside_output_dir = false\nextra_search_paths = true\n",
+    )
+    .unwrap();
+    let policy_arg = format!("--security-policy={}", policy.display());
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            "-",
+            "--outdir=out",
+            "--untrusted",
+            &policy_arg,
+            "-Zsearch-path=subdirectory/content",
+        ],
+        "\\immediate\\openout1=../escape.txt \\immediate\\write1{hi}\\immediate\\closeout1 \
+         \\input 1.tex\n\\bye",
+    );
+    success_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("it would land outside of the output directory"));
+    assert!(!tempdir.path().join("escape.txt").exists());
+    check_file(&tempdir, "out/texput.pdf");
+}
+
+/// Test that the Makefile rules list the files that were read, with phony
+/// targets for them.
+#[test]
+fn makefile_rules_list_inputs() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            "-",
+            "--makefile-rules=deps.mk",
+            "--makefile-exclude-bundle",
+        ],
+        "\\input subdirectory/content/1.tex\n\\bye",
+    );
+    success_or_panic(&output);
+
+    let rules = std::fs::read_to_string(tempdir.path().join("deps.mk")).unwrap();
+    let lines: Vec<_> = rules.lines().collect();
+    assert!(lines[0].contains("texput.pdf"));
+    assert!(lines
+        .iter()
+        .any(|l| l.starts_with("  ") && l.contains("subdirectory/content/1.tex")));
+    assert!(lines
+        .iter()
+        .any(|l| l.ends_with("subdirectory/content/1.tex:")));
+}
+
+/// Test that a primary input given by a relative path is only listed once in
+/// the Makefile rules, even though the engine reports it by absolute path.
+#[test]
+fn makefile_rules_no_duplicates() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[
+        "subdirectory/relative_include.tex",
+        "subdirectory/content/1.tex",
+    ]);
+
+    let output = run_tectonic(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            "./subdirectory/relative_include.tex",
+            "--makefile-rules=deps.mk",
+            "--makefile-exclude-bundle",
+        ],
+    );
+    success_or_panic(&output);
+
+    let rules = std::fs::read_to_string(tempdir.path().join("deps.mk")).unwrap();
+    let n_listed = rules
+        .lines()
+        .filter(|l| l.starts_with("  ") && l.contains("relative_include.tex"))
+        .count();
+    assert_eq!(n_listed, 1, "unexpected rules: {}", rules);
+}
+
+/// Test that two reproducible builds of the same input are byte-identical.
+#[test]
+fn reproducible_builds_match() {
+    let fmt_arg = get_plain_format_arg();
+    let mut outputs = Vec::new();
+
+    // The second build happens in a time zone where the Unix epoch falls on
+    // the previous day, which must not change the date seen by TeX.
+    for tz in &["UTC0", "XXX12"] {
+        let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+        let mut command = prep_tectonic(tempdir.path(), &[&fmt_arg, "-", "--reproducible"]);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env("SOURCE_DATE_EPOCH", "0")
+            .env("TZ", tz);
+
+        println!("running {:?}", command);
+        let mut child = command.spawn().expect("tectonic failed to start");
+        write!(
+            child.stdin.as_mut().unwrap(),
+            "\\input subdirectory/content/1.tex\n\\number\\year/\\number\\day/\\number\\time\n\\bye"
+        )
+        .expect("failed to send data to tectonic subprocess");
+
+        let output = child
+            .wait_with_output()
+            .expect("failed to wait on tectonic subprocess");
+        success_or_panic(&output);
+        outputs.push(std::fs::read(tempdir.path().join("texput.pdf")).unwrap());
+    }
+
+    assert!(outputs[0] == outputs[1], "reproducible builds differ");
+}
+
+/// Test that a streamed output ends up in place, with no temporary files left
+/// behind.
+#[test]
+fn stream_outputs() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--stream-outputs"],
+        "\\input subdirectory/content/1.tex\n\\bye",
+    );
+    success_or_panic(&output);
+
+    let pdf = std::fs::read(tempdir.path().join("texput.pdf")).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+
+    for entry in std::fs::read_dir(tempdir.path()).unwrap() {
+        let name = entry.unwrap().file_name();
+        assert!(!name.to_string_lossy().ends_with(".tmp"));
+    }
+}
+
+/// Test that the main output can be written to standard output, with nothing
+/// written to disk.
+#[test]
+fn output_to_stdout() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(tempdir.path(), &[&fmt_arg, "-", "-o", "-"], "Hello\\bye");
+    success_or_panic(&output);
+
+    assert!(output.stdout.starts_with(b"%PDF"));
+    assert!(!tempdir.path().join("texput.pdf").exists());
+}
+
+/// Test that shell-escape commands can't corrupt a PDF written to standard
+/// output.
+#[cfg(unix)]
+#[test]
+fn output_to_stdout_with_shell_escape() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "-o", "-", "-Zshell-escape"],
+        "\\immediate\\write18{echo shell-escape-output}Hello\\bye",
+    );
+    success_or_panic(&output);
+
+    assert!(output.stdout.starts_with(b"%PDF"));
+    assert!(!output
+        .stdout
+        .windows(19)
+        .any(|w| w == b"shell-escape-output"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shell-escape-output"));
+}
+
+/// Test that a failed build leaves the previous outputs and logs untouched,
+/// saving its logs in the requested directory instead.
+#[test]
+fn failed_build_keeps_outputs() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--keep-logs"],
+        "Hello\\bye",
+    );
+    success_or_panic(&output);
+    let pdf = std::fs::read(tempdir.path().join("texput.pdf")).unwrap();
+    let log = std::fs::read(tempdir.path().join("texput.log")).unwrap();
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--keep-logs", "--failed-logs-dir=failed"],
+        "no end to this file",
+    );
+    error_or_panic(&output);
+
+    assert_eq!(
+        std::fs::read(tempdir.path().join("texput.pdf")).unwrap(),
+        pdf
+    );
+    assert_eq!(
+        std::fs::read(tempdir.path().join("texput.log")).unwrap(),
+        log
+    );
+
+    let failed_log = std::fs::read_to_string(tempdir.path().join("failed/texput.log")).unwrap();
+    assert!(failed_log.contains(r"job aborted, no legal \end found"));
+}
+
+/// Test that errors found in the TeX log are reported, even if the log isn't
+/// kept.
+#[test]
+fn log_errors_reported() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-"],
+        "Hello \\undefinedmacro\n\\bye",
+    );
+    error_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Undefined control sequence."));
+    assert!(!tempdir.path().join("texput.log").exists());
+}
+
+/// Test that undefined references are reported, and fail the build if asked.
+/// The warning is written to the log by hand, since we only have plain TeX.
+#[test]
+fn undefined_references_reported() {
+    let fmt_arg = get_plain_format_arg();
+    let input = "\\immediate\\write-1{LaTeX Warning: Reference `fig:one' on page 1 \
+                 undefined on input line 1.}\n\\immediate\\write-1{}\nHello\n\\bye";
+
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(tempdir.path(), &[&fmt_arg, "-"], input);
+    success_or_panic(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Reference `fig:one' on page 1 undefined"));
+
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--fail-on-undefined-refs"],
+        input,
+    );
+    error_or_panic(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("the document has 1 undefined reference or citation"));
+    assert!(!tempdir.path().join("texput.pdf").exists());
+}
+
+/// Test that the dependency manifest lists the inputs with their digests.
+#[cfg(feature = "serialization")]
+#[test]
+fn dependency_manifest() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--dependency-manifest=manifest.json"],
+        "\\input subdirectory/content/1.tex\n\\bye",
+    );
+    success_or_panic(&output);
+
+    let text = std::fs::read_to_string(tempdir.path().join("manifest.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(manifest["bundle_digest"].is_string());
+
+    let inputs = manifest["inputs"].as_array().unwrap();
+    assert_eq!(inputs[0]["source"], "primary");
+    assert!(inputs[0]["digest"].is_string());
+
+    let content = inputs
+        .iter()
+        .find(|i| i["name"] == "subdirectory/content/1.tex")
+        .unwrap();
+    assert_eq!(content["source"], "filesystem");
+    assert!(content["digest"].is_string());
+}
+
 /// -X in non-initial position fails
 #[test]
 fn bad_v2_position() {